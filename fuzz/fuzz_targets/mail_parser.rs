@@ -4,6 +4,7 @@ use libfuzzer_sys::fuzz_target;
 use mail_parser::{
     decoders::{
         base64::decode_base64,
+        binhex::decode_binhex_block,
         charsets::{
             map::get_charset_decoder,
             single_byte::decoder_iso_8859_1,
@@ -13,8 +14,11 @@ use mail_parser::{
         hex::decode_hex,
         html::{add_html_token, html_to_text, text_to_html},
         quoted_printable::decode_quoted_printable,
+        uuencode::{decode_uuencode, decode_uuencode_block},
+        yenc::decode_yenc_block,
     },
     parsers::{
+        embedded::decode_embedded_files,
         fields::{
             address::parse_address,
             content_type::parse_content_type,
@@ -26,15 +30,17 @@ use mail_parser::{
             unstructured::parse_unstructured,
         },
         header::parse_header_name,
+        icalendar::Component,
         message::MessageStream,
         mime::*,
+        vcard::VCard,
     },
     Message,
 };
 
 fuzz_target!(|data: &[u8]| {
     // Fuzz every parsing function
-    for n_fuzz in 1..=24 {
+    for n_fuzz in 1..=26 {
         let mut stream = MessageStream::new(&data);
 
         match n_fuzz {
@@ -110,7 +116,13 @@ fuzz_target!(|data: &[u8]| {
             24 => {
                 decode_quoted_printable(&mut stream, 0, &[], false);
             }
-            0 | 25..=u32::MAX => unreachable!(),
+            25 => {
+                decode_uuencode(&mut stream, 0, b"\n", false);
+            }
+            26 => {
+                decode_uuencode(&mut stream, 0, &[], false);
+            }
+            0 | 27..=u32::MAX => unreachable!(),
         }
     }
 
@@ -122,10 +134,18 @@ fuzz_target!(|data: &[u8]| {
     text_to_html(&str_data);
     thread_name(&str_data);
     trim_trailing_fwd(&str_data);
+    if let Some(calendar) = Component::parse(&str_data) {
+        calendar.get_invite();
+    }
+    VCard::parse(&str_data);
 
     // Fuzz decoding functions
     decode_hex(data);
     get_charset_decoder(data);
+    decode_uuencode_block(data);
+    decode_yenc_block(data);
+    decode_binhex_block(data);
+    decode_embedded_files(data);
 
     for decoder in &[
         decoder_utf7,
@@ -139,5 +159,11 @@ fuzz_target!(|data: &[u8]| {
     }
 
     // Fuzz the entire library
-    Message::parse(&data[..]);
+    if let Some(message) = Message::parse(&data[..]) {
+        for part in &message.parts {
+            part.get_external_body();
+        }
+        message.get_calendar_invite();
+        message.get_contacts();
+    }
 });
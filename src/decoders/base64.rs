@@ -102,6 +102,32 @@ pub fn decode_base64<'x>(
     )
 }
 
+// Encodes bytes as base64, breaking lines every 76 characters
+pub(crate) fn encode_base64(bytes: &[u8], line_ending: &[u8]) -> Vec<u8> {
+    const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut result = Vec::with_capacity(bytes.len() * 4 / 3 + bytes.len() / 57 * 2 + 4);
+
+    for (pos, chunk) in bytes.chunks(57).enumerate() {
+        if pos > 0 {
+            result.extend_from_slice(line_ending);
+        }
+        for chunk in chunk.chunks(3) {
+            let n = (u32::from(chunk[0]) << 16)
+                | (u32::from(chunk.get(1).copied().unwrap_or(0)) << 8)
+                | u32::from(chunk.get(2).copied().unwrap_or(0));
+            for i in 0..4 {
+                result.push(if i <= chunk.len() {
+                    BASE64_CHARS[((n >> (18 - i * 6)) & 0x3f) as usize]
+                } else {
+                    b'='
+                });
+            }
+        }
+    }
+
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
@@ -0,0 +1,210 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use super::{charsets::single_byte::decoder_macintosh, next_line, DecodedFile};
+
+const RLE_MARKER: u8 = 0x90;
+
+/// Returns `true` if the data starts with the BinHex 4.0 banner line.
+pub fn is_binhex_start(data: &[u8]) -> bool {
+    data.starts_with(b"(This file must be converted with BinHex")
+}
+
+/// Decodes a BinHex 4.0 block starting at its banner line, returning the number
+/// of bytes read and the data fork of the encoded file.
+pub fn decode_binhex_block(data: &[u8]) -> (usize, Option<DecodedFile>) {
+    if !is_binhex_start(data) {
+        return (0, None);
+    }
+
+    // Skip banner and look for the opening colon
+    let mut pos = next_line(data, 0).1;
+    match data[pos..].iter().position(|&ch| !ch.is_ascii_whitespace()) {
+        Some(start) if data[pos + start] == b':' => {
+            pos += start + 1;
+        }
+        _ => return (0, None),
+    }
+
+    // Convert 6-bit characters to bytes and expand RLE sequences
+    let mut contents = Vec::with_capacity(data.len() * 3 / 4);
+    let mut chunk: u32 = 0;
+    let mut byte_count = 0;
+    let mut is_rle = false;
+    let mut is_done = false;
+
+    for ch in data[pos..].iter() {
+        pos += 1;
+
+        let val = match BINHEX_MAP[*ch as usize] {
+            -1 => {
+                if *ch == b':' {
+                    is_done = true;
+                    break;
+                } else if ch.is_ascii_whitespace() {
+                    continue;
+                } else {
+                    return (0, None);
+                }
+            }
+            val => val as u32,
+        };
+
+        chunk = (chunk << 6) | val;
+        byte_count += 1;
+
+        if byte_count == 4 {
+            for &byte in &chunk.to_be_bytes()[1..] {
+                add_rle_byte(&mut contents, &mut is_rle, byte);
+            }
+            chunk = 0;
+            byte_count = 0;
+        }
+    }
+
+    if !is_done {
+        return (0, None);
+    }
+
+    // Flush any remaining bits
+    match byte_count {
+        2 => {
+            add_rle_byte(&mut contents, &mut is_rle, (chunk >> 4) as u8);
+        }
+        3 => {
+            for &byte in &(chunk >> 2).to_be_bytes()[2..] {
+                add_rle_byte(&mut contents, &mut is_rle, byte);
+            }
+        }
+        _ => (),
+    }
+
+    // Skip the remaining of the line
+    pos = next_line(data, pos).1;
+
+    // Parse header: name length, name, version, type, creator, flags,
+    // data fork length, resource fork length and CRC
+    let name_len = match contents.first() {
+        Some(name_len) => *name_len as usize,
+        None => return (0, None),
+    };
+    let data_start = 1 + name_len + 1 + 4 + 4 + 2 + 4 + 4 + 2;
+    let data_len = match contents.get(data_start - 10..data_start - 6) {
+        Some(len) => u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize,
+        None => return (0, None),
+    };
+
+    match contents.get(data_start..data_start + data_len) {
+        Some(data_fork) => (
+            pos,
+            Some(DecodedFile {
+                name: if name_len > 0 {
+                    decoder_macintosh(&contents[1..1 + name_len]).into()
+                } else {
+                    None
+                },
+                contents: data_fork.to_vec(),
+            }),
+        ),
+        None => (0, None),
+    }
+}
+
+fn add_rle_byte(buf: &mut Vec<u8>, is_rle: &mut bool, byte: u8) {
+    if *is_rle {
+        *is_rle = false;
+        if byte == 0 {
+            buf.push(RLE_MARKER);
+        } else if let Some(&last_byte) = buf.last() {
+            for _ in 1..byte {
+                buf.push(last_byte);
+            }
+        }
+    } else if byte == RLE_MARKER {
+        *is_rle = true;
+    } else {
+        buf.push(byte);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::decoders::{
+        binhex::{decode_binhex_block, is_binhex_start},
+        uuencode::{decode_uuencode_block, is_uuencode_start},
+    };
+
+    #[test]
+    fn decode_binhex_blocks() {
+        let input = concat!(
+            "(This file must be converted with BinHex 4.0)\n",
+            ":\"f0KG#jdH(3!9%9B9(4dH(3!N!8-!*!%CXa$BC!)G*!!)4fK!!!:\n",
+            "more text\n"
+        );
+
+        let (bytes_read, result) = decode_binhex_block(input.as_bytes());
+        let result = result.unwrap();
+        assert_eq!(bytes_read, input.len() - "more text\n".len());
+        assert_eq!(result.name.as_deref(), Some("cat.txt"));
+        assert_eq!(result.contents, b"Caaaaaaaat\x90!");
+
+        for input in [
+            "(This file must be converted with BinHex 4.0)\nno colon:\n",
+            "(This file must be converted with BinHex 4.0)\n:\"f0KG#j~H(3!9%:\n",
+            "(This file must be converted with BinHex 4.0)\n:\"f0KG#jdH(3!9%9B9(4dH(3!N!8-!*!%CXa$BC!\n",
+            "(This file must be converted with BinHex 4.0)\n:\"f0KG#jdH(3!9%9B9(4dH(3!N!8-!*!%:\n",
+        ] {
+            assert_eq!(
+                decode_binhex_block(input.as_bytes()),
+                (0, None),
+                "Failed for '{:?}'",
+                input
+            );
+        }
+
+        // Compare the BinHex and uuencoded versions of the same file
+        let mut test_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_dir.push("tests");
+        test_dir.push("legacy");
+
+        let binhex_message = fs::read(test_dir.join("016.eml")).unwrap();
+        let uu_message = fs::read(test_dir.join("017.eml")).unwrap();
+
+        let binhex_file = (0..binhex_message.len())
+            .find(|&pos| is_binhex_start(&binhex_message[pos..]))
+            .and_then(|pos| decode_binhex_block(&binhex_message[pos..]).1)
+            .unwrap();
+        let uu_file = (0..uu_message.len())
+            .filter(|&pos| is_uuencode_start(&uu_message[pos..]))
+            .filter_map(|pos| decode_uuencode_block(&uu_message[pos..]).1)
+            .find(|file| file.name.as_deref() == Some("blueball.png"))
+            .unwrap();
+
+        assert_eq!(binhex_file, uu_file);
+        assert!(binhex_file.contents.starts_with(b"\x89PNG"));
+    }
+}
+
+static BINHEX_MAP: &[i8] = &[
+    -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+    -1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, -1, -1, 13, 14,
+    15, 16, 17, 18, 19, -1, 20, 21, -1, -1, -1, -1, -1, -1, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31,
+    32, 33, 34, 35, 36, -1, 37, 38, 39, 40, 41, 42, 43, -1, 44, 45, 46, 47, -1, -1, -1, -1, 48, 49,
+    50, 51, 52, 53, 54, -1, 55, 56, 57, 58, 59, 60, -1, -1, 61, 62, 63, -1, -1, -1, -1, -1, -1, -1,
+    -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+    -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+    -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+    -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+    -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+    -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+];
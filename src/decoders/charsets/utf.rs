@@ -35,6 +35,17 @@ fn add_utf16_bytes(state: &mut Utf7DecoderState, n_bytes: usize) {
 }
 
 pub fn decoder_utf7(bytes: &[u8]) -> String {
+    decoder_utf7_(bytes, false)
+}
+
+/// Decodes IMAP modified UTF-7, as used in mailbox names
+/// ([RFC 3501 - Section 5.1.3](https://datatracker.ietf.org/doc/html/rfc3501#section-5.1.3)).
+pub fn decoder_utf7_imap(bytes: &[u8]) -> String {
+    decoder_utf7_(bytes, true)
+}
+
+fn decoder_utf7_(bytes: &[u8], is_imap: bool) -> String {
+    let shift_char = if is_imap { b'&' } else { b'+' };
     let mut result = String::with_capacity(bytes.len());
     let mut byte_count: u8 = 0;
     let mut in_b64 = false;
@@ -47,7 +58,11 @@ pub fn decoder_utf7(bytes: &[u8]) -> String {
 
     for byte in bytes {
         if in_b64 {
-            let val = BASE64_MAP[byte_count as usize][*byte as usize];
+            let val = BASE64_MAP[byte_count as usize][if is_imap && *byte == b',' {
+                b'/'
+            } else {
+                *byte
+            } as usize];
 
             if val < 0x01ffffff {
                 byte_count = (byte_count + 1) & 3;
@@ -81,16 +96,23 @@ pub fn decoder_utf7(bytes: &[u8]) -> String {
                     );
                 } else if byte_count > 0 || state.pending_byte.is_some() {
                     result.push(REPLACEMENT_CHARACTER);
+                } else if is_imap {
+                    // "&-" represents "&"
+                    result.push('&');
                 } else {
                     result.push('+');
                     result.push(char::from(*byte));
                 }
 
+                if is_imap && *byte != b'-' {
+                    result.push(char::from(*byte));
+                }
+
                 state.pending_byte = None;
                 byte_count = 0;
                 in_b64 = false;
             }
-        } else if byte == &b'+' {
+        } else if byte == &shift_char {
             in_b64 = true;
         } else {
             result.push(char::from(*byte));
@@ -101,6 +123,53 @@ pub fn decoder_utf7(bytes: &[u8]) -> String {
     result
 }
 
+/// Encodes text as IMAP modified UTF-7, as used in mailbox names
+/// ([RFC 3501 - Section 5.1.3](https://datatracker.ietf.org/doc/html/rfc3501#section-5.1.3)).
+pub fn encoder_utf7_imap(text: &str) -> String {
+    const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+,";
+
+    let mut result = String::with_capacity(text.len());
+    let mut utf16_bytes = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if (' '..='~').contains(&ch) {
+            if ch == '&' {
+                result.push_str("&-");
+            } else {
+                result.push(ch);
+            }
+            continue;
+        }
+
+        let mut buf = [0u16; 2];
+        for unit in ch.encode_utf16(&mut buf) {
+            utf16_bytes.extend_from_slice(&unit.to_be_bytes());
+        }
+
+        if chars.peek().is_none_or(|ch| (' '..='~').contains(ch)) {
+            result.push('&');
+            for chunk in utf16_bytes.chunks(3) {
+                let b = [
+                    chunk[0],
+                    chunk.get(1).copied().unwrap_or(0),
+                    chunk.get(2).copied().unwrap_or(0),
+                ];
+                let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+                for i in 0..=chunk.len() {
+                    result.push(char::from(
+                        BASE64_CHARS[((n >> (18 - i * 6)) & 0x3f) as usize],
+                    ));
+                }
+            }
+            result.push('-');
+            utf16_bytes.clear();
+        }
+    }
+
+    result
+}
+
 fn decoder_utf16_(bytes: &[u8], fnc: fn([u8; 2]) -> u16) -> String {
     if bytes.len() >= 2 {
         decode_utf16(bytes.chunks_exact(2).map(|c| fnc([c[0], c[1]])))
@@ -138,7 +207,7 @@ pub fn decoder_utf8(bytes: &[u8]) -> String {
 
 #[cfg(test)]
 mod tests {
-    use crate::decoders::charsets::utf::decoder_utf7;
+    use crate::decoders::charsets::utf::{decoder_utf7, decoder_utf7_imap, encoder_utf7_imap};
 
     #[test]
     fn decode_utf7() {
@@ -169,5 +238,16 @@ mod tests {
         for input in inputs {
             assert_eq!(decoder_utf7(input.0.as_bytes()), input.1);
         }
+
+        for (encoded, decoded) in [
+            ("Sent.Entw&APw-rfe", "Sent.Entwürfe"),
+            ("~peter/mail/&U,BTFw-/&ZeVnLIqe-", "~peter/mail/台北/日本語"),
+            ("Tom &- Jerry", "Tom & Jerry"),
+            ("&2D3eAA-", "😀"),
+            ("INBOX", "INBOX"),
+        ] {
+            assert_eq!(decoder_utf7_imap(encoded.as_bytes()), decoded);
+            assert_eq!(encoder_utf7_imap(decoded), encoded);
+        }
     }
 }
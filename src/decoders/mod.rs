@@ -12,11 +12,14 @@
 use crate::parsers::message::MessageStream;
 
 pub mod base64;
+pub mod binhex;
 pub mod charsets;
 pub mod encoded_word;
 pub mod hex;
 pub mod html;
 pub mod quoted_printable;
+pub mod uuencode;
+pub mod yenc;
 
 pub type DecodeFnc<'x> = fn(&MessageStream<'x>, usize, &[u8], bool) -> (usize, DecodeResult);
 
@@ -26,3 +29,25 @@ pub enum DecodeResult {
     Borrowed((usize, usize)),
     Empty,
 }
+
+/// A file decoded from an uuencode, yEnc or BinHex block.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DecodedFile {
+    pub name: Option<String>,
+    pub contents: Vec<u8>,
+}
+
+pub(crate) fn next_line(data: &[u8], pos: usize) -> (&[u8], usize) {
+    let line = &data[pos..];
+    match line.iter().position(|&ch| ch == b'\n') {
+        Some(end) => (trim_cr(&line[..end]), pos + end + 1),
+        None => (trim_cr(line), data.len()),
+    }
+}
+
+fn trim_cr(line: &[u8]) -> &[u8] {
+    match line.split_last() {
+        Some((b'\r', line)) => line,
+        _ => line,
+    }
+}
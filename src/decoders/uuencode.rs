@@ -0,0 +1,188 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use crate::parsers::{message::MessageStream, mime::get_bytes_to_boundary};
+
+use super::{next_line, DecodeResult, DecodedFile};
+
+/// Decodes a part encoded with the `x-uuencode` Content-Transfer-Encoding.
+pub fn decode_uuencode<'x>(
+    stream: &MessageStream<'x>,
+    start_pos: usize,
+    boundary: &[u8],
+    is_word: bool,
+) -> (usize, DecodeResult) {
+    let (bytes_read, result) = get_bytes_to_boundary(stream, start_pos, boundary, is_word);
+
+    let data = match result {
+        DecodeResult::Borrowed((from, to)) => &stream.data[from..to],
+        _ => return (bytes_read, DecodeResult::Empty),
+    };
+
+    // Skip any text preceding the 'begin' line
+    let mut pos = 0;
+    while pos < data.len() {
+        if is_uuencode_start(&data[pos..]) {
+            if let (_, Some(file)) = decode_uuencode_block(&data[pos..]) {
+                return (
+                    bytes_read,
+                    if !file.contents.is_empty() {
+                        DecodeResult::Owned(file.contents)
+                    } else {
+                        DecodeResult::Empty
+                    },
+                );
+            }
+            break;
+        }
+        pos = next_line(data, pos).1;
+    }
+
+    (0, DecodeResult::Empty)
+}
+
+/// Returns `true` if the data starts with an uuencode `begin <mode> <name>` line.
+pub fn is_uuencode_start(data: &[u8]) -> bool {
+    data.starts_with(b"begin ") && data.get(6).is_some_and(|ch| ch.is_ascii_digit())
+}
+
+/// Decodes an uuencoded block starting at a `begin` line and ending at an `end` line,
+/// returning the number of bytes read.
+pub fn decode_uuencode_block(data: &[u8]) -> (usize, Option<DecodedFile>) {
+    if !is_uuencode_start(data) {
+        return (0, None);
+    }
+
+    // Parse 'begin <mode> <name>'
+    let (line, mut pos) = next_line(data, 0);
+    let name = line[6..]
+        .iter()
+        .position(|ch| !ch.is_ascii_digit())
+        .map(|mode_end| {
+            String::from_utf8_lossy(&line[6 + mode_end..])
+                .trim()
+                .to_string()
+        })
+        .filter(|name| !name.is_empty());
+
+    let mut contents = Vec::with_capacity(data.len() * 3 / 4);
+
+    while pos < data.len() {
+        let (line, next_pos) = next_line(data, pos);
+        pos = next_pos;
+
+        if line == b"end" || line.starts_with(b"end ") {
+            return (pos, Some(DecodedFile { name, contents }));
+        } else if !decode_uuencode_line(line, &mut contents) {
+            return (0, None);
+        }
+    }
+
+    // Missing 'end' line, return whatever was decoded
+    if !contents.is_empty() {
+        (pos, Some(DecodedFile { name, contents }))
+    } else {
+        (0, None)
+    }
+}
+
+fn decode_uuencode_line(line: &[u8], buf: &mut Vec<u8>) -> bool {
+    let (len, chars) = match line.split_first() {
+        Some((&len_ch, chars)) if (b' '..=b'`').contains(&len_ch) => {
+            (((len_ch - b' ') & 0x3f) as usize, chars)
+        }
+        Some(_) => return false,
+        None => return true,
+    };
+
+    let mut remaining = len;
+    for group in 0..len.div_ceil(3) {
+        let mut chunk: u32 = 0;
+
+        for pos in 0..4 {
+            // Trailing spaces might have been removed in transit
+            let ch = chars.get(group * 4 + pos).copied().unwrap_or(b' ');
+            if !(b' '..=b'`').contains(&ch) {
+                return false;
+            }
+            chunk = (chunk << 6) | ((ch - b' ') & 0x3f) as u32;
+        }
+
+        for &byte in chunk.to_be_bytes()[1..].iter().take(remaining) {
+            buf.push(byte);
+        }
+        remaining = remaining.saturating_sub(3);
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        decoders::{uuencode::decode_uuencode, uuencode::decode_uuencode_block, DecodeResult},
+        parsers::message::MessageStream,
+    };
+
+    #[test]
+    fn decode_uuencode_blocks() {
+        let inputs = [
+            ("begin 644 cat.txt\n#0V%T\n`\nend\n", Some("cat.txt"), "Cat"),
+            (
+                concat!(
+                    "begin 600 hello world.txt\r\n",
+                    "M5&AI<R!I<R!A(&QO;F=E<B!T97AT('1H870@<W!A;G,@;6]R92!T:&%N(&]N\r\n",
+                    "/92!L:6YE+B!(96QL;R$`\r\n",
+                    "`\r\n",
+                    "end\r\n"
+                ),
+                Some("hello world.txt"),
+                "This is a longer text that spans more than one line. Hello!\0",
+            ),
+            ("begin 644 short.txt\n#0V%\nend\n", Some("short.txt"), "Ca@"),
+            ("begin 644\n#0V%T\nend\n", None, "Cat"),
+            (
+                "begin 644 invalid.txt\nthis is not uuencoded\nend\n",
+                None,
+                "",
+            ),
+            ("begin with some text\n#0V%T\nend\n", None, ""),
+        ];
+
+        for (input, expected_name, expected_contents) in inputs {
+            let (bytes_read, result) = decode_uuencode_block(input.as_bytes());
+
+            if !expected_contents.is_empty() {
+                let result = result.unwrap_or_else(|| panic!("Failed for '{:?}'", input));
+                assert_eq!(bytes_read, input.len(), "Failed for '{:?}'", input);
+                assert_eq!(result.name.as_deref(), expected_name);
+                assert_eq!(
+                    std::str::from_utf8(&result.contents).unwrap(),
+                    expected_contents,
+                    "Failed for '{:?}'",
+                    input
+                );
+            } else {
+                assert!(result.is_none(), "Failed for '{:?}'", input);
+            }
+        }
+
+        let input = b"\n\nbegin 644 cat.txt\n#0V%T\n`\nend\n\n--boundary--\n";
+        let stream = MessageStream::new(&input[..]);
+        match decode_uuencode(&stream, 0, b"--boundary", false) {
+            (bytes_read, DecodeResult::Owned(contents)) => {
+                assert_eq!(bytes_read, input.len() - 3);
+                assert_eq!(contents, b"Cat");
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use super::{next_line, DecodedFile};
+
+/// Returns `true` if the data starts with a yEnc `=ybegin` line.
+pub fn is_yenc_start(data: &[u8]) -> bool {
+    data.starts_with(b"=ybegin ")
+}
+
+/// Decodes a yEnc block starting at a `=ybegin` line and ending at a `=yend` line,
+/// returning the number of bytes read.
+pub fn decode_yenc_block(data: &[u8]) -> (usize, Option<DecodedFile>) {
+    if !is_yenc_start(data) {
+        return (0, None);
+    }
+
+    // Parse '=ybegin line=128 size=1234 name=file name.bin'
+    let (line, mut pos) = next_line(data, 0);
+    let line = String::from_utf8_lossy(&line[8..]);
+    let mut name = None;
+    let mut size = 0;
+
+    for (key, value) in YencKeywords::new(&line) {
+        match key {
+            "name" => {
+                let value = value.trim();
+                if !value.is_empty() {
+                    name = Some(value.to_string());
+                }
+            }
+            "size" => {
+                size = value.parse().unwrap_or(0);
+            }
+            _ => (),
+        }
+    }
+
+    let mut contents = Vec::with_capacity(std::cmp::min(size, data.len()));
+
+    while pos < data.len() {
+        let (line, next_pos) = next_line(data, pos);
+        pos = next_pos;
+
+        if line.starts_with(b"=yend") {
+            return (pos, Some(DecodedFile { name, contents }));
+        } else if line.starts_with(b"=ypart ") {
+            continue;
+        }
+
+        let mut is_escaped = false;
+        for &ch in line {
+            if is_escaped {
+                contents.push(ch.wrapping_sub(64).wrapping_sub(42));
+                is_escaped = false;
+            } else if ch == b'=' {
+                is_escaped = true;
+            } else {
+                contents.push(ch.wrapping_sub(42));
+            }
+        }
+    }
+
+    // Missing '=yend' line, return whatever was decoded
+    if !contents.is_empty() {
+        (pos, Some(DecodedFile { name, contents }))
+    } else {
+        (0, None)
+    }
+}
+
+/// Iterates over the `key=value` pairs of a yEnc header line. The `name`
+/// keyword always comes last and its value extends to the end of the line.
+struct YencKeywords<'x> {
+    line: &'x str,
+}
+
+impl<'x> YencKeywords<'x> {
+    fn new(line: &'x str) -> Self {
+        YencKeywords { line }
+    }
+}
+
+impl<'x> Iterator for YencKeywords<'x> {
+    type Item = (&'x str, &'x str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, rest) = self.line.trim_start().split_once('=')?;
+
+        if key == "name" {
+            self.line = "";
+            Some((key, rest))
+        } else if let Some((value, rest)) = rest.split_once(' ') {
+            self.line = rest;
+            Some((key, value))
+        } else {
+            self.line = "";
+            Some((key, rest))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoders::yenc::decode_yenc_block;
+
+    #[test]
+    fn decode_yenc_blocks() {
+        let inputs = [
+            (
+                &b"=ybegin line=128 size=3 name=cat.txt\r\nm\x8b\x9e\r\n=yend size=3\r\n"[..],
+                Some("cat.txt"),
+                &b"Cat"[..],
+            ),
+            (
+                &b"=ybegin part=1 line=128 size=4 name=my file.bin\n=ypart begin=1 end=4\n=}*=J\xd6\n=yend size=4 part=1\n"[..],
+                Some("my file.bin"),
+                &b"\x13\x00\xe0\xac"[..],
+            ),
+            (
+                &b"=ybegin line=128 size=3\n\x8b\x9e\n"[..],
+                None,
+                &b"at"[..],
+            ),
+            (&b"=ybegin line=128 size=0 name=empty\n"[..], None, &b""[..]),
+        ];
+
+        for (input, expected_name, expected_contents) in inputs {
+            let (bytes_read, result) = decode_yenc_block(input);
+
+            if !expected_contents.is_empty() {
+                let result = result.unwrap_or_else(|| panic!("Failed for '{:?}'", input));
+                assert_eq!(bytes_read, input.len(), "Failed for '{:?}'", input);
+                assert_eq!(result.name.as_deref(), expected_name);
+                assert_eq!(
+                    result.contents, expected_contents,
+                    "Failed for '{:?}'",
+                    input
+                );
+            } else {
+                assert!(result.is_none(), "Failed for '{:?}'", input);
+            }
+        }
+    }
+}
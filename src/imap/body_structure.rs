@@ -0,0 +1,314 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::{borrow::Cow, fmt::Write};
+
+use crate::{
+    ContentType, GetHeader, HeaderValue, Message, MessageAttachment, MessagePart, MimeHeaders,
+    PartType,
+};
+
+use super::{envelope::push_envelope, push_nstring, push_string, raw_bytes};
+
+/// Returns the BODY (when `is_extended` is `false`) or BODYSTRUCTURE of
+/// a message, as defined in
+/// [RFC 9051 - Internet Message Access Protocol (IMAP) - Version 4rev2 (Section 7.5.2)](https://datatracker.ietf.org/doc/html/rfc9051#section-7.5.2).
+///
+/// Sizes and line counts refer to the encoded body of each part, encoded
+/// `message/rfc822` parts are decoded to describe the attached message.
+pub fn body_structure(message: &Message, is_extended: bool) -> String {
+    let mut buf = String::with_capacity(256);
+    push_body(&mut buf, message, 0, is_extended);
+    buf
+}
+
+fn push_body(buf: &mut String, message: &Message, part_id: usize, is_extended: bool) {
+    let part = if let Some(part) = message.parts.get(part_id) {
+        part
+    } else {
+        return;
+    };
+    let content_type = part.get_content_type();
+
+    buf.push('(');
+    if let PartType::Multipart(sub_part_ids) = &part.body {
+        for &sub_part_id in sub_part_ids {
+            push_body(buf, message, sub_part_id, is_extended);
+        }
+        buf.push(' ');
+        push_string(
+            buf,
+            content_type
+                .and_then(|content_type| content_type.get_subtype())
+                .unwrap_or("mixed"),
+        );
+        if is_extended {
+            buf.push(' ');
+            push_parameters(buf, content_type, false);
+            push_extension_fields(buf, part);
+        }
+        buf.push(')');
+        return;
+    }
+
+    // Encoded message parts are parsed to obtain their envelope and body,
+    // the few that cannot be parsed are reported as basic parts.
+    let nested_message = match &part.body {
+        PartType::Message(MessageAttachment::Parsed(message)) => {
+            Some(Cow::Borrowed(message.as_ref()))
+        }
+        PartType::Message(MessageAttachment::Raw(raw_message)) => {
+            Message::parse(raw_message).map(Cow::Owned)
+        }
+        _ => None,
+    };
+    let (c_type, c_subtype) = match content_type {
+        _ if part.is_message() && nested_message.is_none() => ("application", "octet-stream"),
+        Some(content_type) => (
+            content_type.get_type(),
+            content_type
+                .get_subtype()
+                .unwrap_or_else(|| match content_type.get_type() {
+                    "text" => "plain",
+                    "message" => "rfc822",
+                    _ => "octet-stream",
+                }),
+        ),
+        // Parts of a multipart/digest default to message/rfc822
+        None if part.is_message() => ("message", "rfc822"),
+        None => ("text", "plain"),
+    };
+    let is_text = c_type.eq_ignore_ascii_case("text");
+    let contents = raw_bytes(message, part.offset_body, part.offset_end);
+
+    push_string(buf, c_type);
+    buf.push(' ');
+    push_string(buf, c_subtype);
+    buf.push(' ');
+    push_parameters(buf, content_type, is_text);
+    buf.push(' ');
+    push_nstring(
+        buf,
+        part.get_content_id()
+            .map(|id| format!("<{}>", id))
+            .as_deref(),
+    );
+    buf.push(' ');
+    push_nstring(buf, part.get_content_description());
+    buf.push(' ');
+    push_string(
+        buf,
+        part.get_content_transfer_encoding()
+            .map(|encoding| encoding.trim())
+            .unwrap_or("7bit"),
+    );
+    let _ = write!(buf, " {}", contents.len());
+
+    if let Some(nested_message) = &nested_message {
+        buf.push(' ');
+        push_envelope(buf, nested_message);
+        buf.push(' ');
+        push_body(buf, nested_message, 0, is_extended);
+        let _ = write!(buf, " {}", count_lines(contents));
+    } else if is_text {
+        let _ = write!(buf, " {}", count_lines(contents));
+    }
+
+    if is_extended {
+        buf.push(' ');
+        push_nstring(
+            buf,
+            part.headers
+                .get_header("Content-MD5")
+                .and_then(|header| header.value.as_text_ref())
+                .map(|md5| md5.trim())
+                .filter(|md5| !md5.is_empty()),
+        );
+        push_extension_fields(buf, part);
+    }
+    buf.push(')');
+}
+
+// Writes the disposition, language and location fields
+fn push_extension_fields(buf: &mut String, part: &MessagePart) {
+    buf.push(' ');
+    if let Some(disposition) = part.get_content_disposition() {
+        buf.push('(');
+        push_string(buf, disposition.get_type());
+        buf.push(' ');
+        push_parameters(buf, Some(disposition), false);
+        buf.push(')');
+    } else {
+        buf.push_str("NIL");
+    }
+
+    buf.push(' ');
+    match part.get_content_language() {
+        HeaderValue::Text(language) => push_string(buf, language),
+        HeaderValue::TextList(languages) => {
+            buf.push('(');
+            for (pos, language) in languages.iter().enumerate() {
+                if pos > 0 {
+                    buf.push(' ');
+                }
+                push_string(buf, language);
+            }
+            buf.push(')');
+        }
+        _ => buf.push_str("NIL"),
+    }
+
+    buf.push(' ');
+    push_nstring(buf, part.get_content_location());
+}
+
+// Text parts without a charset default to US-ASCII (RFC 2045)
+fn push_parameters(buf: &mut String, content_type: Option<&ContentType>, is_text: bool) {
+    let attributes = content_type
+        .and_then(|content_type| content_type.get_attributes())
+        .unwrap_or_default();
+    let add_charset = is_text
+        && !attributes
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("charset"));
+
+    if attributes.is_empty() && !add_charset {
+        buf.push_str("NIL");
+        return;
+    }
+
+    buf.push('(');
+    if add_charset {
+        buf.push_str("\"charset\" \"us-ascii\"");
+    }
+    for (pos, (name, value)) in attributes.iter().enumerate() {
+        if pos > 0 || add_charset {
+            buf.push(' ');
+        }
+        push_string(buf, name);
+        buf.push(' ');
+        push_string(buf, value);
+    }
+    buf.push(')');
+}
+
+fn count_lines(contents: &[u8]) -> usize {
+    contents.iter().filter(|&&ch| ch == b'\n').count()
+        + usize::from(contents.last().is_some_and(|&ch| ch != b'\n'))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Message;
+
+    use super::body_structure;
+
+    #[test]
+    fn imap_body_structure() {
+        let message = Message::parse(
+            concat!(
+                "From: john@example.com\r\n",
+                "Subject: Test\r\n",
+                "Content-Type: multipart/mixed; boundary=\"outer\"\r\n\r\n",
+                "--outer\r\n",
+                "Content-Type: multipart/alternative; boundary=\"inner\"\r\n\r\n",
+                "--inner\r\n",
+                "Content-Type: text/plain; charset=utf-8\r\n",
+                "Content-Language: en, de\r\n\r\n",
+                "Hello,\r\nworld!\r\n",
+                "--inner\r\n",
+                "Content-Type: text/html\r\n",
+                "Content-Transfer-Encoding: quoted-printable\r\n\r\n",
+                "<p>Hello, world!</p>\r\n",
+                "--inner--\r\n",
+                "--outer\r\n",
+                "Content-Type: application/pdf; name=\"doc.pdf\"\r\n",
+                "Content-Transfer-Encoding: base64\r\n",
+                "Content-Disposition: attachment; filename=\"doc.pdf\"\r\n",
+                "Content-ID: <doc@example.com>\r\n",
+                "Content-Description: A document\r\n",
+                "Content-MD5: Q2hlY2sgSW50ZWdyaXR5IQ==\r\n",
+                "Content-Location: http://example.com/doc.pdf\r\n\r\n",
+                "SGVsbG8=\r\n",
+                "--outer\r\n",
+                "Content-Type: message/rfc822\r\n\r\n",
+                "From: jane@example.com\r\n",
+                "Subject: Nested\r\n\r\n",
+                "Nested\r\nbody\r\n",
+                "--outer--\r\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            body_structure(&message, false),
+            concat!(
+                "(((\"text\" \"plain\" (\"charset\" \"utf-8\") NIL NIL \"7bit\" 14 2)",
+                "(\"text\" \"html\" (\"charset\" \"us-ascii\") NIL NIL \"quoted-printable\" 20 1)",
+                " \"alternative\")",
+                "(\"application\" \"pdf\" (\"name\" \"doc.pdf\") \"<doc@example.com>\" ",
+                "\"A document\" \"base64\" 8)",
+                "(\"message\" \"rfc822\" NIL NIL NIL \"7bit\" 55 ",
+                "(NIL \"Nested\" ((NIL NIL \"jane\" \"example.com\")) ",
+                "((NIL NIL \"jane\" \"example.com\")) ((NIL NIL \"jane\" \"example.com\")) ",
+                "NIL NIL NIL NIL NIL) ",
+                "(\"text\" \"plain\" (\"charset\" \"us-ascii\") NIL NIL \"7bit\" 12 2) 5)",
+                " \"mixed\")"
+            )
+        );
+        assert_eq!(
+            body_structure(&message, true),
+            concat!(
+                "(((\"text\" \"plain\" (\"charset\" \"utf-8\") NIL NIL \"7bit\" 14 2 ",
+                "NIL NIL (\"en\" \"de\") NIL)",
+                "(\"text\" \"html\" (\"charset\" \"us-ascii\") NIL NIL \"quoted-printable\" 20 1 ",
+                "NIL NIL NIL NIL)",
+                " \"alternative\" (\"boundary\" \"inner\") NIL NIL NIL)",
+                "(\"application\" \"pdf\" (\"name\" \"doc.pdf\") \"<doc@example.com>\" ",
+                "\"A document\" \"base64\" 8 \"Q2hlY2sgSW50ZWdyaXR5IQ==\" ",
+                "(\"attachment\" (\"filename\" \"doc.pdf\")) NIL \"http://example.com/doc.pdf\")",
+                "(\"message\" \"rfc822\" NIL NIL NIL \"7bit\" 55 ",
+                "(NIL \"Nested\" ((NIL NIL \"jane\" \"example.com\")) ",
+                "((NIL NIL \"jane\" \"example.com\")) ((NIL NIL \"jane\" \"example.com\")) ",
+                "NIL NIL NIL NIL NIL) ",
+                "(\"text\" \"plain\" (\"charset\" \"us-ascii\") NIL NIL \"7bit\" 12 2 ",
+                "NIL NIL NIL NIL) 5 NIL NIL NIL NIL)",
+                " \"mixed\" (\"boundary\" \"outer\") NIL NIL NIL)"
+            )
+        );
+
+        // Encoded messages are parsed to report their envelope and body
+        let message = Message::parse(
+            concat!(
+                "Content-Type: multipart/mixed; boundary=\"m\"\r\n\r\n",
+                "--m\r\n",
+                "Content-Type: message/rfc822\r\n",
+                "Content-Transfer-Encoding: base64\r\n\r\n",
+                "RnJvbTogamFuZUBleGFtcGxlLmNvbQ0KU3ViamVjdDogTmVzdGVkDQoNCkhpDQo=\r\n",
+                "--m--\r\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            body_structure(&message, false),
+            concat!(
+                "((\"message\" \"rfc822\" NIL NIL NIL \"base64\" 64 ",
+                "(NIL \"Nested\" ((NIL NIL \"jane\" \"example.com\")) ",
+                "((NIL NIL \"jane\" \"example.com\")) ((NIL NIL \"jane\" \"example.com\")) ",
+                "NIL NIL NIL NIL NIL) ",
+                "(\"text\" \"plain\" (\"charset\" \"us-ascii\") NIL NIL \"7bit\" 4 1) 1)",
+                " \"mixed\")"
+            )
+        );
+    }
+}
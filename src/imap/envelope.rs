@@ -0,0 +1,234 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::borrow::Cow;
+
+use crate::{decoders::base64::encode_base64, Addr, GetHeader, HeaderValue, Message, RfcHeader};
+
+use super::{push_nstring, push_string, raw_bytes};
+
+/// Returns the ENVELOPE of a message, as defined in
+/// [RFC 9051 - Internet Message Access Protocol (IMAP) - Version 4rev2 (Section 7.5.2)](https://datatracker.ietf.org/doc/html/rfc9051#section-7.5.2).
+///
+/// The date and subject are returned as they appear in the message,
+/// unfolded. Non-ASCII display names are sent as RFC 2047 encoded-words
+/// and raw 8-bit header values as literals.
+pub fn envelope(message: &Message) -> String {
+    let mut buf = String::with_capacity(128);
+    push_envelope(&mut buf, message);
+    buf
+}
+
+pub(super) fn push_envelope(buf: &mut String, message: &Message) {
+    let date = raw_header(message, RfcHeader::Date)
+        .map(|date| date.split_whitespace().collect::<Vec<_>>().join(" "));
+    let subject = raw_header(message, RfcHeader::Subject)
+        .map(|subject| subject.replace(['\r', '\n'], "").trim().to_string());
+    let from = message.get_from();
+    let sender = message.get_sender();
+    let reply_to = message.get_reply_to();
+
+    buf.push('(');
+    push_nstring(buf, date.as_deref().filter(|date| !date.is_empty()));
+    buf.push(' ');
+    push_nstring(buf, subject.as_deref());
+    for addresses in [
+        from,
+        if !sender.is_empty() { sender } else { from },
+        if !reply_to.is_empty() { reply_to } else { from },
+        message.get_to(),
+        message.get_cc(),
+        message.get_bcc(),
+    ] {
+        buf.push(' ');
+        push_addresses(buf, addresses);
+    }
+    buf.push(' ');
+    push_nstring(
+        buf,
+        match message.get_in_reply_to() {
+            HeaderValue::Text(id) => format!("<{}>", id).into(),
+            HeaderValue::TextList(ids) => ids
+                .iter()
+                .map(|id| format!("<{}>", id))
+                .collect::<Vec<_>>()
+                .join(" ")
+                .into(),
+            _ => None,
+        }
+        .as_deref(),
+    );
+    buf.push(' ');
+    push_nstring(
+        buf,
+        message
+            .get_message_id()
+            .map(|id| format!("<{}>", id))
+            .as_deref(),
+    );
+    buf.push(')');
+}
+
+// Groups are delimited by a start marker with the group name in the
+// mailbox field and an end marker with all fields set to NIL.
+fn push_addresses(buf: &mut String, value: &HeaderValue) {
+    match value {
+        HeaderValue::Address(address) => {
+            buf.push('(');
+            push_address(buf, address);
+            buf.push(')');
+        }
+        HeaderValue::AddressList(addresses) => {
+            buf.push('(');
+            for address in addresses {
+                push_address(buf, address);
+            }
+            buf.push(')');
+        }
+        HeaderValue::Group(group) => {
+            buf.push('(');
+            push_group(buf, group.name.as_deref(), &group.addresses);
+            buf.push(')');
+        }
+        HeaderValue::GroupList(groups) => {
+            buf.push('(');
+            for group in groups {
+                push_group(buf, group.name.as_deref(), &group.addresses);
+            }
+            buf.push(')');
+        }
+        _ => buf.push_str("NIL"),
+    }
+}
+
+fn push_group(buf: &mut String, name: Option<&str>, addresses: &[Addr]) {
+    buf.push_str("(NIL NIL ");
+    push_string(buf, &encoded_words(name.unwrap_or_default()));
+    buf.push_str(" NIL)");
+    for address in addresses {
+        push_address(buf, address);
+    }
+    buf.push_str("(NIL NIL NIL NIL)");
+}
+
+fn push_address(buf: &mut String, address: &Addr) {
+    let (mailbox, host) = match address.address.as_deref() {
+        Some(address) => address.rsplit_once('@').unwrap_or((address, "")),
+        None => ("", ""),
+    };
+    buf.push('(');
+    push_nstring(buf, address.name.as_deref().map(encoded_words).as_deref());
+    buf.push_str(" NIL ");
+    push_string(buf, mailbox);
+    buf.push(' ');
+    push_string(buf, host);
+    buf.push(')');
+}
+
+// Returns the raw value of the last instance of a header
+fn raw_header<'y>(message: &'y Message, name: RfcHeader) -> Option<Cow<'y, str>> {
+    let header = message.parts.first()?.headers.get_header(name.as_str())?;
+    Some(String::from_utf8_lossy(raw_bytes(
+        message,
+        header.offset_start,
+        header.offset_end,
+    )))
+}
+
+// Encodes non-ASCII text as UTF-8 encoded-words (RFC 2047), splitting it
+// at character boundaries to keep each word under 75 characters.
+fn encoded_words(text: &str) -> Cow<'_, str> {
+    if text.is_ascii() {
+        return text.into();
+    }
+
+    let mut result = String::with_capacity(text.len() * 2);
+    let mut start = 0;
+    while start < text.len() {
+        let mut end = (start + 45).min(text.len());
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        if start > 0 {
+            result.push(' ');
+        }
+        result.push_str("=?utf-8?b?");
+        result.push_str(
+            std::str::from_utf8(&encode_base64(&text.as_bytes()[start..end], b""))
+                .unwrap_or_default(),
+        );
+        result.push_str("?=");
+        start = end;
+    }
+    result.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Message;
+
+    use super::envelope;
+
+    #[test]
+    fn imap_envelope() {
+        for (message, expected) in [
+            (
+                concat!(
+                    "Date: Mon, 15 Jan 2018\r\n  10:00:00 +0000\r\n",
+                    "From: \"John \\\"Doe\\\"\" <john@example.com>\r\n",
+                    "Sender: list@example.com\r\n",
+                    "To: Jane <jane@example.com>, bob@example.org\r\n",
+                    "Cc: Friends: amy@example.com, Tom <tom@example.com>;\r\n",
+                    "Subject: =?utf-8?q?Gr=C3=BC=C3=9Fe?=\r\n",
+                    "In-Reply-To: <a@example.com> <b@example.com>\r\n",
+                    "Message-ID: <c@example.com>\r\n\r\nHi!\r\n"
+                ),
+                concat!(
+                    "(\"Mon, 15 Jan 2018 10:00:00 +0000\" \"=?utf-8?q?Gr=C3=BC=C3=9Fe?=\" ",
+                    "((\"John \\\"Doe\\\"\" NIL \"john\" \"example.com\")) ",
+                    "((NIL NIL \"list\" \"example.com\")) ",
+                    "((\"John \\\"Doe\\\"\" NIL \"john\" \"example.com\")) ",
+                    "((\"Jane\" NIL \"jane\" \"example.com\")(NIL NIL \"bob\" \"example.org\")) ",
+                    "((NIL NIL \"Friends\" NIL)(NIL NIL \"amy\" \"example.com\")",
+                    "(\"Tom\" NIL \"tom\" \"example.com\")(NIL NIL NIL NIL)) NIL ",
+                    "\"<a@example.com> <b@example.com>\" \"<c@example.com>\")"
+                ),
+            ),
+            (
+                concat!(
+                    "From: =?utf-8?q?J=C3=BCrgen?= <jurgen@example.com>\r\n",
+                    "To: Grüße: amy@example.com;\r\n",
+                    "Subject: Grüße\r\n from\r\n  München\r\n\r\nHi!\r\n"
+                ),
+                concat!(
+                    "(NIL {22}\r\nGrüße from  München ",
+                    "((\"=?utf-8?b?SsO8cmdlbg==?=\" NIL \"jurgen\" \"example.com\")) ",
+                    "((\"=?utf-8?b?SsO8cmdlbg==?=\" NIL \"jurgen\" \"example.com\")) ",
+                    "((\"=?utf-8?b?SsO8cmdlbg==?=\" NIL \"jurgen\" \"example.com\")) ",
+                    "((NIL NIL \"=?utf-8?b?R3LDvMOfZQ==?=\" NIL)",
+                    "(NIL NIL \"amy\" \"example.com\")(NIL NIL NIL NIL)) ",
+                    "NIL NIL NIL NIL)"
+                ),
+            ),
+            (
+                "Subject: Hello\r\n\r\nHi!\r\n",
+                "(NIL \"Hello\" NIL NIL NIL NIL NIL NIL NIL NIL)",
+            ),
+        ] {
+            assert_eq!(
+                envelope(&Message::parse(message.as_bytes()).unwrap()),
+                expected,
+                "{}",
+                message
+            );
+        }
+    }
+}
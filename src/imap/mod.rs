@@ -0,0 +1,95 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Building blocks for IMAP servers.
+
+pub mod body_structure;
+pub mod envelope;
+pub mod search;
+pub mod section;
+pub mod sort;
+pub mod subject;
+pub mod thread;
+
+use std::fmt::Write;
+
+use crate::{Addr, HeaderValue, Message};
+
+/// A message stored in an IMAP mailbox
+#[derive(Debug, Clone, Copy)]
+pub struct MailboxMessage<'x, 'y> {
+    /// Sequence number or UID included in responses
+    pub id: u32,
+    /// The parsed message
+    pub message: &'y Message<'x>,
+    /// Internal date in seconds since the Unix epoch
+    pub internal_date: i64,
+    /// Size of the message in bytes
+    pub size: usize,
+}
+
+impl<'x, 'y> MailboxMessage<'x, 'y> {
+    /// Returns the sent date as defined in RFC 5256, which is the Date
+    /// header or the internal date if the header is missing or invalid.
+    pub fn sent_date(&self) -> i64 {
+        self.message
+            .get_date()
+            .map_or(self.internal_date, |date| date.to_timestamp())
+    }
+}
+
+// Returns the first address of an address header
+fn first_address<'x, 'y>(value: &'y HeaderValue<'x>) -> Option<&'y Addr<'x>> {
+    match value {
+        HeaderValue::Address(address) => Some(address),
+        HeaderValue::AddressList(addresses) => addresses.first(),
+        HeaderValue::Group(group) => group.addresses.first(),
+        HeaderValue::GroupList(groups) => groups.iter().find_map(|group| group.addresses.first()),
+        _ => None,
+    }
+}
+
+// Returns the raw bytes between two offsets of a message. The offsets of
+// nested messages are relative to the top-level message and may end
+// past the nested message.
+fn raw_bytes<'y>(message: &'y Message, start: usize, end: usize) -> &'y [u8] {
+    let base = message.parts.first().map_or(0, |part| part.offset_header);
+    let raw_message = message.raw_message.as_ref();
+    let end = end.saturating_sub(base).min(raw_message.len());
+    raw_message
+        .get(start.saturating_sub(base)..end)
+        .unwrap_or_default()
+}
+
+// Writes an IMAP string, using a literal when it cannot be quoted as
+// quoted strings are limited to 7-bit characters.
+fn push_string(buf: &mut String, value: &str) {
+    if !value.is_ascii() || value.contains(['\r', '\n', '\0']) {
+        let _ = write!(buf, "{{{}}}\r\n{}", value.len(), value);
+    } else {
+        buf.push('"');
+        for ch in value.chars() {
+            if matches!(ch, '"' | '\\') {
+                buf.push('\\');
+            }
+            buf.push(ch);
+        }
+        buf.push('"');
+    }
+}
+
+fn push_nstring(buf: &mut String, value: Option<&str>) {
+    if let Some(value) = value {
+        push_string(buf, value);
+    } else {
+        buf.push_str("NIL");
+    }
+}
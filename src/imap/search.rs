@@ -0,0 +1,321 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::{borrow::Cow, ops::RangeInclusive};
+
+use crate::{
+    decoders::html::html_to_text, Addr, DateTime, Group, Header, HeaderValue, Message,
+    MessageAttachment, PartType,
+};
+
+use super::{raw_bytes, MailboxMessage};
+
+/// IMAP SEARCH keys, as defined in
+/// [RFC 9051 - Internet Message Access Protocol (IMAP) - Version 4rev2 (Section 6.4.4)](https://datatracker.ietf.org/doc/html/rfc9051#section-6.4.4).
+///
+/// Dates only compare the year, month and day. The `UN*` keys are
+/// expressed with [`SearchKey::Not`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SearchKey<'z> {
+    All,
+    Answered,
+    Deleted,
+    Draft,
+    Flagged,
+    Seen,
+    Recent,
+    Keyword(&'z str),
+    SequenceSet(&'z [RangeInclusive<u32>]),
+    Uid(&'z [RangeInclusive<u32>]),
+    From(&'z str),
+    To(&'z str),
+    Cc(&'z str),
+    Bcc(&'z str),
+    Subject(&'z str),
+    Header(&'z str, &'z str),
+    Body(&'z str),
+    Text(&'z str),
+    Before(DateTime),
+    On(DateTime),
+    Since(DateTime),
+    SentBefore(DateTime),
+    SentOn(DateTime),
+    SentSince(DateTime),
+    Larger(usize),
+    Smaller(usize),
+    Not(Box<SearchKey<'z>>),
+    Or(Box<SearchKey<'z>>, Box<SearchKey<'z>>),
+    And(Vec<SearchKey<'z>>),
+}
+
+/// A mailbox message along with the metadata used by SEARCH
+#[derive(Debug, Clone, Copy)]
+pub struct SearchMessage<'x, 'y> {
+    /// The message, its `id` is the sequence number
+    pub message: MailboxMessage<'x, 'y>,
+    /// Unique identifier of the message
+    pub uid: u32,
+    /// System flags such as `\Seen` and keywords, compared case-insensitively
+    pub flags: &'y [&'y str],
+}
+
+/// Returns `true` if the message matches the search key. Text is
+/// matched case-insensitively against the decoded headers and body,
+/// HTML parts are converted to text before matching.
+pub fn matches(message: &SearchMessage, key: &SearchKey) -> bool {
+    let mailbox_message = &message.message;
+    let contents = mailbox_message.message;
+
+    match key {
+        SearchKey::All => true,
+        SearchKey::Answered => has_flag(message, "\\Answered"),
+        SearchKey::Deleted => has_flag(message, "\\Deleted"),
+        SearchKey::Draft => has_flag(message, "\\Draft"),
+        SearchKey::Flagged => has_flag(message, "\\Flagged"),
+        SearchKey::Seen => has_flag(message, "\\Seen"),
+        SearchKey::Recent => has_flag(message, "\\Recent"),
+        SearchKey::Keyword(keyword) => has_flag(message, keyword),
+        SearchKey::SequenceSet(ranges) => ranges
+            .iter()
+            .any(|range| range.contains(&mailbox_message.id)),
+        SearchKey::Uid(ranges) => ranges.iter().any(|range| range.contains(&message.uid)),
+        SearchKey::From(text) => contains(&address_text(contents.get_from()), text),
+        SearchKey::To(text) => contains(&address_text(contents.get_to()), text),
+        SearchKey::Cc(text) => contains(&address_text(contents.get_cc()), text),
+        SearchKey::Bcc(text) => contains(&address_text(contents.get_bcc()), text),
+        SearchKey::Subject(text) => contents
+            .get_subject()
+            .is_some_and(|subject| contains(subject, text)),
+        SearchKey::Header(name, text) => contents.parts.first().is_some_and(|part| {
+            part.headers.iter().any(|header| {
+                header.name.as_str().eq_ignore_ascii_case(name)
+                    && contains(&header_text(contents, header), text)
+            })
+        }),
+        SearchKey::Body(text) => body_contains(contents, text),
+        SearchKey::Text(text) => {
+            contents.parts.first().is_some_and(|part| {
+                part.headers.iter().any(|header| {
+                    contains(header.name.as_str(), text)
+                        || contains(&header_text(contents, header), text)
+                })
+            }) || body_contains(contents, text)
+        }
+        SearchKey::Before(date) => {
+            date_key(&DateTime::from_timestamp(mailbox_message.internal_date)) < date_key(date)
+        }
+        SearchKey::On(date) => {
+            date_key(&DateTime::from_timestamp(mailbox_message.internal_date)) == date_key(date)
+        }
+        SearchKey::Since(date) => {
+            date_key(&DateTime::from_timestamp(mailbox_message.internal_date)) >= date_key(date)
+        }
+        SearchKey::SentBefore(date) => sent_date_key(mailbox_message) < date_key(date),
+        SearchKey::SentOn(date) => sent_date_key(mailbox_message) == date_key(date),
+        SearchKey::SentSince(date) => sent_date_key(mailbox_message) >= date_key(date),
+        SearchKey::Larger(size) => mailbox_message.size > *size,
+        SearchKey::Smaller(size) => mailbox_message.size < *size,
+        SearchKey::Not(key) => !matches(message, key),
+        SearchKey::Or(key1, key2) => matches(message, key1) || matches(message, key2),
+        SearchKey::And(keys) => keys.iter().all(|key| matches(message, key)),
+    }
+}
+
+fn has_flag(message: &SearchMessage, flag: &str) -> bool {
+    message
+        .flags
+        .iter()
+        .any(|message_flag| message_flag.eq_ignore_ascii_case(flag))
+}
+
+fn contains(haystack: &str, needle: &str) -> bool {
+    needle.is_empty() || haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+fn date_key(date: &DateTime) -> (u16, u8, u8) {
+    (date.year, date.month, date.day)
+}
+
+// The Date header in its own time zone, or the internal date
+fn sent_date_key(message: &MailboxMessage) -> (u16, u8, u8) {
+    message.message.get_date().map_or_else(
+        || date_key(&DateTime::from_timestamp(message.internal_date)),
+        date_key,
+    )
+}
+
+// Addresses are matched in the "name <address>" form
+fn address_text(value: &HeaderValue) -> String {
+    let mut text = String::new();
+    match value {
+        HeaderValue::Address(address) => push_address_text(&mut text, address),
+        HeaderValue::AddressList(addresses) => addresses
+            .iter()
+            .for_each(|address| push_address_text(&mut text, address)),
+        HeaderValue::Group(group) => push_group_text(&mut text, group),
+        HeaderValue::GroupList(groups) => groups
+            .iter()
+            .for_each(|group| push_group_text(&mut text, group)),
+        _ => (),
+    }
+    text
+}
+
+fn push_group_text(text: &mut String, group: &Group) {
+    if let Some(name) = &group.name {
+        text.push_str(name);
+        text.push('\n');
+    }
+    for address in &group.addresses {
+        push_address_text(text, address);
+    }
+}
+
+fn push_address_text(text: &mut String, address: &Addr) {
+    if let Some(name) = &address.name {
+        text.push_str(name);
+        text.push(' ');
+    }
+    if let Some(address) = &address.address {
+        text.push('<');
+        text.push_str(address);
+        text.push('>');
+    }
+    text.push('\n');
+}
+
+// Decoded text values are used when available, otherwise the raw value
+fn header_text<'y>(message: &'y Message, header: &'y Header) -> Cow<'y, str> {
+    match &header.value {
+        HeaderValue::Text(text) => text.as_ref().into(),
+        HeaderValue::TextList(texts) => texts.join("\n").into(),
+        HeaderValue::Address(_)
+        | HeaderValue::AddressList(_)
+        | HeaderValue::Group(_)
+        | HeaderValue::GroupList(_) => address_text(&header.value).into(),
+        _ => String::from_utf8_lossy(raw_bytes(message, header.offset_start, header.offset_end)),
+    }
+}
+
+// Searches text parts, including those of nested messages
+fn body_contains(message: &Message, text: &str) -> bool {
+    message.parts.iter().any(|part| match &part.body {
+        PartType::Text(contents) => contains(contents, text),
+        PartType::Html(contents) => contains(&html_to_text(contents), text),
+        PartType::Message(MessageAttachment::Parsed(nested_message)) => {
+            body_contains(nested_message, text)
+        }
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{imap::MailboxMessage, DateTime, Message};
+
+    use super::{matches, SearchKey, SearchMessage};
+
+    #[test]
+    fn imap_search() {
+        let contents = Message::parse(
+            concat!(
+                "From: =?utf-8?q?J=C3=BCrgen?= <jurgen@example.com>\r\n",
+                "To: Friends: jane@example.com;\r\n",
+                "Cc: Bob <bob@example.org>\r\n",
+                "Subject: Quarterly REPORT\r\n",
+                "Date: Mon, 15 Jan 2018 23:30:00 -0800\r\n",
+                "X-Priority: High\r\n",
+                "Content-Type: multipart/alternative; boundary=\"b\"\r\n\r\n",
+                "--b\r\n",
+                "Content-Type: text/plain; charset=utf-8\r\n",
+                "Content-Transfer-Encoding: quoted-printable\r\n\r\n",
+                "Die Zahlen f=C3=BCr das Quartal.\r\n",
+                "--b\r\n",
+                "Content-Type: text/html\r\n\r\n",
+                "<p>The <b>numbers</b>&nbsp;are&amp;in</p>\r\n",
+                "--b--\r\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let message = SearchMessage {
+            message: MailboxMessage {
+                id: 3,
+                message: &contents,
+                internal_date: 1516089600, // 16 Jan 2018 08:00 UTC
+                size: 500,
+            },
+            uid: 30,
+            flags: &["\\Seen", "$Important"],
+        };
+        let date = |day| DateTime {
+            year: 2018,
+            month: 1,
+            day,
+            hour: 0,
+            minute: 0,
+            second: 0,
+            tz_before_gmt: false,
+            tz_hour: 0,
+            tz_minute: 0,
+        };
+
+        for (key, expected) in [
+            (SearchKey::All, true),
+            (SearchKey::Seen, true),
+            (SearchKey::Flagged, false),
+            (SearchKey::Keyword("$important"), true),
+            (SearchKey::SequenceSet(&[1..=2, 3..=3]), true),
+            (SearchKey::Uid(&[1..=29]), false),
+            (SearchKey::From("jürgen"), true),
+            (SearchKey::From("JURGEN@EXAMPLE"), true),
+            (SearchKey::From("bob"), false),
+            (SearchKey::To("friends"), true),
+            (SearchKey::To("jane@"), true),
+            (SearchKey::Cc("bob <bob@"), true),
+            (SearchKey::Bcc(""), true),
+            (SearchKey::Subject("quarterly report"), true),
+            (SearchKey::Header("x-priority", "high"), true),
+            (SearchKey::Header("X-Priority", ""), true),
+            (SearchKey::Header("X-Mailer", ""), false),
+            (SearchKey::Header("Date", "23:30"), true),
+            (SearchKey::Body("FÜR DAS"), true),
+            (SearchKey::Body("the numbers"), true),
+            (SearchKey::Body("are&in"), true),
+            (SearchKey::Body("<b>"), false),
+            (SearchKey::Body("quarterly"), false),
+            (SearchKey::Text("quarterly"), true),
+            (SearchKey::Text("x-priority"), true),
+            (SearchKey::SentOn(date(15)), true),
+            (SearchKey::SentBefore(date(15)), false),
+            (SearchKey::SentSince(date(15)), true),
+            (SearchKey::On(date(16)), true),
+            (SearchKey::Before(date(16)), false),
+            (SearchKey::Since(date(17)), false),
+            (SearchKey::Larger(500), false),
+            (SearchKey::Smaller(501), true),
+            (SearchKey::Not(Box::new(SearchKey::Draft)), true),
+            (
+                SearchKey::Or(
+                    Box::new(SearchKey::Deleted),
+                    Box::new(SearchKey::Subject("report")),
+                ),
+                true,
+            ),
+            (
+                SearchKey::And(vec![SearchKey::Seen, SearchKey::Answered]),
+                false,
+            ),
+        ] {
+            assert_eq!(matches(&message, &key), expected, "{:?}", key);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::borrow::Cow;
+
+use crate::{
+    decoders::{
+        base64::decode_base64, quoted_printable::decode_quoted_printable,
+        uuencode::decode_uuencode, DecodeResult,
+    },
+    parsers::message::MessageStream,
+    Encoding, Message, MessageAttachment, MessagePartId, PartType,
+};
+
+use super::raw_bytes;
+
+/// The text part of a `BODY[section]` fetch item
+/// ([RFC 9051](https://datatracker.ietf.org/doc/html/rfc9051#section-6.4.5))
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SectionText<'z> {
+    Header,
+    HeaderFields(&'z [&'z str]),
+    HeaderFieldsNot(&'z [&'z str]),
+    Text,
+    Mime,
+}
+
+/// Maps an IMAP part number, such as `[1, 2]` for `1.2`, onto a message
+/// part. Returns the message that contains the part, which is a nested
+/// message for parts inside a `message/rfc822`, along with the part id.
+/// An empty part number refers to the whole message.
+pub fn find_part<'x, 'y>(
+    message: &'y Message<'x>,
+    part: &[u32],
+) -> Option<(&'y Message<'x>, MessagePartId)> {
+    let mut message = message;
+    let mut part_id = 0;
+
+    for (pos, &number) in part.iter().enumerate() {
+        let mut mime_part = message.parts.get(part_id)?;
+        let mut is_message_body = pos == 0;
+
+        // Sub-parts of a message/rfc822 refer to the nested message body
+        if pos > 0 {
+            if let PartType::Message(MessageAttachment::Parsed(nested_message)) = &mime_part.body {
+                message = nested_message;
+                part_id = 0;
+                mime_part = message.parts.get(part_id)?;
+                is_message_body = true;
+            }
+        }
+
+        match &mime_part.body {
+            PartType::Multipart(sub_part_ids) => {
+                part_id = *sub_part_ids.get((number as usize).checked_sub(1)?)?;
+            }
+            // A non-multipart message body is part number 1
+            _ if is_message_body && number == 1 => (),
+            _ => return None,
+        }
+    }
+
+    Some((message, part_id))
+}
+
+/// Returns the contents of a `BODY[section]<offset.length>` fetch item,
+/// with `partial` being the optional offset and length. Contents are
+/// returned exactly as they appear in the raw message, except for
+/// `HEADER.FIELDS` and `HEADER.FIELDS.NOT` which return the matching
+/// header lines followed by a blank line.
+pub fn body_section<'y>(
+    message: &'y Message,
+    part: &[u32],
+    text: Option<SectionText>,
+    partial: Option<(usize, usize)>,
+) -> Option<Cow<'y, [u8]>> {
+    let (message, part_id) = find_part(message, part)?;
+    let mime_part = message.parts.get(part_id)?;
+
+    let contents = match text {
+        None if part.is_empty() => message.raw_message.as_ref().into(),
+        None => raw_bytes(message, mime_part.offset_body, mime_part.offset_end).into(),
+        Some(SectionText::Mime) if !part.is_empty() => {
+            raw_bytes(message, mime_part.offset_header, mime_part.offset_body).into()
+        }
+        Some(text) => {
+            // HEADER and TEXT refer to the message or to a nested message/rfc822
+            let message = match &mime_part.body {
+                _ if part.is_empty() => message,
+                PartType::Message(MessageAttachment::Parsed(nested_message)) => nested_message,
+                _ => return None,
+            };
+            let root_part = message.parts.first()?;
+
+            match text {
+                SectionText::Header => {
+                    raw_bytes(message, root_part.offset_header, root_part.offset_body).into()
+                }
+                SectionText::HeaderFields(names) => header_fields(message, names, false).into(),
+                SectionText::HeaderFieldsNot(names) => header_fields(message, names, true).into(),
+                SectionText::Text => {
+                    raw_bytes(message, root_part.offset_body, root_part.offset_end).into()
+                }
+                SectionText::Mime => return None,
+            }
+        }
+    };
+
+    Some(apply_partial(contents, partial))
+}
+
+/// Returns the contents of a `BINARY[section]<offset.length>` fetch item
+/// ([RFC 3516](https://datatracker.ietf.org/doc/html/rfc3516)), which is
+/// the part body with its Content-Transfer-Encoding removed.
+/// Returns `None` if the part does not exist or could not be decoded.
+pub fn binary_section<'y>(
+    message: &'y Message,
+    part: &[u32],
+    partial: Option<(usize, usize)>,
+) -> Option<Cow<'y, [u8]>> {
+    let (message, part_id) = find_part(message, part)?;
+    let mime_part = message.parts.get(part_id)?;
+    if part.is_empty() {
+        return Some(apply_partial(message.raw_message.as_ref().into(), partial));
+    }
+
+    let contents = raw_bytes(message, mime_part.offset_body, mime_part.offset_end);
+    let stream = MessageStream::new(contents);
+    let contents = match match mime_part.encoding {
+        _ if contents.is_empty() => return Some(contents.into()),
+        Encoding::None => return Some(apply_partial(contents.into(), partial)),
+        Encoding::Base64 => decode_base64(&stream, 0, &[], false),
+        Encoding::QuotedPrintable => decode_quoted_printable(&stream, 0, &[], false),
+        Encoding::UuEncode => decode_uuencode(&stream, 0, &[], false),
+    } {
+        (_, DecodeResult::Owned(bytes)) => bytes.into(),
+        (_, DecodeResult::Borrowed((from, to))) => contents.get(from..to)?.into(),
+        (_, DecodeResult::Empty) => return None,
+    };
+
+    Some(apply_partial(contents, partial))
+}
+
+/// Returns the size of a `BINARY.SIZE[section]` fetch item.
+pub fn binary_size(message: &Message, part: &[u32]) -> Option<usize> {
+    binary_section(message, part, None).map(|contents| contents.len())
+}
+
+// Header lines are returned raw, including folded lines.
+fn header_fields(message: &Message, names: &[&str], is_not: bool) -> Vec<u8> {
+    let mut contents = Vec::new();
+    if let Some(root_part) = message.parts.first() {
+        for header in &root_part.headers {
+            if names
+                .iter()
+                .any(|name| header.name.as_str().eq_ignore_ascii_case(name))
+                != is_not
+            {
+                // Offsets point to the header value, seek the start of the line
+                let line_start = raw_bytes(message, root_part.offset_header, header.offset_start)
+                    .iter()
+                    .rposition(|&ch| ch == b'\n')
+                    .map_or(root_part.offset_header, |pos| {
+                        root_part.offset_header + pos + 1
+                    });
+                contents.extend_from_slice(raw_bytes(message, line_start, header.offset_end));
+            }
+        }
+    }
+    contents.extend_from_slice(b"\r\n");
+    contents
+}
+
+fn apply_partial(contents: Cow<[u8]>, partial: Option<(usize, usize)>) -> Cow<[u8]> {
+    if let Some((offset, length)) = partial {
+        let start = offset.min(contents.len());
+        let end = offset.saturating_add(length).min(contents.len());
+        match contents {
+            Cow::Borrowed(contents) => contents[start..end].into(),
+            Cow::Owned(contents) => contents[start..end].to_vec().into(),
+        }
+    } else {
+        contents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Message;
+
+    use super::{binary_section, binary_size, body_section, find_part, SectionText};
+
+    #[test]
+    fn imap_body_section() {
+        let message = Message::parse(
+            concat!(
+                "From: john@example.com\r\n",
+                "To: jane@example.com\r\n",
+                "Subject: Test\r\n",
+                "Content-Type: multipart/mixed; boundary=\"outer\"\r\n\r\n",
+                "--outer\r\n",
+                "Content-Type: multipart/alternative; boundary=\"inner\"\r\n\r\n",
+                "--inner\r\n",
+                "Content-Type: text/plain\r\n\r\n",
+                "Hello, world!\r\n",
+                "--inner\r\n",
+                "Content-Type: text/html\r\n",
+                "Content-Transfer-Encoding: quoted-printable\r\n\r\n",
+                "<p>Hello, w=C3=B6rld!</p>\r\n",
+                "--inner--\r\n",
+                "--outer\r\n",
+                "Content-Type: application/octet-stream\r\n",
+                "Content-Transfer-Encoding: base64\r\n\r\n",
+                "SGVsbG8gd29y\r\nbGQh\r\n",
+                "--outer\r\n",
+                "Content-Type: message/rfc822\r\n\r\n",
+                "From: jane@example.com\r\n",
+                "Subject: Nested\r\n",
+                "Content-Type: multipart/mixed; boundary=\"nested\"\r\n\r\n",
+                "--nested\r\n",
+                "Content-Type: text/plain\r\n\r\n",
+                "Nested part\r\n",
+                "--nested--\r\n",
+                "--outer--\r\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        for (part, text, partial, expected) in [
+            (
+                &[][..],
+                Some(SectionText::HeaderFields(&["from", "TO"])),
+                None,
+                "From: john@example.com\r\nTo: jane@example.com\r\n\r\n",
+            ),
+            (
+                &[],
+                Some(SectionText::HeaderFieldsNot(&["Content-Type", "To"])),
+                None,
+                "From: john@example.com\r\nSubject: Test\r\n\r\n",
+            ),
+            (&[], Some(SectionText::Text), Some((0, 9)), "--outer\r\n"),
+            (&[1, 1], None, None, "Hello, world!"),
+            (
+                &[1, 2],
+                Some(SectionText::Mime),
+                None,
+                "Content-Type: text/html\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\n",
+            ),
+            (&[1, 2], None, Some((3, 5)), "Hello"),
+            (&[2], None, Some((14, 100)), "bGQh"),
+            (
+                &[3],
+                Some(SectionText::Header),
+                None,
+                concat!(
+                    "From: jane@example.com\r\nSubject: Nested\r\n",
+                    "Content-Type: multipart/mixed; boundary=\"nested\"\r\n\r\n"
+                ),
+            ),
+            (
+                &[3],
+                Some(SectionText::Text),
+                None,
+                "--nested\r\nContent-Type: text/plain\r\n\r\nNested part\r\n--nested--",
+            ),
+            (
+                &[3],
+                Some(SectionText::HeaderFields(&["Subject"])),
+                None,
+                "Subject: Nested\r\n\r\n",
+            ),
+            (&[3, 1], None, None, "Nested part"),
+            (
+                &[3, 1],
+                Some(SectionText::Mime),
+                None,
+                "Content-Type: text/plain\r\n\r\n",
+            ),
+        ] {
+            assert_eq!(
+                String::from_utf8_lossy(&body_section(&message, part, text, partial).unwrap()),
+                expected,
+                "{:?} {:?}",
+                part,
+                text
+            );
+        }
+
+        for part in [&[2, 1][..], &[4], &[3, 2], &[0], &[1, 2, 1]] {
+            assert!(find_part(&message, part).is_none(), "{:?}", part);
+        }
+        assert!(body_section(&message, &[1, 1], Some(SectionText::Text), None).is_none());
+        assert!(body_section(&message, &[], Some(SectionText::Mime), None).is_none());
+
+        assert_eq!(
+            binary_section(&message, &[1, 2], None).unwrap().as_ref(),
+            "<p>Hello, wörld!</p>".as_bytes()
+        );
+        assert_eq!(
+            binary_section(&message, &[2], Some((6, 100)))
+                .unwrap()
+                .as_ref(),
+            b"world!"
+        );
+        assert_eq!(binary_size(&message, &[2]), Some(12));
+        assert_eq!(binary_size(&message, &[3, 1]), Some(11));
+    }
+}
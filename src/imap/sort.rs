@@ -0,0 +1,207 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::cmp::Ordering;
+
+use crate::HeaderValue;
+
+use super::{first_address, subject::base_subject, MailboxMessage};
+
+/// IMAP SORT keys, as defined in
+/// [RFC 5256](https://datatracker.ietf.org/doc/html/rfc5256#section-3) and
+/// [RFC 5957](https://datatracker.ietf.org/doc/html/rfc5957)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SortKey {
+    Arrival,
+    Cc,
+    Date,
+    From,
+    Size,
+    Subject,
+    To,
+    DisplayFrom,
+    DisplayTo,
+}
+
+/// A SORT key along with its `REVERSE` modifier
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SortCriterion {
+    pub key: SortKey,
+    pub is_reverse: bool,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SortValue {
+    Number(i64),
+    Text(String),
+}
+
+impl SortCriterion {
+    /// Creates an ascending sort criterion
+    pub fn new(key: SortKey) -> Self {
+        SortCriterion {
+            key,
+            is_reverse: false,
+        }
+    }
+
+    /// Creates a descending sort criterion
+    pub fn reverse(key: SortKey) -> Self {
+        SortCriterion {
+            key,
+            is_reverse: true,
+        }
+    }
+}
+
+/// Sorts the messages of a mailbox and returns their ids. Messages that
+/// compare equal on all criteria are returned in their original order,
+/// which is expected to be the sequence number order.
+pub fn sort(messages: &[MailboxMessage], criteria: &[SortCriterion]) -> Vec<u32> {
+    let mut sorted = messages
+        .iter()
+        .map(|message| {
+            (
+                criteria
+                    .iter()
+                    .map(|criterion| sort_value(message, criterion.key))
+                    .collect::<Vec<_>>(),
+                message.id,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    sorted.sort_by(|(a, _), (b, _)| {
+        a.iter()
+            .zip(b.iter())
+            .zip(criteria.iter())
+            .map(|((a, b), criterion)| {
+                if criterion.is_reverse {
+                    b.cmp(a)
+                } else {
+                    a.cmp(b)
+                }
+            })
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+    sorted.into_iter().map(|(_, id)| id).collect()
+}
+
+// Text values are compared using the i;ascii-casemap collation
+fn sort_value(message: &MailboxMessage, key: SortKey) -> SortValue {
+    let text = match key {
+        SortKey::Arrival => return SortValue::Number(message.internal_date),
+        SortKey::Date => return SortValue::Number(message.sent_date()),
+        SortKey::Size => return SortValue::Number(message.size as i64),
+        SortKey::Cc => address_mailbox(message.message.get_cc()),
+        SortKey::From => address_mailbox(message.message.get_from()),
+        SortKey::To => address_mailbox(message.message.get_to()),
+        SortKey::DisplayFrom => display_name(message.message.get_from()),
+        SortKey::DisplayTo => display_name(message.message.get_to()),
+        SortKey::Subject => message
+            .message
+            .get_subject()
+            .map(base_subject)
+            .unwrap_or_default(),
+    };
+    SortValue::Text(text.to_ascii_uppercase())
+}
+
+// The local part of the first address
+fn address_mailbox(value: &HeaderValue) -> String {
+    first_address(value)
+        .and_then(|address| address.address.as_deref())
+        .map(|address| {
+            address
+                .rsplit_once('@')
+                .map_or(address, |(mailbox, _)| mailbox)
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+// The display name of the first address, or its address if it has none
+fn display_name(value: &HeaderValue) -> String {
+    first_address(value)
+        .and_then(|address| {
+            address
+                .name
+                .as_deref()
+                .filter(|name| !name.trim().is_empty())
+                .or(address.address.as_deref())
+        })
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{imap::MailboxMessage, Message};
+
+    use super::{sort, SortCriterion, SortKey};
+
+    #[test]
+    fn imap_sort() {
+        let raw_messages = [
+            concat!(
+                "From: Zoe <alice@example.com>\nTo: bob@example.com\n",
+                "Subject: Re: Beta\nDate: Mon, 15 Jan 2018 10:00:00 +0000\n\nA\n"
+            ),
+            concat!(
+                "From: carol@example.com\nTo: Dave <zed@example.com>\nCc: amy@example.com\n",
+                "Subject: alpha\nDate: Mon, 15 Jan 2018 09:00:00 +0000\n\nB\n"
+            ),
+            concat!(
+                "From: \"Bert\" <bert@example.com>\nTo: ann@example.com\n",
+                "Subject: [list] Beta (fwd)\n\nC\n"
+            ),
+        ];
+        let messages = raw_messages
+            .iter()
+            .map(|message| Message::parse(message.as_bytes()).unwrap())
+            .collect::<Vec<_>>();
+        let messages = messages
+            .iter()
+            .zip([(30, 300), (20, 100), (10, 200)])
+            .enumerate()
+            .map(|(pos, (message, (internal_date, size)))| MailboxMessage {
+                id: pos as u32 + 1,
+                message,
+                internal_date,
+                size,
+            })
+            .collect::<Vec<_>>();
+
+        for (criteria, expected) in [
+            (vec![SortCriterion::new(SortKey::Arrival)], vec![3, 2, 1]),
+            (vec![SortCriterion::new(SortKey::Date)], vec![3, 2, 1]),
+            (vec![SortCriterion::reverse(SortKey::Size)], vec![1, 3, 2]),
+            (vec![SortCriterion::new(SortKey::From)], vec![1, 3, 2]),
+            (
+                vec![SortCriterion::new(SortKey::DisplayFrom)],
+                vec![3, 2, 1],
+            ),
+            (vec![SortCriterion::new(SortKey::To)], vec![3, 1, 2]),
+            (vec![SortCriterion::new(SortKey::DisplayTo)], vec![3, 1, 2]),
+            (vec![SortCriterion::new(SortKey::Cc)], vec![1, 3, 2]),
+            (
+                vec![
+                    SortCriterion::new(SortKey::Subject),
+                    SortCriterion::reverse(SortKey::Date),
+                ],
+                vec![2, 1, 3],
+            ),
+        ] {
+            assert_eq!(sort(&messages, &criteria), expected, "{:?}", criteria);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+/// Returns the base subject as defined in
+/// [RFC 5256 - Internet Message Access Protocol - SORT and THREAD Extensions (Section 2.1)](https://datatracker.ietf.org/doc/html/rfc5256#section-2.1).
+///
+/// Unlike [`Message::get_thread_name`](crate::Message::get_thread_name),
+/// only the English `re`, `fw` and `fwd` prefixes are recognized.
+pub fn base_subject(subject: &str) -> String {
+    extract_base_subject(subject).0
+}
+
+/// Returns the base subject along with whether the subject indicates a
+/// reply or forward.
+pub fn extract_base_subject(subject: &str) -> (String, bool) {
+    // (1) Tabs and continuations are converted to a single space
+    let mut normalized = String::with_capacity(subject.len());
+    for ch in subject.chars() {
+        if matches!(ch, ' ' | '\t' | '\r' | '\n') {
+            if !normalized.ends_with(' ') {
+                normalized.push(' ');
+            }
+        } else {
+            normalized.push(ch);
+        }
+    }
+
+    let mut subject = normalized.as_str();
+    let mut is_reply = false;
+    loop {
+        // (2) Remove trailing "(fwd)" and spaces
+        loop {
+            subject = subject.trim_end_matches(' ');
+            match subject
+                .len()
+                .checked_sub(5)
+                .and_then(|start| Some((start, subject.get(start..)?)))
+            {
+                Some((start, trailer)) if trailer.eq_ignore_ascii_case("(fwd)") => {
+                    subject = &subject[..start];
+                    is_reply = true;
+                }
+                _ => break,
+            }
+        }
+
+        loop {
+            let prev_len = subject.len();
+
+            // (3) Remove leading "re:", "fw:" and "fwd:", optionally
+            // preceded by blobs.
+            loop {
+                subject = subject.trim_start_matches(' ');
+                match strip_refwd(subject) {
+                    Some(rest) => {
+                        subject = rest;
+                        is_reply = true;
+                    }
+                    None => break,
+                }
+            }
+
+            // (4) Remove a leading blob unless it is all that is left
+            if let Some(rest) = strip_blob(subject).filter(|rest| !rest.is_empty()) {
+                subject = rest;
+            }
+
+            // (5) Repeat until no changes are made
+            if subject.len() == prev_len {
+                break;
+            }
+        }
+
+        // (6) Remove "[fwd: ... ]" and start over
+        if subject.len() >= 6
+            && subject
+                .get(..5)
+                .is_some_and(|header| header.eq_ignore_ascii_case("[fwd:"))
+            && subject.ends_with(']')
+        {
+            subject = &subject[5..subject.len() - 1];
+            is_reply = true;
+        } else {
+            break;
+        }
+    }
+
+    (subject.to_string(), is_reply)
+}
+
+// subj-refwd = ("re" / ("fw" ["d"])) *WSP [subj-blob] ":"
+fn strip_refwd(text: &str) -> Option<&str> {
+    let mut text = text;
+    while let Some(rest) = strip_blob(text) {
+        text = rest;
+    }
+
+    let prefix_len = ["fwd", "fw", "re"].iter().find_map(|prefix| {
+        text.get(..prefix.len())
+            .filter(|text| text.eq_ignore_ascii_case(prefix))
+            .map(|_| prefix.len())
+    })?;
+    let text = text[prefix_len..].trim_start_matches(' ');
+    text.strip_prefix(':')
+        .or_else(|| strip_blob(text).and_then(|rest| rest.strip_prefix(':')))
+}
+
+// subj-blob = "[" *BLOBCHAR "]" *WSP
+fn strip_blob(text: &str) -> Option<&str> {
+    let text = text.strip_prefix('[')?;
+    let end = text.find(['[', ']'])?;
+    if text[end..].starts_with(']') {
+        Some(text[end + 1..].trim_start_matches(' '))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::extract_base_subject;
+
+    #[test]
+    fn rfc5256_base_subject() {
+        for (subject, expected_subject, expected_is_reply) in [
+            ("Hello", "Hello", false),
+            ("  Hello\t\r\n  world  ", "Hello world", false),
+            ("Re: Hello", "Hello", true),
+            ("RE:re: Fwd: FW: Hello", "Hello", true),
+            ("Re [list]: Hello", "Hello", true),
+            ("[list] Re: Hello", "Hello", true),
+            ("[list][other] Hello", "Hello", false),
+            ("Hello (fwd) (FWD)", "Hello", true),
+            ("[Fwd: Re: Hello]", "Hello", true),
+            ("[fwd: [list] Hello] (fwd)", "Hello", true),
+            ("[list]", "[list]", false),
+            ("Re: [list]", "[list]", true),
+            ("Reply needed", "Reply needed", false),
+            ("Re: Grüße", "Grüße", true),
+            ("", "", false),
+        ] {
+            assert_eq!(
+                extract_base_subject(subject),
+                (expected_subject.to_string(), expected_is_reply),
+                "{:?}",
+                subject
+            );
+        }
+    }
+}
@@ -0,0 +1,213 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use crate::threading::{thread_references, ThreadMessage, ThreadNode};
+
+use super::{subject::extract_base_subject, MailboxMessage};
+
+/// IMAP THREAD algorithms
+/// ([RFC 5256](https://datatracker.ietf.org/doc/html/rfc5256#section-3))
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ThreadAlgorithm {
+    OrderedSubject,
+    References,
+}
+
+/// Threads the messages of a mailbox, which are expected to be in
+/// sequence number order. The message positions in the returned nodes
+/// refer to the `messages` slice.
+pub fn thread(messages: &[MailboxMessage], algorithm: ThreadAlgorithm) -> Vec<ThreadNode> {
+    match algorithm {
+        ThreadAlgorithm::OrderedSubject => thread_ordered_subject(messages),
+        ThreadAlgorithm::References => thread_references(
+            messages
+                .iter()
+                .map(|message| {
+                    ThreadMessage::new(
+                        message.message,
+                        message.message.get_subject().map(|subject| {
+                            let (subject, is_reply) = extract_base_subject(subject);
+                            (subject.to_ascii_uppercase(), is_reply)
+                        }),
+                        message.sent_date().into(),
+                    )
+                })
+                .collect(),
+        ),
+    }
+}
+
+// Messages are grouped by base subject, the first message of each group
+// is the parent of all others.
+fn thread_ordered_subject(messages: &[MailboxMessage]) -> Vec<ThreadNode> {
+    let mut sorted = messages
+        .iter()
+        .enumerate()
+        .map(|(pos, message)| {
+            (
+                message
+                    .message
+                    .get_subject()
+                    .map(base_subject_key)
+                    .unwrap_or_default(),
+                message.sent_date(),
+                pos,
+            )
+        })
+        .collect::<Vec<_>>();
+    sorted.sort();
+
+    let mut threads: Vec<((i64, usize), ThreadNode)> = Vec::new();
+    let mut last_subject = None;
+    for (subject, date, pos) in sorted {
+        let node = ThreadNode {
+            message: Some(pos),
+            children: Vec::new(),
+        };
+        match threads.last_mut() {
+            Some((_, parent)) if last_subject.as_ref() == Some(&subject) => {
+                parent.children.push(node);
+            }
+            _ => {
+                threads.push(((date, pos), node));
+                last_subject = Some(subject);
+            }
+        }
+    }
+
+    threads.sort_by_key(|(key, _)| *key);
+    threads.into_iter().map(|(_, node)| node).collect()
+}
+
+fn base_subject_key(subject: &str) -> String {
+    extract_base_subject(subject).0.to_ascii_uppercase()
+}
+
+/// Formats threads as the contents of an IMAP THREAD response, such as
+/// `(1 2 (3)(4))((5)(6))`, using the ids of the threaded messages.
+pub fn format_threads(threads: &[ThreadNode], messages: &[MailboxMessage]) -> String {
+    let mut result = String::new();
+    for thread in threads {
+        result.push('(');
+        format_node(thread, messages, &mut result);
+        result.push(')');
+    }
+    result
+}
+
+// Threads are formatted with an explicit stack as they can be arbitrarily
+// deep, `None` entries close a parenthesized child.
+fn format_node(node: &ThreadNode, messages: &[MailboxMessage], result: &mut String) {
+    let mut pending = vec![Some((node, false))];
+    while let Some(entry) = pending.pop() {
+        let (node, is_nested) = match entry {
+            Some(entry) => entry,
+            None => {
+                result.push(')');
+                continue;
+            }
+        };
+        if is_nested {
+            result.push('(');
+            pending.push(None);
+        }
+
+        if let Some(message) = node.message.and_then(|pos| messages.get(pos)) {
+            result.push_str(&message.id.to_string());
+            if !node.children.is_empty() {
+                result.push(' ');
+            }
+        }
+
+        // A single reply continues the chain, multiple replies are nested
+        if let [child] = node.children.as_slice() {
+            pending.push(Some((child, false)));
+        } else {
+            pending.extend(node.children.iter().rev().map(|child| Some((child, true))));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{imap::MailboxMessage, threading::ThreadNode, Message};
+
+    use super::{format_threads, thread, ThreadAlgorithm};
+
+    #[test]
+    fn imap_thread() {
+        let raw_messages = [
+            ("a", "", "Hello", Some(10)),
+            ("b", "<a>", "Re: Hello", Some(11)),
+            ("c", "<a> <m>", "RE: hello", Some(12)),
+            ("d", "<z>", "Re: Lunch?", Some(9)),
+            ("e", "<z>", "[list] Lunch? (fwd)", Some(8)),
+            ("f", "", "Meeting", None),
+            ("g", "", "Re: Meeting", Some(16)),
+            ("h", "<b>", "Re: Hello", Some(13)),
+        ]
+        .iter()
+        .map(|(id, references, subject, hour)| {
+            let mut message = format!("Message-ID: <{}>\nSubject: {}\n", id, subject);
+            if let Some(hour) = hour {
+                message.push_str(&format!("Date: Mon, 15 Jan 2018 {:02}:00:00 +0000\n", hour));
+            }
+            if !references.is_empty() {
+                message.push_str(&format!("References: {}\n", references));
+            }
+            message.push_str("\nHi.\n");
+            message
+        })
+        .collect::<Vec<_>>();
+        let messages = raw_messages
+            .iter()
+            .map(|message| Message::parse(message.as_bytes()).unwrap())
+            .collect::<Vec<_>>();
+        let messages = messages
+            .iter()
+            .enumerate()
+            .map(|(pos, message)| MailboxMessage {
+                id: pos as u32 + 1,
+                message,
+                internal_date: 1516028400, // 15:00
+                size: 100,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            format_threads(&thread(&messages, ThreadAlgorithm::References), &messages),
+            "((5)(4))(1 (2 8)(3))(6 7)"
+        );
+        assert_eq!(
+            format_threads(
+                &thread(&messages, ThreadAlgorithm::OrderedSubject),
+                &messages
+            ),
+            "(5 4)(1 (2)(3)(8))(6 7)"
+        );
+
+        // Long reply chains are formatted without recursion
+        let mut chain = ThreadNode {
+            message: Some(0),
+            children: vec![],
+        };
+        for _ in 1..100000 {
+            chain = ThreadNode {
+                message: Some(0),
+                children: vec![chain],
+            };
+        }
+        assert_eq!(
+            format_threads(&[chain], &messages[..1]),
+            format!("({})", vec!["1"; 100000].join(" "))
+        );
+    }
+}
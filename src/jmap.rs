@@ -0,0 +1,673 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Conversion of messages into JMAP Email objects
+//! ([RFC 8621](https://datatracker.ietf.org/doc/html/rfc8621#section-4)).
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "serde_support")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    parsers::preview::{preview_html, preview_text, truncate_html, truncate_text},
+    Addr, Header, HeaderForm, HeaderValue, Message, MessagePart, MessagePartId, MimeHeaders,
+    PartType,
+};
+
+/// Options of the conversion, matching the arguments of `Email/get`
+#[derive(Debug, Default, Clone)]
+pub struct EmailOptions<'z> {
+    /// Header properties to include, such as `header:X-Tenant:asText:all`
+    pub header_properties: &'z [&'z str],
+    /// Include the values of the `textBody` parts in `bodyValues`
+    pub fetch_text_body_values: bool,
+    /// Include the values of the `htmlBody` parts in `bodyValues`
+    pub fetch_html_body_values: bool,
+    /// Include the values of all text parts in `bodyValues`
+    pub fetch_all_body_values: bool,
+    /// Maximum size of each body value, zero for no limit
+    pub max_body_value_bytes: usize,
+}
+
+/// A JMAP Email object, without the metadata properties
+/// (`id`, `blobId`, `threadId`, `mailboxIds`, `keywords` and `receivedAt`)
+/// which are managed by the server.
+#[derive(Debug, Default, PartialEq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde_support", serde(rename_all = "camelCase"))]
+pub struct Email {
+    pub size: usize,
+    pub headers: Vec<EmailHeader>,
+    pub message_id: Option<Vec<String>>,
+    pub in_reply_to: Option<Vec<String>>,
+    pub references: Option<Vec<String>>,
+    pub sender: Option<Vec<EmailAddress>>,
+    pub from: Option<Vec<EmailAddress>>,
+    pub to: Option<Vec<EmailAddress>>,
+    pub cc: Option<Vec<EmailAddress>>,
+    pub bcc: Option<Vec<EmailAddress>>,
+    pub reply_to: Option<Vec<EmailAddress>>,
+    pub subject: Option<String>,
+    pub sent_at: Option<String>,
+    pub body_structure: EmailBodyPart,
+    pub body_values: BTreeMap<String, EmailBodyValue>,
+    pub text_body: Vec<EmailBodyPart>,
+    pub html_body: Vec<EmailBodyPart>,
+    pub attachments: Vec<EmailBodyPart>,
+    pub has_attachment: bool,
+    pub preview: String,
+    /// The requested `header:` properties
+    #[cfg_attr(feature = "serde_support", serde(flatten))]
+    pub header_properties: BTreeMap<String, HeaderProperty>,
+}
+
+/// A raw header field
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
+}
+
+/// A mailbox address
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct EmailAddress {
+    pub name: Option<String>,
+    pub email: String,
+}
+
+/// A named group of addresses, ungrouped addresses have no name
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct EmailAddressGroup {
+    pub name: Option<String>,
+    pub addresses: Vec<EmailAddress>,
+}
+
+/// A header field parsed in one of the [`HeaderForm`]s, `All` holds the
+/// values of every instance of the field for the `:all` properties.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde_support", serde(untagged))]
+pub enum HeaderProperty {
+    Null,
+    Text(String),
+    TextList(Vec<String>),
+    Addresses(Vec<EmailAddress>),
+    GroupedAddresses(Vec<EmailAddressGroup>),
+    All(Vec<HeaderProperty>),
+}
+
+/// A node of the MIME structure. Multipart nodes have no `partId` or
+/// `blobId`, nested messages are not expanded.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde_support", serde(rename_all = "camelCase"))]
+pub struct EmailBodyPart {
+    pub part_id: Option<String>,
+    pub blob_id: Option<String>,
+    pub size: usize,
+    pub headers: Vec<EmailHeader>,
+    pub name: Option<String>,
+    #[cfg_attr(feature = "serde_support", serde(rename = "type"))]
+    pub content_type: String,
+    pub charset: Option<String>,
+    pub disposition: Option<String>,
+    pub cid: Option<String>,
+    pub language: Option<Vec<String>>,
+    pub location: Option<String>,
+    pub sub_parts: Option<Vec<EmailBodyPart>>,
+}
+
+/// The decoded contents of a text part
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde_support", serde(rename_all = "camelCase"))]
+pub struct EmailBodyValue {
+    pub value: String,
+    pub is_encoding_problem: bool,
+    pub is_truncated: bool,
+}
+
+/// Converts a message into a JMAP Email. Part ids are the positions of the
+/// parts in [`Message::parts`] and the blob ids of leaf parts are
+/// obtained from `blob_id`. Unknown `header:` properties are ignored.
+pub fn to_email(
+    message: &Message,
+    options: &EmailOptions,
+    blob_id: impl Fn(MessagePartId) -> Option<String>,
+) -> Email {
+    let addresses = |name: &str| match header_property(message, name, HeaderForm::Addresses) {
+        HeaderProperty::Addresses(addresses) => Some(addresses),
+        _ => None,
+    };
+    let text_list = |name: &str| match header_property(message, name, HeaderForm::MessageIds) {
+        HeaderProperty::TextList(ids) => Some(ids),
+        _ => None,
+    };
+    let text = |name: &str, form| match header_property(message, name, form) {
+        HeaderProperty::Text(text) => Some(text),
+        _ => None,
+    };
+    let body_parts = |part_ids: &[MessagePartId]| {
+        part_ids
+            .iter()
+            .filter_map(|&part_id| body_part(message, part_id, &blob_id, false))
+            .collect::<Vec<_>>()
+    };
+
+    let mut body_values = BTreeMap::new();
+    for (part_id, part) in message.parts.iter().enumerate() {
+        if !(options.fetch_all_body_values
+            && matches!(part.body, PartType::Text(_) | PartType::Html(_))
+            || options.fetch_text_body_values && message.text_body.contains(&part_id)
+            || options.fetch_html_body_values && message.html_body.contains(&part_id))
+        {
+            continue;
+        }
+        if let PartType::Text(contents) | PartType::Html(contents) = &part.body {
+            let (value, is_truncated) = if options.max_body_value_bytes > 0
+                && contents.len() > options.max_body_value_bytes
+            {
+                let value = if part.is_text_html() {
+                    truncate_html(contents.as_ref().into(), options.max_body_value_bytes)
+                } else {
+                    truncate_text(contents.as_ref().into(), options.max_body_value_bytes)
+                };
+                (value.into_owned(), true)
+            } else {
+                (contents.to_string(), false)
+            };
+            body_values.insert(
+                part_id.to_string(),
+                EmailBodyValue {
+                    value,
+                    is_encoding_problem: part.is_encoding_problem,
+                    is_truncated,
+                },
+            );
+        }
+    }
+
+    Email {
+        size: message.raw_message.len(),
+        headers: message
+            .parts
+            .first()
+            .map(|part| email_headers(message, part))
+            .unwrap_or_default(),
+        message_id: text_list("Message-ID"),
+        in_reply_to: text_list("In-Reply-To"),
+        references: text_list("References"),
+        sender: addresses("Sender"),
+        from: addresses("From"),
+        to: addresses("To"),
+        cc: addresses("Cc"),
+        bcc: addresses("Bcc"),
+        reply_to: addresses("Reply-To"),
+        subject: text("Subject", HeaderForm::Text),
+        sent_at: text("Date", HeaderForm::Date),
+        body_structure: body_part(message, 0, &blob_id, true).unwrap_or_default(),
+        body_values,
+        text_body: body_parts(&message.text_body),
+        html_body: body_parts(&message.html_body),
+        attachments: body_parts(&message.attachments),
+        has_attachment: !message.attachments.is_empty(),
+        preview: match message
+            .text_body
+            .first()
+            .and_then(|&part_id| message.parts.get(part_id))
+            .map(|part| &part.body)
+        {
+            Some(PartType::Text(text)) => preview_text(text.as_ref().into(), 256).into_owned(),
+            Some(PartType::Html(html)) => preview_html(html.as_ref().into(), 256).into_owned(),
+            _ => String::new(),
+        },
+        header_properties: options
+            .header_properties
+            .iter()
+            .filter_map(|property| {
+                let mut parts = property.split(':');
+                if !parts.next()?.eq_ignore_ascii_case("header") {
+                    return None;
+                }
+                let name = parts.next().filter(|name| !name.is_empty())?;
+                let mut form = HeaderForm::Raw;
+                let mut is_all = false;
+                for modifier in parts {
+                    if modifier.eq_ignore_ascii_case("all") && !is_all {
+                        is_all = true;
+                    } else if form == HeaderForm::Raw && !is_all {
+                        form = HeaderForm::parse(modifier)?;
+                    } else {
+                        return None;
+                    }
+                }
+
+                Some((
+                    property.to_string(),
+                    if is_all {
+                        HeaderProperty::All(
+                            header_instances(message, name)
+                                .map(|header| parse_header(message, header, form))
+                                .collect(),
+                        )
+                    } else {
+                        header_property(message, name, form)
+                    },
+                ))
+            })
+            .collect(),
+    }
+}
+
+// The last instance of a header field parsed in the requested form
+fn header_property(message: &Message, name: &str, form: HeaderForm) -> HeaderProperty {
+    header_instances(message, name)
+        .last()
+        .map_or(HeaderProperty::Null, |header| {
+            parse_header(message, header, form)
+        })
+}
+
+fn header_instances<'y>(
+    message: &'y Message,
+    name: &'y str,
+) -> impl Iterator<Item = &'y Header<'y>> + 'y {
+    message
+        .parts
+        .first()
+        .into_iter()
+        .flat_map(|part| part.headers.iter())
+        .filter(move |header| header.name.as_str().eq_ignore_ascii_case(name))
+}
+
+// Headers are parsed again from the raw message in the requested form
+fn parse_header(message: &Message, header: &Header, form: HeaderForm) -> HeaderProperty {
+    let value = message.parse_header_as(header, form);
+
+    match form {
+        HeaderForm::Raw | HeaderForm::Text => HeaderProperty::Text(match value {
+            HeaderValue::Text(text) => text.into_owned(),
+            _ => String::new(),
+        }),
+        HeaderForm::Addresses => HeaderProperty::Addresses(
+            address_groups(value)
+                .into_iter()
+                .flat_map(|group| group.addresses)
+                .collect(),
+        ),
+        HeaderForm::GroupedAddresses => HeaderProperty::GroupedAddresses(address_groups(value)),
+        HeaderForm::MessageIds | HeaderForm::URLs => match value {
+            HeaderValue::Text(text) => HeaderProperty::TextList(vec![text.into_owned()]),
+            HeaderValue::TextList(texts) => {
+                HeaderProperty::TextList(texts.into_iter().map(|text| text.into_owned()).collect())
+            }
+            _ => HeaderProperty::Null,
+        },
+        HeaderForm::Date => match value {
+            HeaderValue::DateTime(date) if date.is_valid() => {
+                HeaderProperty::Text(date.to_rfc3339())
+            }
+            _ => HeaderProperty::Null,
+        },
+    }
+}
+
+fn address_groups(value: HeaderValue) -> Vec<EmailAddressGroup> {
+    let ungrouped = |addresses: Vec<Addr>| EmailAddressGroup {
+        name: None,
+        addresses: addresses.into_iter().map(email_address).collect(),
+    };
+
+    match value {
+        HeaderValue::Address(address) => vec![ungrouped(vec![address])],
+        HeaderValue::AddressList(addresses) => vec![ungrouped(addresses)],
+        HeaderValue::Group(group) => vec![EmailAddressGroup {
+            name: group.name.map(|name| name.into_owned()),
+            addresses: group.addresses.into_iter().map(email_address).collect(),
+        }],
+        HeaderValue::GroupList(groups) => groups
+            .into_iter()
+            .map(|group| EmailAddressGroup {
+                name: group.name.map(|name| name.into_owned()),
+                addresses: group.addresses.into_iter().map(email_address).collect(),
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn email_address(address: Addr) -> EmailAddress {
+    EmailAddress {
+        name: address.name.map(|name| name.into_owned()),
+        email: address
+            .address
+            .map(|address| address.into_owned())
+            .unwrap_or_default(),
+    }
+}
+
+fn email_headers(message: &Message, part: &MessagePart) -> Vec<EmailHeader> {
+    part.headers
+        .iter()
+        .map(|header| EmailHeader {
+            name: header.name.as_str().to_string(),
+            value: match message.parse_header_as(header, HeaderForm::Raw) {
+                HeaderValue::Text(value) => value.into_owned(),
+                _ => String::new(),
+            },
+        })
+        .collect()
+}
+
+fn body_part(
+    message: &Message,
+    part_id: MessagePartId,
+    blob_id: &impl Fn(MessagePartId) -> Option<String>,
+    is_recursive: bool,
+) -> Option<EmailBodyPart> {
+    let part = message.parts.get(part_id)?;
+    let content_type = part.get_content_type();
+    let (c_type, c_subtype) = match content_type {
+        Some(content_type) => (
+            content_type.get_type(),
+            content_type.get_subtype().unwrap_or_default(),
+        ),
+        // Parts of a multipart/digest default to message/rfc822
+        None if part.is_message() => ("message", "rfc822"),
+        None => ("text", "plain"),
+    };
+    let sub_parts = match &part.body {
+        PartType::Multipart(sub_part_ids) => Some(if is_recursive {
+            sub_part_ids
+                .iter()
+                .filter_map(|&sub_part_id| body_part(message, sub_part_id, blob_id, true))
+                .collect()
+        } else {
+            Vec::new()
+        }),
+        _ => None,
+    };
+
+    Some(EmailBodyPart {
+        part_id: sub_parts.is_none().then(|| part_id.to_string()),
+        blob_id: sub_parts.is_none().then(|| blob_id(part_id)).flatten(),
+        size: part.len(),
+        headers: email_headers(message, part),
+        name: part.get_attachment_name().map(|name| name.to_string()),
+        content_type: if !c_subtype.is_empty() {
+            format!("{}/{}", c_type, c_subtype)
+        } else {
+            c_type.to_string()
+        },
+        charset: content_type
+            .and_then(|content_type| content_type.get_attribute("charset"))
+            .map(|charset| charset.to_string())
+            .or_else(|| (c_type == "text").then(|| "us-ascii".to_string())),
+        disposition: part
+            .get_content_disposition()
+            .map(|disposition| disposition.get_type().to_string()),
+        cid: part.get_content_id().map(|cid| cid.to_string()),
+        language: match part.get_content_language() {
+            HeaderValue::Text(language) => Some(vec![language.to_string()]),
+            HeaderValue::TextList(languages) => Some(
+                languages
+                    .iter()
+                    .map(|language| language.to_string())
+                    .collect(),
+            ),
+            _ => None,
+        },
+        location: part
+            .get_content_location()
+            .map(|location| location.to_string()),
+        sub_parts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Message;
+
+    use super::{to_email, EmailAddress, EmailAddressGroup, EmailOptions, HeaderProperty};
+
+    #[test]
+    fn jmap_email() {
+        let message = Message::parse(
+            concat!(
+                "From: =?utf-8?q?J=C3=BCrgen?= <jurgen@example.com>\r\n",
+                "To: Friends: jane@example.com, Bob <bob@example.org>;, ann@example.net\r\n",
+                "Subject:  Quarterly\r\n report \r\n",
+                "Date: Mon, 15 Jan 2018 23:30:00 -0800\r\n",
+                "Message-ID: <a@example.com>\r\n",
+                "References: <b@example.com> <c@example.com>\r\n",
+                "List-Post: <mailto:list@example.com>, <https://example.com/post>\r\n",
+                "X-Tenant: =?utf-8?q?M=C3=BCnchen?=\r\n",
+                "X-Tenant: Berlin\r\n",
+                "Content-Type: multipart/mixed; boundary=\"m\"\r\n\r\n",
+                "--m\r\n",
+                "Content-Type: multipart/alternative; boundary=\"a\"\r\n\r\n",
+                "--a\r\n",
+                "Content-Type: text/plain; charset=utf-8\r\n",
+                "Content-Transfer-Encoding: quoted-printable\r\n\r\n",
+                "Die Zahlen f=C3=BCr das Quartal.\r\n",
+                "--a\r\n",
+                "Content-Type: text/html\r\n",
+                "Content-Language: en, de\r\n\r\n",
+                "<p>The numbers for the quarter.</p>\r\n",
+                "--a--\r\n",
+                "--m\r\n",
+                "Content-Type: application/pdf\r\n",
+                "Content-Disposition: attachment; filename=\"report.pdf\"\r\n",
+                "Content-ID: <report@example.com>\r\n",
+                "Content-Transfer-Encoding: base64\r\n\r\n",
+                "SGVsbG8=\r\n",
+                "--m--\r\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let email = to_email(
+            &message,
+            &EmailOptions {
+                header_properties: &[
+                    "header:X-Tenant",
+                    "header:x-tenant:asText:all",
+                    "header:To:asGroupedAddresses",
+                    "header:List-Post:asURLs",
+                    "header:Date:asDate",
+                    "header:X-Missing:asText",
+                    "header:X-Missing:all",
+                    "header:X-Tenant:asFoo",
+                ],
+                fetch_text_body_values: true,
+                max_body_value_bytes: 20,
+                ..Default::default()
+            },
+            |part_id| Some(format!("blob-{}", part_id)),
+        );
+
+        assert_eq!(email.size, message.raw_message.len());
+        assert_eq!(email.subject.as_deref(), Some("Quarterly report"));
+        assert_eq!(email.sent_at.as_deref(), Some("2018-01-15T23:30:00-08:00"));
+        assert_eq!(email.message_id, Some(vec!["a@example.com".to_string()]));
+        assert_eq!(
+            email.references,
+            Some(vec![
+                "b@example.com".to_string(),
+                "c@example.com".to_string()
+            ])
+        );
+        assert_eq!(email.in_reply_to, None);
+        assert_eq!(
+            email.from,
+            Some(vec![EmailAddress {
+                name: Some("Jürgen".to_string()),
+                email: "jurgen@example.com".to_string()
+            }])
+        );
+        assert_eq!(email.to.as_ref().map(|to| to.len()), Some(3));
+        assert_eq!(email.sender, None);
+        assert_eq!(email.headers.len(), 10);
+        assert_eq!(email.headers[2].name, "Subject");
+        assert_eq!(email.headers[2].value, "  Quarterly\r\n report ");
+
+        assert_eq!(
+            email.header_properties.keys().collect::<Vec<_>>(),
+            [
+                "header:Date:asDate",
+                "header:List-Post:asURLs",
+                "header:To:asGroupedAddresses",
+                "header:X-Missing:all",
+                "header:X-Missing:asText",
+                "header:X-Tenant",
+                "header:x-tenant:asText:all",
+            ]
+        );
+        for (property, expected) in [
+            (
+                "header:X-Tenant",
+                HeaderProperty::Text(" Berlin".to_string()),
+            ),
+            (
+                "header:x-tenant:asText:all",
+                HeaderProperty::All(vec![
+                    HeaderProperty::Text("München".to_string()),
+                    HeaderProperty::Text("Berlin".to_string()),
+                ]),
+            ),
+            (
+                "header:To:asGroupedAddresses",
+                HeaderProperty::GroupedAddresses(vec![
+                    EmailAddressGroup {
+                        name: Some("Friends".to_string()),
+                        addresses: vec![
+                            EmailAddress {
+                                name: None,
+                                email: "jane@example.com".to_string(),
+                            },
+                            EmailAddress {
+                                name: Some("Bob".to_string()),
+                                email: "bob@example.org".to_string(),
+                            },
+                        ],
+                    },
+                    EmailAddressGroup {
+                        name: None,
+                        addresses: vec![EmailAddress {
+                            name: None,
+                            email: "ann@example.net".to_string(),
+                        }],
+                    },
+                ]),
+            ),
+            (
+                "header:List-Post:asURLs",
+                HeaderProperty::TextList(vec![
+                    "mailto:list@example.com".to_string(),
+                    "https://example.com/post".to_string(),
+                ]),
+            ),
+            (
+                "header:Date:asDate",
+                HeaderProperty::Text("2018-01-15T23:30:00-08:00".to_string()),
+            ),
+            ("header:X-Missing:asText", HeaderProperty::Null),
+            ("header:X-Missing:all", HeaderProperty::All(vec![])),
+        ] {
+            assert_eq!(email.header_properties[property], expected, "{}", property);
+        }
+
+        // Body structure
+        let structure = &email.body_structure;
+        assert_eq!(structure.content_type, "multipart/mixed");
+        assert_eq!(structure.part_id, None);
+        let sub_parts = structure.sub_parts.as_ref().unwrap();
+        assert_eq!(sub_parts.len(), 2);
+        assert_eq!(sub_parts[0].sub_parts.as_ref().unwrap().len(), 2);
+        let html_part = &sub_parts[0].sub_parts.as_ref().unwrap()[1];
+        assert_eq!(html_part.part_id.as_deref(), Some("3"));
+        assert_eq!(html_part.blob_id.as_deref(), Some("blob-3"));
+        assert_eq!(html_part.charset.as_deref(), Some("us-ascii"));
+        assert_eq!(
+            html_part.language,
+            Some(vec!["en".to_string(), "de".to_string()])
+        );
+        let attachment = &sub_parts[1];
+        assert_eq!(attachment.content_type, "application/pdf");
+        assert_eq!(attachment.name.as_deref(), Some("report.pdf"));
+        assert_eq!(attachment.disposition.as_deref(), Some("attachment"));
+        assert_eq!(attachment.cid.as_deref(), Some("report@example.com"));
+        assert_eq!(attachment.size, 5);
+        assert_eq!(attachment.charset, None);
+
+        assert_eq!(email.text_body.len(), 1);
+        assert_eq!(email.text_body[0].part_id.as_deref(), Some("2"));
+        assert_eq!(email.html_body[0].part_id.as_deref(), Some("3"));
+        assert_eq!(email.attachments, vec![attachment.clone()]);
+        assert!(email.has_attachment);
+
+        // Body values
+        assert_eq!(email.body_values.keys().collect::<Vec<_>>(), ["2"]);
+        let body_value = &email.body_values["2"];
+        assert!(body_value.is_truncated);
+        assert!(!body_value.is_encoding_problem);
+        assert!(body_value.value.len() <= 20);
+        assert!(body_value.value.starts_with("Die Zahlen f"));
+        assert_eq!(email.preview, "Die Zahlen für das Quartal.");
+
+        // Header offsets of attached messages refer to the top-level message
+        let message = Message::parse(
+            concat!(
+                "Subject: Fwd: Lunch\r\n",
+                "Content-Type: multipart/mixed; boundary=\"m\"\r\n\r\n",
+                "--m\r\n",
+                "Content-Type: text/plain\r\n\r\n",
+                "See below.\r\n",
+                "--m\r\n",
+                "Content-Type: message/rfc822\r\n\r\n",
+                "From: Jane <jane@example.com>\r\n",
+                "Subject: Lunch\r\n",
+                "X-Tenant: Berlin\r\n\r\n",
+                "Shall we?\r\n",
+                "--m--\r\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let nested = message.get_attachment(0).unwrap().get_message().unwrap();
+        let email = to_email(
+            &nested,
+            &EmailOptions {
+                header_properties: &["header:X-Tenant:asText"],
+                ..Default::default()
+            },
+            |_| None,
+        );
+        assert_eq!(email.subject.as_deref(), Some("Lunch"));
+        assert_eq!(
+            email.from,
+            Some(vec![EmailAddress {
+                name: Some("Jane".to_string()),
+                email: "jane@example.com".to_string()
+            }])
+        );
+        assert_eq!(email.headers[1].value, " Lunch");
+        assert_eq!(
+            email.header_properties["header:X-Tenant:asText"],
+            HeaderProperty::Text("Berlin".to_string())
+        );
+    }
+}
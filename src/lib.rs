@@ -250,17 +250,22 @@
 //!```
 #[forbid(unsafe_code)]
 pub mod decoders;
+pub mod imap;
+pub mod jmap;
 pub mod mailbox;
 pub mod parsers;
+pub mod threading;
 
 use std::{
     borrow::Cow,
+    collections::HashMap,
     fmt::{self, Display},
 };
 
 use decoders::html::{html_to_text, text_to_html};
 use parsers::{
     fields::thread::thread_name,
+    header::{parse_header_form, parse_header_name, HeaderParserResult},
     preview::{preview_html, preview_text},
 };
 #[cfg(feature = "serde_support")]
@@ -310,6 +315,7 @@ pub enum Encoding {
     None = 0,
     QuotedPrintable = 1,
     Base64 = 2,
+    UuEncode = 3,
 }
 
 impl From<u8> for Encoding {
@@ -317,6 +323,7 @@ impl From<u8> for Encoding {
         match v {
             1 => Encoding::QuotedPrintable,
             2 => Encoding::Base64,
+            3 => Encoding::UuEncode,
             _ => Encoding::None,
         }
     }
@@ -456,6 +463,18 @@ impl<'x> Header<'x> {
     pub fn offset_end(&self) -> usize {
         self.offset_end
     }
+
+    /// Parses the header value again from the raw message in the requested
+    /// form. The offsets of headers of nested messages refer to the
+    /// top-level message.
+    pub fn parse_as<'y>(&self, raw_message: &'y [u8], form: HeaderForm) -> HeaderValue<'y> {
+        parse_header_form(
+            raw_message
+                .get(self.offset_start..self.offset_end)
+                .unwrap_or_default(),
+            form,
+        )
+    }
 }
 
 #[derive(Debug, Hash, Clone)]
@@ -654,6 +673,150 @@ impl<'x> Default for HeaderValue<'x> {
     }
 }
 
+/// A form a header value can be parsed as, regardless of the parser that
+/// is used for the header by default
+/// ([RFC 8621](https://datatracker.ietf.org/doc/html/rfc8621#section-4.1.2)).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub enum HeaderForm {
+    /// The raw value, without the terminating line break
+    Raw,
+    /// Unstructured text with RFC 2047 encoded words decoded
+    Text,
+    /// Addresses, with group members merged into a single list
+    Addresses,
+    /// Addresses grouped by their group, ungrouped addresses are returned
+    /// in groups without name
+    GroupedAddresses,
+    /// Message ids without angle brackets
+    MessageIds,
+    /// An RFC 5322 date
+    Date,
+    /// The URLs of an RFC 2369 list header
+    URLs,
+}
+
+impl HeaderForm {
+    /// Parses a JMAP form name such as `asGroupedAddresses`.
+    pub fn parse(value: &str) -> Option<HeaderForm> {
+        const FORMS: &[(&str, HeaderForm)] = &[
+            ("asRaw", HeaderForm::Raw),
+            ("asText", HeaderForm::Text),
+            ("asAddresses", HeaderForm::Addresses),
+            ("asGroupedAddresses", HeaderForm::GroupedAddresses),
+            ("asMessageIds", HeaderForm::MessageIds),
+            ("asDate", HeaderForm::Date),
+            ("asURLs", HeaderForm::URLs),
+        ];
+        FORMS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(value))
+            .map(|(_, form)| *form)
+    }
+}
+
+/// Parser used for header fields registered with a [`MessageParser`].
+#[derive(Debug, Clone, Copy)]
+pub enum HeaderParser {
+    /// Unstructured text, returned as `HeaderValue::Text`
+    Text,
+    /// Comma-separated list, returned as `HeaderValue::TextList`
+    TextList,
+    /// One or more addresses or groups
+    Address,
+    /// One or more message ids, without angle brackets
+    Id,
+    /// An RFC 5322 date, returned as `HeaderValue::DateTime`
+    Date,
+    /// A value with attributes, such as Content-Type
+    ContentType,
+    /// The raw value with folding removed
+    Raw,
+    /// A user-supplied function, which receives the raw value following
+    /// the colon, including the terminating line break
+    Custom(for<'x> fn(&'x [u8]) -> HeaderValue<'x>),
+}
+
+/// A reusable message parser configuration.
+///
+/// Header fields not known to the parser are returned as raw text, unless a
+/// parser has been registered for them:
+///
+/// ```
+/// use mail_parser::{HeaderParser, HeaderValue, MessageParser};
+///
+/// let parser = MessageParser::new().with_header_parser("X-Spam-Score", HeaderParser::Text);
+/// let message = parser
+///     .parse(b"X-Spam-Score: =?utf-8?q?3=2E5?=\r\n\r\nHi!\r\n")
+///     .unwrap();
+/// assert_eq!(
+///     message.get_header("x-spam-score"),
+///     Some(&HeaderValue::Text("3.5".into()))
+/// );
+/// ```
+///
+/// RFC headers known to this library, such as `Subject` or `List-Id`, are
+/// always parsed by the library and registering them has no effect. To read
+/// them in a different form use [`Message::get_header_as`].
+#[derive(Debug, Clone, Default)]
+pub struct MessageParser {
+    // Parsers are grouped by name length and lowercase first byte, which
+    // finds them without allocating or hashing the whole name.
+    header_parsers: HashMap<(usize, u8), Vec<(String, HeaderParser)>>,
+}
+
+impl MessageParser {
+    /// Creates a parser configuration without custom header parsers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a parser for a header field, names are case-insensitive.
+    /// Registrations for RFC headers known to this library are ignored.
+    pub fn with_header_parser(mut self, name: impl Into<String>, parser: HeaderParser) -> Self {
+        let name = name.into();
+        if matches!(
+            parse_header_name(name.as_bytes()).1,
+            HeaderParserResult::Rfc(_)
+        ) {
+            return self;
+        }
+
+        let parsers = self
+            .header_parsers
+            .entry(header_parser_key(&name))
+            .or_default();
+        match parsers
+            .iter_mut()
+            .find(|(current, _)| current.eq_ignore_ascii_case(&name))
+        {
+            Some((_, current)) => *current = parser,
+            None => parsers.push((name, parser)),
+        }
+        self
+    }
+
+    pub(crate) fn get_header_parser(&self, name: &str) -> Option<HeaderParser> {
+        if self.header_parsers.is_empty() {
+            return None;
+        }
+        self.header_parsers
+            .get(&header_parser_key(name))?
+            .iter()
+            .find(|(current, _)| current.eq_ignore_ascii_case(name))
+            .map(|(_, parser)| *parser)
+    }
+}
+
+fn header_parser_key(name: &str) -> (usize, u8) {
+    (
+        name.len(),
+        name.as_bytes()
+            .first()
+            .map_or(0, |ch| ch.to_ascii_lowercase()),
+    )
+}
+
 impl<'x> HeaderValue<'x> {
     pub fn is_empty(&self) -> bool {
         *self == HeaderValue::Empty
@@ -778,6 +941,71 @@ pub struct DateTime {
     pub tz_minute: u8,
 }
 
+/// An RFC2046 `message/external-body` reference to data not included in the message.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct ExternalBody<'x> {
+    /// How the referenced data can be retrieved.
+    pub access_type: AccessType<'x>,
+    /// Date after which the referenced data may no longer exist.
+    #[cfg_attr(feature = "serde_support", serde(default))]
+    pub expiration: Option<DateTime>,
+    /// Size in octets of the referenced data.
+    #[cfg_attr(feature = "serde_support", serde(default))]
+    pub size: Option<usize>,
+    /// Whether the referenced data may be overwritten (`read` or `read-write`).
+    #[cfg_attr(feature = "serde_support", serde(default))]
+    pub permission: Option<Cow<'x, str>>,
+    /// Headers of the referenced body.
+    pub headers: Vec<Header<'x>>,
+    /// Contents of the phantom body, used by the `mail-server` access type
+    /// to specify the commands to send to the server.
+    pub body: Cow<'x, str>,
+}
+
+/// The access mechanism of a `message/external-body` part.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub enum AccessType<'x> {
+    /// File Transfer Protocol.
+    Ftp {
+        name: Cow<'x, str>,
+        site: Cow<'x, str>,
+        directory: Option<Cow<'x, str>>,
+        mode: Option<Cow<'x, str>>,
+    },
+    /// Anonymous FTP.
+    AnonFtp {
+        name: Cow<'x, str>,
+        site: Cow<'x, str>,
+        directory: Option<Cow<'x, str>>,
+        mode: Option<Cow<'x, str>>,
+    },
+    /// Trivial File Transfer Protocol.
+    Tftp {
+        name: Cow<'x, str>,
+        site: Cow<'x, str>,
+        mode: Option<Cow<'x, str>>,
+    },
+    /// A file accessible from the local file system.
+    LocalFile {
+        name: Cow<'x, str>,
+        site: Option<Cow<'x, str>>,
+    },
+    /// Data retrievable by sending the phantom body to a mail server.
+    MailServer {
+        server: Cow<'x, str>,
+        subject: Option<Cow<'x, str>>,
+    },
+    /// An RFC2017 URL.
+    Url(Cow<'x, str>),
+    /// An RFC1873 reference to a body part with the Content-ID of the
+    /// phantom headers.
+    ContentId,
+    /// Unknown access type or missing mandatory parameters.
+    Other(Cow<'x, str>),
+}
+
 impl<'x> Message<'x> {
     /// Returns the root message part
     pub fn get_root_part(&self) -> &MessagePart<'x> {
@@ -815,6 +1043,38 @@ impl<'x> Message<'x> {
             .and_then(|h| std::str::from_utf8(&self.raw_message[h.offset_start..h.offset_end]).ok())
     }
 
+    /// Returns the last instance of a header parsed in the requested form.
+    pub fn get_header_as(&self, header: &str, form: HeaderForm) -> Option<HeaderValue<'_>> {
+        self.parts[0]
+            .headers
+            .get_header(header)
+            .map(|h| self.parse_header_as(h, form))
+    }
+
+    /// Returns all instances of a header parsed in the requested form.
+    pub fn get_all_headers_as(&self, header: &str, form: HeaderForm) -> Vec<HeaderValue<'_>> {
+        self.parts[0]
+            .headers
+            .iter()
+            .filter(|h| h.name.as_str().eq_ignore_ascii_case(header))
+            .map(|h| self.parse_header_as(h, form))
+            .collect()
+    }
+
+    // Header offsets of nested messages are relative to the top-level message
+    pub(crate) fn parse_header_as(&self, header: &Header, form: HeaderForm) -> HeaderValue<'_> {
+        let base = self.parts[0].offset_header;
+        parse_header_form(
+            self.raw_message
+                .get(
+                    header.offset_start.saturating_sub(base)
+                        ..header.offset_end.saturating_sub(base),
+                )
+                .unwrap_or_default(),
+            form,
+        )
+    }
+
     /// Returns an iterator over the RFC headers of this message.
     pub fn get_headers(&self) -> &[Header] {
         &self.parts[0].headers
@@ -1432,6 +1692,56 @@ impl<'x> MimeHeaders<'x> for MessagePart<'x> {
     }
 }
 
+impl<'x> MimeHeaders<'x> for ExternalBody<'x> {
+    fn get_content_description(&self) -> Option<&str> {
+        mime_text(&self.headers, RfcHeader::ContentDescription)
+    }
+
+    fn get_content_disposition(&self) -> Option<&ContentType<'_>> {
+        mime_content_type(&self.headers, RfcHeader::ContentDisposition)
+    }
+
+    fn get_content_id(&self) -> Option<&str> {
+        mime_text(&self.headers, RfcHeader::ContentId)
+    }
+
+    fn get_content_transfer_encoding(&self) -> Option<&str> {
+        mime_text(&self.headers, RfcHeader::ContentTransferEncoding)
+    }
+
+    fn get_content_type(&self) -> Option<&ContentType<'_>> {
+        mime_content_type(&self.headers, RfcHeader::ContentType)
+    }
+
+    fn get_content_language(&self) -> &HeaderValue<'_> {
+        rfc_header(&self.headers, RfcHeader::ContentLanguage).unwrap_or(&HeaderValue::Empty)
+    }
+
+    fn get_content_location(&self) -> Option<&str> {
+        mime_text(&self.headers, RfcHeader::ContentLocation)
+    }
+}
+
+// Returns the last instance of an RFC header in a header list
+fn rfc_header<'x, 'y>(headers: &'y [Header<'x>], name: RfcHeader) -> Option<&'y HeaderValue<'x>> {
+    headers
+        .iter()
+        .rev()
+        .find(|header| matches!(&header.name, HeaderName::Rfc(rfc_name) if *rfc_name == name))
+        .map(|header| &header.value)
+}
+
+fn mime_text<'y>(headers: &'y [Header], name: RfcHeader) -> Option<&'y str> {
+    rfc_header(headers, name).and_then(|header| header.as_text_ref())
+}
+
+fn mime_content_type<'y>(
+    headers: &'y [Header<'y>],
+    name: RfcHeader,
+) -> Option<&'y ContentType<'y>> {
+    rfc_header(headers, name).and_then(|header| header.as_content_type_ref())
+}
+
 pub trait GetHeader {
     fn get_rfc(&self, name: &RfcHeader) -> Option<&HeaderValue>;
     fn get_header(&self, name: &str) -> Option<&Header>;
@@ -0,0 +1,237 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::io::{BufRead, BufReader, Read};
+
+use super::{
+    mbox::{Message, ParseError},
+    Flag,
+};
+
+/// Parses a Babyl (Emacs RMAIL) mailbox from a Read stream, messages are
+/// preceded by `^_^L` separator lines.
+pub struct MessageIterator<T: Read> {
+    reader: BufReader<T>,
+    message: Option<Vec<u8>>,
+    pending_form_feed: bool,
+}
+
+impl<T> MessageIterator<T>
+where
+    T: Read,
+{
+    /// Creates a new Babyl message iterator
+    pub fn new(reader: T) -> MessageIterator<T> {
+        MessageIterator {
+            reader: BufReader::new(reader),
+            message: None,
+            pending_form_feed: false,
+        }
+    }
+}
+
+impl<T> Iterator for MessageIterator<T>
+where
+    T: Read,
+{
+    type Item = Result<Message, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = Vec::with_capacity(80);
+
+        loop {
+            line.clear();
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(_) => return Some(Err(ParseError {})),
+            }
+
+            if line[0] == 0x1f {
+                // End of the file header or of a message
+                let message = self.message.take();
+                if line.get(1) == Some(&0x0c) {
+                    self.message = Vec::with_capacity(1024).into();
+                    self.pending_form_feed = false;
+                } else {
+                    self.pending_form_feed = true;
+                }
+                if let Some(message) = message {
+                    return Some(Ok(build_message(&message)));
+                }
+            } else if self.pending_form_feed {
+                if line[0] == 0x0c {
+                    self.message = Vec::with_capacity(1024).into();
+                }
+                self.pending_form_feed = false;
+            } else if let Some(message) = &mut self.message {
+                message.extend_from_slice(&line);
+            }
+        }
+
+        self.message
+            .take()
+            .filter(|message| !message.is_empty())
+            .map(|message| Ok(build_message(&message)))
+    }
+}
+
+fn build_message(raw_message: &[u8]) -> Message {
+    let mut lines = raw_message.split_inclusive(|&ch| ch == b'\n');
+
+    // Attributes line: "<reformatted>, attr1, attr2,, label1, label2,"
+    let attributes = lines
+        .next()
+        .map(String::from_utf8_lossy)
+        .unwrap_or_default();
+    let (attributes, labels) = attributes
+        .split_once(",,")
+        .unwrap_or((attributes.as_ref(), ""));
+    let mut attributes = attributes.split(',').map(|attr| attr.trim());
+    let is_reformatted = attributes.next() == Some("1");
+
+    let mut flags = vec![Flag::Seen];
+    for attribute in attributes {
+        let flag = match attribute {
+            "unseen" => {
+                flags.retain(|flag| flag != &Flag::Seen);
+                continue;
+            }
+            "deleted" => Flag::Trashed,
+            "answered" => Flag::Replied,
+            "forwarded" | "resent" => Flag::Passed,
+            _ => continue,
+        };
+        if !flags.contains(&flag) {
+            flags.push(flag);
+        }
+    }
+    let keywords = labels
+        .split(',')
+        .map(|label| label.trim())
+        .filter(|label| !label.is_empty())
+        .map(|label| label.to_string())
+        .collect::<Vec<_>>();
+
+    // The original header is followed by the visible header after the
+    // "*** EOOH ***" line, which is the only header for messages that
+    // were not reformatted.
+    let mut original_header = Vec::new();
+    let mut contents = Vec::with_capacity(raw_message.len());
+    let mut from_line = String::new();
+    let mut found_eooh = false;
+    for line in lines {
+        let trimmed_line = line.trim_ascii_end();
+        if !found_eooh && trimmed_line == b"*** EOOH ***" {
+            found_eooh = true;
+            continue;
+        }
+        if from_line.is_empty()
+            && trimmed_line
+                .get(..10)
+                .is_some_and(|name| name.eq_ignore_ascii_case(b"Mail-from:"))
+        {
+            from_line = String::from_utf8_lossy(&trimmed_line[10..])
+                .trim()
+                .to_string();
+        }
+        if found_eooh {
+            contents.extend_from_slice(line);
+        } else if !trimmed_line
+            .get(..13)
+            .is_some_and(|name| name.eq_ignore_ascii_case(b"Summary-line:"))
+        {
+            original_header.extend_from_slice(line);
+        }
+    }
+
+    let original_header = original_header.trim_ascii_end();
+    if is_reformatted && !original_header.is_empty() {
+        let body_start = contents
+            .windows(2)
+            .position(|window| window == b"\n\n")
+            .map_or(contents.len(), |pos| pos + 2);
+        let mut message = Vec::with_capacity(original_header.len() + contents.len());
+        message.extend_from_slice(original_header);
+        message.extend_from_slice(b"\n\n");
+        message.extend_from_slice(&contents[body_start..]);
+        contents = message;
+    } else if !found_eooh {
+        contents = original_header.to_vec();
+    }
+
+    Message::from_parts(&from_line, contents, flags, keywords)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mailbox::Flag;
+
+    use super::MessageIterator;
+
+    #[test]
+    fn parse_babyl() {
+        let babyl = concat!(
+            "BABYL OPTIONS: -*- rmail -*-\n",
+            "Version: 5\n",
+            "Labels: work,personal\n",
+            "Note:   This is the header of an rmail file.\n",
+            "\x1f\x0c\n",
+            "0, answered,, work, personal,\n",
+            "*** EOOH ***\n",
+            "Mail-from: From alice@example.com Mon Jan 15 10:30:00 2018\n",
+            "From: alice@example.com\n",
+            "Subject: First\n",
+            "\n",
+            "First message.\n",
+            "\x1f\x0c\n",
+            "1, unseen, deleted,,\n",
+            "Summary-line: 15-Jan bob  Second\n",
+            "From: Bob <bob@example.com>\n",
+            "Subject: Second\n",
+            "X-Original: yes\n",
+            "\n",
+            "*** EOOH ***\n",
+            "From: bob@example.com\n",
+            "Subject: Second\n",
+            "\n",
+            "Second message.\n",
+            "\x1f",
+        );
+
+        let messages = MessageIterator::new(babyl.as_bytes())
+            .map(|message| message.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 2);
+
+        assert_eq!(messages[0].from(), "alice@example.com");
+        assert_eq!(messages[0].internal_date(), 1516012200);
+        assert_eq!(messages[0].flags(), &[Flag::Seen, Flag::Replied]);
+        assert_eq!(messages[0].keywords(), &["work", "personal"]);
+        assert!(messages[0]
+            .contents()
+            .ends_with(b"Subject: First\n\nFirst message.\n"));
+
+        assert_eq!(messages[1].flags(), &[Flag::Trashed]);
+        assert!(messages[1].keywords().is_empty());
+        assert_eq!(
+            messages[1].contents(),
+            concat!(
+                "From: Bob <bob@example.com>\n",
+                "Subject: Second\n",
+                "X-Original: yes\n",
+                "\n",
+                "Second message.\n",
+            )
+            .as_bytes()
+        );
+    }
+}
@@ -0,0 +1,426 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+};
+
+use super::maildir::split_info;
+
+const KEYWORDS_FILE: &str = "dovecot-keywords";
+const UIDLIST_FILE: &str = "dovecot-uidlist";
+const MAX_KEYWORDS: usize = 26;
+
+/// Keyword names assigned to the `a` to `z` info letters of a Dovecot Maildir
+/// folder, as stored in its `dovecot-keywords` file.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Keywords {
+    names: Vec<Option<String>>,
+}
+
+/// IMAP UIDs of the messages in a Dovecot Maildir folder, as stored in its
+/// `dovecot-uidlist` file.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct UidList {
+    uid_validity: u32,
+    uid_next: u32,
+    extensions: Vec<String>,
+    entries: Vec<UidEntry>,
+    uids: HashMap<String, usize>,
+}
+
+/// A message in a `dovecot-uidlist` file
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct UidEntry {
+    /// IMAP UID of the message
+    pub uid: u32,
+    /// Extension fields, such as `S<size>` or `W<virtual size>`
+    pub extensions: Vec<String>,
+    /// Unique name of the message file, without the info flags
+    pub name: String,
+}
+
+impl Keywords {
+    /// Creates an empty keyword list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the `dovecot-keywords` file of a folder. Returns an empty list
+    /// if the file does not exist.
+    pub fn load(folder_path: &Path) -> io::Result<Keywords> {
+        match fs::File::open(folder_path.join(KEYWORDS_FILE)) {
+            Ok(file) => Keywords::read_from(file),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Keywords::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes the `dovecot-keywords` file of a folder.
+    pub fn save(&self, folder_path: &Path) -> io::Result<()> {
+        write_file(&folder_path.join(KEYWORDS_FILE), |writer| {
+            self.write_to(writer)
+        })
+    }
+
+    /// Parses the contents of a `dovecot-keywords` file.
+    pub fn read_from(reader: impl Read) -> io::Result<Keywords> {
+        let mut keywords = Keywords::default();
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            if let Some((idx, name)) = line.split_once(' ') {
+                match idx.parse::<usize>() {
+                    Ok(idx) if idx < MAX_KEYWORDS && !name.is_empty() => {
+                        if keywords.names.len() <= idx {
+                            keywords.names.resize(idx + 1, None);
+                        }
+                        keywords.names[idx] = name.to_string().into();
+                    }
+                    _ => (),
+                }
+            }
+        }
+        Ok(keywords)
+    }
+
+    /// Writes the keywords in the `dovecot-keywords` format.
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        for (idx, name) in self.names.iter().enumerate() {
+            if let Some(name) = name {
+                writeln!(writer, "{} {}", idx, name)?;
+            }
+        }
+        writer.flush()
+    }
+
+    /// Returns the keyword assigned to an info letter.
+    pub fn get(&self, letter: char) -> Option<&str> {
+        if letter.is_ascii_lowercase() {
+            self.names.get((letter as u8 - b'a') as usize)?.as_deref()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the info letter assigned to a keyword.
+    pub fn get_letter(&self, name: &str) -> Option<char> {
+        self.names
+            .iter()
+            .position(|n| n.as_deref() == Some(name))
+            .map(|idx| (b'a' + idx as u8) as char)
+    }
+
+    /// Returns the info letter assigned to a keyword, assigning the first
+    /// free letter if needed. Returns `None` if all letters are in use.
+    pub fn get_or_insert(&mut self, name: &str) -> Option<char> {
+        if let Some(letter) = self.get_letter(name) {
+            return letter.into();
+        }
+        let idx = match self.names.iter().position(|n| n.is_none()) {
+            Some(idx) => idx,
+            None if self.names.len() < MAX_KEYWORDS => {
+                self.names.push(None);
+                self.names.len() - 1
+            }
+            None => return None,
+        };
+        self.names[idx] = name.to_string().into();
+        Some((b'a' + idx as u8) as char)
+    }
+
+    /// Returns the keywords and their info letters
+    pub fn iter(&self) -> impl Iterator<Item = (char, &str)> {
+        self.names.iter().enumerate().filter_map(|(idx, name)| {
+            name.as_deref()
+                .map(|name| ((b'a' + idx as u8) as char, name))
+        })
+    }
+
+    /// Returns `true` if there are no keywords
+    pub fn is_empty(&self) -> bool {
+        self.names.iter().all(|name| name.is_none())
+    }
+}
+
+impl UidList {
+    /// Creates an empty UID list.
+    pub fn new(uid_validity: u32) -> Self {
+        UidList {
+            uid_validity,
+            uid_next: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Reads the `dovecot-uidlist` file of a folder. Returns `None` if the
+    /// file does not exist.
+    pub fn load(folder_path: &Path) -> io::Result<Option<UidList>> {
+        match fs::File::open(folder_path.join(UIDLIST_FILE)) {
+            Ok(file) => UidList::read_from(file).map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes the `dovecot-uidlist` file of a folder. The file is written to
+    /// `dovecot-uidlist.lock` first, which fails if another process holds
+    /// the lock, and then renamed.
+    pub fn save(&self, folder_path: &Path) -> io::Result<()> {
+        write_file(&folder_path.join(UIDLIST_FILE), |writer| {
+            self.write_to(writer)
+        })
+    }
+
+    /// Parses the contents of a `dovecot-uidlist` file, versions 1 and 3
+    /// are supported.
+    pub fn read_from(reader: impl Read) -> io::Result<UidList> {
+        let invalid_data = || io::Error::new(io::ErrorKind::InvalidData, "Invalid uidlist.");
+        let mut lines = BufReader::new(reader).lines();
+        let header = lines.next().ok_or_else(invalid_data)??;
+        let mut fields = header.split_ascii_whitespace();
+        let mut uid_list = UidList::default();
+
+        let version = fields.next();
+        match version {
+            Some("1") => {
+                let mut next_number = || -> io::Result<u32> {
+                    fields
+                        .next()
+                        .and_then(|value| value.parse().ok())
+                        .ok_or_else(invalid_data)
+                };
+                uid_list.uid_validity = next_number()?;
+                uid_list.uid_next = next_number()?;
+            }
+            Some("3") => {
+                for field in fields {
+                    if let Some(value) = field.strip_prefix('V') {
+                        uid_list.uid_validity = value.parse().map_err(|_| invalid_data())?;
+                    } else if let Some(value) = field.strip_prefix('N') {
+                        uid_list.uid_next = value.parse().map_err(|_| invalid_data())?;
+                    } else {
+                        uid_list.extensions.push(field.to_string());
+                    }
+                }
+            }
+            _ => return Err(invalid_data()),
+        }
+
+        for line in lines {
+            let line = line?;
+            let (fields, name) = if version == Some("3") {
+                match line.split_once(" :") {
+                    Some((fields, name)) => (fields, name),
+                    None => continue,
+                }
+            } else {
+                // Version 1 entries are "<uid> <file name>"
+                match line.split_once(' ') {
+                    Some((uid, name)) => (uid, split_info(name).0),
+                    None => continue,
+                }
+            };
+            let mut fields = fields.split_ascii_whitespace();
+            if let Some(uid) = fields.next().and_then(|uid| uid.parse().ok()) {
+                uid_list.insert(UidEntry {
+                    uid,
+                    extensions: fields.map(|field| field.to_string()).collect(),
+                    name: name.to_string(),
+                });
+            }
+        }
+
+        Ok(uid_list)
+    }
+
+    /// Writes the list in the `dovecot-uidlist` version 3 format.
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        write!(writer, "3 V{} N{}", self.uid_validity, self.uid_next)?;
+        for extension in &self.extensions {
+            write!(writer, " {}", extension)?;
+        }
+        writeln!(writer)?;
+        for entry in &self.entries {
+            write!(writer, "{}", entry.uid)?;
+            for extension in &entry.extensions {
+                write!(writer, " {}", extension)?;
+            }
+            writeln!(writer, " :{}", entry.name)?;
+        }
+        writer.flush()
+    }
+
+    /// Returns the UID of a message by its unique name.
+    pub fn get_uid(&self, name: &str) -> Option<u32> {
+        self.uids.get(name).map(|&idx| self.entries[idx].uid)
+    }
+
+    /// Returns the UID of a message, assigning the next UID if the message
+    /// is not in the list.
+    pub fn get_or_assign(&mut self, name: &str) -> u32 {
+        if let Some(uid) = self.get_uid(name) {
+            return uid;
+        }
+        let uid = self.uid_next;
+        self.insert(UidEntry {
+            uid,
+            extensions: Vec::new(),
+            name: name.to_string(),
+        });
+        uid
+    }
+
+    /// Removes a message from the list.
+    pub fn remove(&mut self, name: &str) -> Option<UidEntry> {
+        let idx = self.uids.remove(name)?;
+        let entry = self.entries.remove(idx);
+        for entry_idx in self.uids.values_mut() {
+            if *entry_idx > idx {
+                *entry_idx -= 1;
+            }
+        }
+        Some(entry)
+    }
+
+    fn insert(&mut self, entry: UidEntry) {
+        if entry.uid >= self.uid_next {
+            self.uid_next = entry.uid + 1;
+        }
+        self.uids.insert(entry.name.clone(), self.entries.len());
+        self.entries.push(entry);
+    }
+
+    /// Returns the UIDVALIDITY of the folder
+    pub fn uid_validity(&self) -> u32 {
+        self.uid_validity
+    }
+
+    /// Returns the UID that will be assigned to the next message
+    pub fn uid_next(&self) -> u32 {
+        self.uid_next
+    }
+
+    /// Returns all entries in UID order
+    pub fn entries(&self) -> &[UidEntry] {
+        &self.entries
+    }
+}
+
+fn write_file(
+    path: &Path,
+    write_fnc: impl FnOnce(&mut io::BufWriter<fs::File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let mut writer = io::BufWriter::new(
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)?,
+    );
+
+    let result = write_fnc(&mut writer)
+        .and_then(|_| writer.get_ref().sync_all())
+        .and_then(|_| fs::rename(&lock_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&lock_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Keywords, UidList};
+
+    #[test]
+    fn parse_dovecot_files() {
+        let mut keywords =
+            Keywords::read_from(&b"0 $Forwarded\n1 Junk\n3 $label1\nbogus\n30 x\n"[..]).unwrap();
+        assert_eq!(keywords.get('a'), Some("$Forwarded"));
+        assert_eq!(keywords.get('b'), Some("Junk"));
+        assert_eq!(keywords.get('c'), None);
+        assert_eq!(keywords.get('d'), Some("$label1"));
+        assert_eq!(keywords.get('A'), None);
+        assert_eq!(keywords.get_letter("$label1"), Some('d'));
+        assert_eq!(keywords.get_or_insert("NonJunk"), Some('c'));
+        assert_eq!(keywords.get_or_insert("Work"), Some('e'));
+        assert_eq!(keywords.get_or_insert("Junk"), Some('b'));
+
+        let mut bytes = Vec::new();
+        keywords.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            String::from_utf8(bytes.clone()).unwrap(),
+            "0 $Forwarded\n1 Junk\n2 NonJunk\n3 $label1\n4 Work\n"
+        );
+        assert_eq!(Keywords::read_from(&bytes[..]).unwrap(), keywords);
+
+        for letter in 'f'..='z' {
+            assert_eq!(keywords.get_or_insert(&letter.to_string()), Some(letter));
+        }
+        assert_eq!(keywords.get_or_insert("Full"), None);
+
+        // Version 3
+        let mut uid_list = UidList::read_from(
+            concat!(
+                "3 V1276528000 N8 G3085f01b7f11094c501100008c4a11c1\n",
+                "1 :1234567890.M20046P2137.mailserver,S=4542\n",
+                "5 W1200 S1100 :1491941793.M41850P8566.mx1.example.com\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(uid_list.uid_validity(), 1276528000);
+        assert_eq!(uid_list.uid_next(), 8);
+        assert_eq!(
+            uid_list.get_uid("1491941793.M41850P8566.mx1.example.com"),
+            Some(5)
+        );
+        assert_eq!(uid_list.entries()[1].extensions, vec!["W1200", "S1100"]);
+        assert_eq!(uid_list.get_or_assign("1500000000.M1P1.host"), 8);
+        assert_eq!(uid_list.uid_next(), 9);
+        assert_eq!(
+            uid_list
+                .remove("1234567890.M20046P2137.mailserver,S=4542")
+                .unwrap()
+                .uid,
+            1
+        );
+        assert_eq!(uid_list.get_uid("1500000000.M1P1.host"), Some(8));
+
+        let mut bytes = Vec::new();
+        uid_list.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            String::from_utf8(bytes.clone()).unwrap(),
+            concat!(
+                "3 V1276528000 N9 G3085f01b7f11094c501100008c4a11c1\n",
+                "5 W1200 S1100 :1491941793.M41850P8566.mx1.example.com\n",
+                "8 :1500000000.M1P1.host\n",
+            )
+        );
+        assert_eq!(UidList::read_from(&bytes[..]).unwrap(), uid_list);
+
+        // Version 1
+        let uid_list = UidList::read_from(
+            &b"1 1276528000 3\n1 1234567890.M20046P2137.mailserver:2,S\n2 1234567891.M1P1.host\n"[..],
+        )
+        .unwrap();
+        assert_eq!(uid_list.uid_validity(), 1276528000);
+        assert_eq!(uid_list.uid_next(), 3);
+        assert_eq!(
+            uid_list.get_uid("1234567890.M20046P2137.mailserver"),
+            Some(1)
+        );
+        assert_eq!(uid_list.get_uid("1234567891.M1P1.host"), Some(2));
+    }
+}
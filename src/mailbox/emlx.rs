@@ -0,0 +1,442 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{decoders::base64::encode_base64, Encoding, GetHeader, PartType};
+
+pub use super::Flag;
+
+/// Apple Mail folder iterator
+pub struct FolderIterator {
+    it_stack: Vec<fs::ReadDir>,
+    name_stack: Vec<String>,
+}
+
+/// Apple Mail message iterator
+pub struct MessageIterator {
+    name: Option<String>,
+    files: std::vec::IntoIter<PathBuf>,
+}
+
+/// Apple Mail message contents and metadata
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+pub struct Message {
+    internal_date: u64,
+    flags: Vec<Flag>,
+    raw_flags: u64,
+    contents: Vec<u8>,
+    plist: String,
+    path: PathBuf,
+}
+
+impl FolderIterator {
+    /// Creates a new Apple Mail folder iterator over the `*.mbox` folders
+    /// found below `path`, usually a `Mailboxes` directory. Nested folder
+    /// names are separated by `/`.
+    pub fn new(path: impl Into<PathBuf>) -> io::Result<FolderIterator> {
+        Ok(FolderIterator {
+            it_stack: vec![fs::read_dir(path.into())?],
+            name_stack: Vec::new(),
+        })
+    }
+}
+
+impl Iterator for FolderIterator {
+    type Item = io::Result<MessageIterator>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = match self.it_stack.last_mut()?.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(err)) => return Some(Err(err)),
+                None => {
+                    self.it_stack.pop();
+                    self.name_stack.pop();
+                    continue;
+                }
+            };
+
+            let path = entry.path();
+            let name = match path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".mbox"))
+            {
+                Some(name) if path.is_dir() => name.to_string(),
+                _ => continue,
+            };
+
+            match fs::read_dir(&path) {
+                Ok(next_it) => {
+                    self.it_stack.push(next_it);
+                    self.name_stack.push(name);
+                }
+                Err(err) => return Some(Err(err)),
+            }
+
+            return Some(MessageIterator::new_(
+                &path,
+                self.name_stack.join("/").into(),
+            ));
+        }
+    }
+}
+
+impl MessageIterator {
+    /// Creates a new iterator over the `.emlx` files of an Apple Mail
+    /// `*.mbox` folder, excluding its sub-folders.
+    pub fn new(path: impl Into<PathBuf>) -> io::Result<MessageIterator> {
+        MessageIterator::new_(&path.into(), None)
+    }
+
+    fn new_(path: &Path, name: Option<String>) -> io::Result<MessageIterator> {
+        let mut files = Vec::new();
+        let mut dirs = vec![path.to_path_buf()];
+
+        // Messages are stored in 'Messages' directories, which newer
+        // versions nest under '<uuid>/Data/...'
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                let name = path.file_name().and_then(|name| name.to_str());
+                if path.is_dir() {
+                    if !name.is_some_and(|name| name.ends_with(".mbox") || name == "Attachments") {
+                        dirs.push(path);
+                    }
+                } else if name.is_some_and(|name| name.ends_with(".emlx"))
+                    && dir.ends_with("Messages")
+                {
+                    files.push(path);
+                }
+            }
+        }
+
+        files.sort_unstable_by_key(|path| {
+            (
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.split_once('.'))
+                    .and_then(|(id, _)| id.parse::<u64>().ok())
+                    .unwrap_or(u64::MAX),
+                path.clone(),
+            )
+        });
+
+        Ok(MessageIterator {
+            name,
+            files: files.into_iter(),
+        })
+    }
+
+    /// Returns the folder name
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+impl Iterator for MessageIterator {
+    type Item = io::Result<Message>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Message::read(self.files.next()?).into()
+    }
+}
+
+impl Message {
+    /// Reads an `.emlx` file. The attachments of `.partial.emlx` files are
+    /// read from the `Attachments` directory next to the `Messages` directory.
+    pub fn read(path: impl Into<PathBuf>) -> io::Result<Message> {
+        let path = path.into();
+        let mut message = Message::parse(&fs::read(&path)?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid emlx file."))?;
+
+        if let Some(id) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".partial.emlx"))
+        {
+            if let Some(attachments_path) = path
+                .parent()
+                .and_then(|path| path.parent())
+                .map(|path| path.join("Attachments").join(id))
+            {
+                message.contents = reassemble_partial(message.contents, &attachments_path)?;
+            }
+        }
+
+        message.path = path;
+        Ok(message)
+    }
+
+    /// Parses the contents of an `.emlx` file.
+    pub fn parse(bytes: &[u8]) -> Option<Message> {
+        let line_end = bytes.iter().position(|&ch| ch == b'\n')?;
+        let length = std::str::from_utf8(&bytes[..line_end])
+            .ok()?
+            .trim()
+            .parse::<usize>()
+            .ok()?;
+        let bytes = &bytes[line_end + 1..];
+        let contents = bytes.get(..length)?.to_vec();
+        let plist = String::from_utf8_lossy(&bytes[length..]).into_owned();
+
+        let raw_flags = plist_value(&plist, "flags")
+            .and_then(|flags| flags.parse::<u64>().ok())
+            .unwrap_or(0);
+        let mut flags = Vec::new();
+        for (bit, flag) in [
+            (0, Flag::Seen),
+            (1, Flag::Trashed),
+            (2, Flag::Replied),
+            (4, Flag::Flagged),
+            (6, Flag::Draft),
+            (8, Flag::Passed),
+        ] {
+            if raw_flags & (1 << bit) != 0 {
+                flags.push(flag);
+            }
+        }
+        flags.sort_unstable();
+
+        Some(Message {
+            internal_date: ["date-received", "date-sent"]
+                .iter()
+                .find_map(|key| plist_value(&plist, key)?.split('.').next()?.parse().ok())
+                .unwrap_or(0),
+            flags,
+            raw_flags,
+            contents,
+            plist,
+            path: PathBuf::new(),
+        })
+    }
+
+    /// Returns the date the message was received in seconds since UNIX epoch
+    pub fn internal_date(&self) -> u64 {
+        self.internal_date
+    }
+
+    /// Returns the message flags
+    pub fn flags(&self) -> &[Flag] {
+        &self.flags
+    }
+
+    /// Returns the Apple Mail flags bitfield
+    pub fn raw_flags(&self) -> u64 {
+        self.raw_flags
+    }
+
+    /// Returns the number of attachments, as stated in the flags bitfield
+    pub fn attachment_count(&self) -> usize {
+        ((self.raw_flags >> 10) & 0x3f) as usize
+    }
+
+    /// Returns `true` if Apple Mail classified the message as junk
+    pub fn is_junk(&self) -> bool {
+        self.raw_flags & (1 << 24) != 0
+    }
+
+    /// Returns a value from the message property list, such as `remote-id`
+    /// or `subject`.
+    pub fn property(&self, key: &str) -> Option<String> {
+        plist_value(&self.plist, key).map(|value| {
+            value
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&")
+        })
+    }
+
+    /// Returns the path to the message file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the message contents
+    pub fn contents(&self) -> &[u8] {
+        &self.contents
+    }
+
+    /// Unwraps the message contents
+    pub fn unwrap_contents(self) -> Vec<u8> {
+        self.contents
+    }
+}
+
+// Returns the contents of the scalar element that follows a key in the
+// top-level dictionary of a plist.
+fn plist_value<'x>(plist: &'x str, key: &str) -> Option<&'x str> {
+    let key_start = plist.find(&format!("<key>{}</key>", key))?;
+    let value = plist[key_start + key.len() + 11..].trim_start();
+    if value.starts_with("<true/>") {
+        return Some("true");
+    } else if value.starts_with("<false/>") {
+        return Some("false");
+    }
+    let value = &value[value.find('>')? + 1..];
+    Some(value[..value.find('<')?].trim())
+}
+
+// Inserts the attachments removed from a `.partial.emlx` message, which
+// are stored in 'Attachments/<id>/<part number>/<file name>'.
+fn reassemble_partial(contents: Vec<u8>, attachments_path: &Path) -> io::Result<Vec<u8>> {
+    let mut replacements = Vec::new();
+    if let Some(message) = crate::Message::parse(&contents) {
+        let line_ending: &[u8] = if contents.windows(2).any(|w| w == b"\r\n") {
+            b"\r\n"
+        } else {
+            b"\n"
+        };
+        let mut stack = vec![(0, String::new())];
+
+        while let Some((part_id, part_number)) = stack.pop() {
+            let part = &message.parts[part_id];
+            match &part.body {
+                PartType::Multipart(sub_parts) => {
+                    for (pos, &sub_part_id) in sub_parts.iter().enumerate() {
+                        stack.push((
+                            sub_part_id,
+                            if part_number.is_empty() {
+                                (pos + 1).to_string()
+                            } else {
+                                format!("{}.{}", part_number, pos + 1)
+                            },
+                        ));
+                    }
+                }
+                _ if part.headers.get_header("X-Apple-Content-Length").is_some() => {
+                    let part_number = if part_number.is_empty() {
+                        "1"
+                    } else {
+                        part_number.as_str()
+                    };
+                    let file_path = match fs::read_dir(attachments_path.join(part_number)) {
+                        Ok(mut dir) => match dir.next() {
+                            Some(entry) => entry?.path(),
+                            None => continue,
+                        },
+                        Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                        Err(err) => return Err(err),
+                    };
+                    let attachment = fs::read(file_path)?;
+                    let mut body = if part.encoding == Encoding::Base64 {
+                        encode_base64(&attachment, line_ending)
+                    } else {
+                        attachment
+                    };
+                    body.extend_from_slice(line_ending);
+                    replacements.push((part.offset_body, part.offset_end, body));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    if replacements.is_empty() {
+        return Ok(contents);
+    }
+
+    replacements.sort_unstable_by_key(|(start, _, _)| *start);
+    let mut result = Vec::with_capacity(
+        contents.len()
+            + replacements
+                .iter()
+                .map(|(_, _, body)| body.len())
+                .sum::<usize>(),
+    );
+    let mut last_offset = 0;
+    for (start, end, body) in replacements {
+        if start >= last_offset && end >= start && end <= contents.len() {
+            result.extend_from_slice(&contents[last_offset..start]);
+            result.extend_from_slice(&body);
+            last_offset = end;
+        }
+    }
+    result.extend_from_slice(&contents[last_offset..]);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::MimeHeaders;
+
+    use super::{Flag, FolderIterator, Message};
+
+    #[test]
+    fn parse_emlx() {
+        let mut test_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_dir.push("tests");
+        test_dir.push("emlx");
+        test_dir.push("Mailboxes");
+
+        let mut folders = FolderIterator::new(&test_dir)
+            .unwrap()
+            .map(|folder| {
+                let folder = folder.unwrap();
+                (
+                    folder.name().unwrap().to_string(),
+                    folder.map(|message| message.unwrap()).collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        folders.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            folders
+                .iter()
+                .map(|(name, messages)| (name.as_str(), messages.len()))
+                .collect::<Vec<_>>(),
+            vec![("Inbox", 2), ("Work", 0), ("Work/Projects", 1)]
+        );
+
+        let message = &folders[0].1[0];
+        assert_eq!(message.flags(), &[Flag::Seen, Flag::Flagged]);
+        assert_eq!(message.internal_date(), 1646063103);
+        assert_eq!(message.property("remote-id").as_deref(), Some("1234"));
+        assert_eq!(message.property("subject").as_deref(), Some("Fish & Chips"));
+        assert!(message.contents().ends_with(b"Hello from Apple Mail.\n"));
+        assert!(!message.is_junk());
+
+        // Attachments are restored from the 'Attachments' directory
+        let message = &folders[0].1[1];
+        assert_eq!(message.flags(), &[Flag::Replied, Flag::Seen]);
+        assert_eq!(message.attachment_count(), 1);
+        let parsed = crate::Message::parse(message.contents()).unwrap();
+        assert_eq!(
+            parsed.get_attachment(0).unwrap().get_contents(),
+            b"Hello, attachment!\n"
+        );
+        assert_eq!(
+            parsed.get_attachment(0).unwrap().get_attachment_name(),
+            Some("hello.txt")
+        );
+        assert_eq!(
+            parsed.get_text_body(0).unwrap().as_ref(),
+            "See the attached file.\n"
+        );
+
+        let message = &folders[2].1[0];
+        assert_eq!(message.flags(), &[Flag::Trashed, Flag::Draft]);
+        assert!(message.is_junk());
+
+        // Invalid byte counts
+        for bytes in [&b"18446744073709551615\nabc"[..], b"4\nabc", b"abc"] {
+            assert!(Message::parse(bytes).is_none());
+        }
+    }
+}
@@ -10,10 +10,19 @@
  */
 
 use std::{
-    fs, io,
+    fs,
+    io::{self, Write},
     path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
+pub use super::{Flag, SpecialUse};
+
+use crate::decoders::charsets::utf::{decoder_utf7_imap, encoder_utf7_imap};
+
+use super::dovecot::{Keywords, UidList};
+
 /// Maildir folder iterator
 pub struct FolderIterator<'x> {
     inbox: Option<MessageIterator>,
@@ -25,8 +34,17 @@ pub struct FolderIterator<'x> {
 /// Maildir message iterator
 pub struct MessageIterator {
     name: Option<String>,
+    path: PathBuf,
     cur_it: fs::ReadDir,
     new_it: fs::ReadDir,
+    metadata_only: bool,
+    sort_by_date: bool,
+    sorted: Option<std::vec::IntoIter<io::Result<Message>>>,
+    with_flags: Vec<Flag>,
+    without_flags: Vec<Flag>,
+    keywords: Keywords,
+    uid_list: Option<UidList>,
+    metadata_error: Option<io::Error>,
 }
 
 /// Maildir message contents and metadata
@@ -34,21 +52,41 @@ pub struct MessageIterator {
 pub struct Message {
     internal_date: u64,
     flags: Vec<Flag>,
-    contents: Vec<u8>,
+    contents: Option<Vec<u8>>,
     path: PathBuf,
+    uid: String,
+    size: u64,
+    keywords: Vec<String>,
+    imap_uid: Option<u32>,
 }
 
-/// Flags of Maildir message
-#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
-pub enum Flag {
-    Passed,
-    Replied,
-    Seen,
-    Trashed,
-    Draft,
-    Flagged,
+/// Delivers messages to a Maildir mailbox and updates their flags.
+pub struct MessageWriter {
+    path: PathBuf,
+    prefix: Option<String>,
+    hostname: String,
+}
+
+/// Maildir folder hierarchy
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FolderTree {
+    delimiter: char,
+    folders: Vec<Folder>,
 }
 
+/// Maildir folder and its sub-folders
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Folder {
+    name: String,
+    full_name: Option<String>,
+    delimiter: char,
+    path: Option<PathBuf>,
+    special_use: Option<SpecialUse>,
+    children: Vec<Folder>,
+}
+
+static DELIVERY_COUNTER: AtomicU64 = AtomicU64::new(0);
+
 impl FolderIterator<'_> {
     /// Creates a new Maildir folder iterator.
     /// For Maildir++ mailboxes use `Some(".")` as the prefix.
@@ -101,10 +139,37 @@ impl MessageIterator {
             ));
         }
 
+        // Damaged Dovecot metadata does not prevent reading the messages,
+        // the error is available through `metadata_error`.
+        let mut metadata_error = None;
+        let keywords = match Keywords::load(path) {
+            Ok(keywords) => keywords,
+            Err(err) => {
+                metadata_error = Some(err);
+                Keywords::default()
+            }
+        };
+        let uid_list = match UidList::load(path) {
+            Ok(uid_list) => uid_list,
+            Err(err) => {
+                metadata_error.get_or_insert(err);
+                None
+            }
+        };
+
         Ok(MessageIterator {
             name,
+            path: path.to_path_buf(),
             cur_it: fs::read_dir(cur_path)?,
             new_it: fs::read_dir(new_path)?,
+            metadata_only: false,
+            sort_by_date: false,
+            sorted: None,
+            with_flags: Vec::new(),
+            without_flags: Vec::new(),
+            keywords,
+            uid_list,
+            metadata_error,
         })
     }
 
@@ -112,6 +177,117 @@ impl MessageIterator {
     pub fn name(&self) -> Option<&str> {
         self.name.as_deref()
     }
+
+    /// Returns the path to the folder
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the UIDVALIDITY of the folder, if it has a `dovecot-uidlist` file.
+    pub fn uid_validity(&self) -> Option<u32> {
+        self.uid_list
+            .as_ref()
+            .map(|uid_list| uid_list.uid_validity())
+    }
+
+    /// Returns the next IMAP UID of the folder, if it has a `dovecot-uidlist` file.
+    pub fn uid_next(&self) -> Option<u32> {
+        self.uid_list.as_ref().map(|uid_list| uid_list.uid_next())
+    }
+
+    /// Returns the keywords of the folder, as defined in its `dovecot-keywords` file.
+    pub fn keywords(&self) -> &Keywords {
+        &self.keywords
+    }
+
+    /// Returns the error found while reading the folder's `dovecot-keywords`
+    /// or `dovecot-uidlist` file. Folders with damaged or unsupported
+    /// metadata are read without keywords or IMAP UIDs.
+    pub fn metadata_error(&self) -> Option<&io::Error> {
+        self.metadata_error.as_ref()
+    }
+
+    /// Returns messages without reading their contents, which can be
+    /// loaded later with `Message::load_contents`.
+    pub fn metadata_only(mut self) -> Self {
+        self.metadata_only = true;
+        self
+    }
+
+    /// Returns only messages that have the specified flag.
+    pub fn with_flag(mut self, flag: Flag) -> Self {
+        self.with_flags.push(flag);
+        self
+    }
+
+    /// Returns only messages that do not have the specified flag.
+    pub fn without_flag(mut self, flag: Flag) -> Self {
+        self.without_flags.push(flag);
+        self
+    }
+
+    /// Returns messages sorted by internal date. Message metadata is read
+    /// for the entire folder on the first call to `next`.
+    pub fn sort_by_internal_date(mut self) -> Self {
+        self.sort_by_date = true;
+        self
+    }
+
+    fn next_message(&mut self, load_contents: bool) -> Option<io::Result<Message>> {
+        loop {
+            let entry = match self.cur_it.next().or_else(|| self.new_it.next()) {
+                Some(Ok(entry)) => entry,
+                Some(Err(err)) => return Some(Err(err)),
+                None => return None,
+            };
+            let path = entry.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) if !name.starts_with('.') => name,
+                _ => continue,
+            };
+            let flags = parse_flags(name);
+            if !self.with_flags.iter().all(|flag| flags.contains(flag))
+                || self.without_flags.iter().any(|flag| flags.contains(flag))
+            {
+                continue;
+            }
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) if metadata.is_file() => metadata,
+                Ok(_) => continue,
+                Err(err) => return Some(Err(err)),
+            };
+            let uid = split_info(name).0;
+            let mut message = Message {
+                internal_date: match internal_date(&metadata, uid) {
+                    Ok(internal_date) => internal_date,
+                    Err(err) => return Some(Err(err)),
+                },
+                flags,
+                contents: None,
+                keywords: split_info(name)
+                    .2
+                    .chars()
+                    .filter_map(|letter| self.keywords.get(letter).map(|name| name.to_string()))
+                    .collect(),
+                imap_uid: self
+                    .uid_list
+                    .as_ref()
+                    .and_then(|uid_list| uid_list.get_uid(uid)),
+                uid: uid.to_string(),
+                size: uid
+                    .split(',')
+                    .find_map(|part| part.strip_prefix("S=")?.parse().ok())
+                    .unwrap_or(metadata.len()),
+                path,
+            };
+            if load_contents {
+                if let Err(err) = message.load_contents() {
+                    return Some(Err(err));
+                }
+            }
+            return Some(Ok(message));
+        }
+    }
 }
 
 impl Iterator for FolderIterator<'_> {
@@ -158,7 +334,7 @@ impl Iterator for FolderIterator<'_> {
                     match fs::read_dir(&path) {
                         Ok(next_it) => {
                             self.it_stack.push(next_it);
-                            self.name_stack.push(name.to_string());
+                            self.name_stack.push(decoder_utf7_imap(name.as_bytes()));
                         }
                         Err(err) => {
                             return Some(Err(err));
@@ -186,58 +362,32 @@ impl Iterator for MessageIterator {
     type Item = io::Result<Message>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            let entry = match self.cur_it.next().or_else(|| self.new_it.next()) {
-                Some(Ok(entry)) => entry,
-                Some(Err(err)) => return Some(Err(err)),
-                None => return None,
-            };
-            let path = entry.path();
-            if path.is_file() {
-                if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
-                    if !name.starts_with('.') {
-                        let internal_date =
-                            match fs::metadata(&path).and_then(|m| m.created()).and_then(|d| {
-                                d.duration_since(std::time::UNIX_EPOCH)
-                                    .map(|d| d.as_secs())
-                                    .map_err(|e| {
-                                        io::Error::new(io::ErrorKind::InvalidData, e.to_string())
-                                    })
-                            }) {
-                                Ok(metadata) => metadata,
-                                Err(err) => return Some(Err(err)),
-                            };
-                        let contents = match fs::read(&path) {
-                            Ok(contents) => contents,
-                            Err(err) => return Some(Err(err)),
-                        };
-                        let mut flags = Vec::new();
-                        if let Some((_, part)) = name.rsplit_once("2,") {
-                            for &ch in part.as_bytes() {
-                                match ch {
-                                    b'P' => flags.push(Flag::Passed),
-                                    b'R' => flags.push(Flag::Replied),
-                                    b'S' => flags.push(Flag::Seen),
-                                    b'T' => flags.push(Flag::Trashed),
-                                    b'D' => flags.push(Flag::Draft),
-                                    b'F' => flags.push(Flag::Flagged),
-                                    _ => {
-                                        if !ch.is_ascii_alphanumeric() {
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        return Some(Ok(Message {
-                            contents,
-                            internal_date,
-                            flags,
-                            path: path.to_path_buf(),
-                        }));
-                    }
-                }
+        if !self.sort_by_date {
+            return self.next_message(!self.metadata_only);
+        }
+
+        if self.sorted.is_none() {
+            let mut messages = Vec::new();
+            while let Some(message) = self.next_message(false) {
+                messages.push(message);
             }
+            messages.sort_by(|a, b| match (a, b) {
+                (Ok(a), Ok(b)) => (a.internal_date, &a.path).cmp(&(b.internal_date, &b.path)),
+                (Err(_), Ok(_)) => std::cmp::Ordering::Less,
+                (Ok(_), Err(_)) => std::cmp::Ordering::Greater,
+                (Err(_), Err(_)) => std::cmp::Ordering::Equal,
+            });
+            self.sorted = Some(messages.into_iter());
+        }
+
+        let message = self.sorted.as_mut()?.next()?;
+        if self.metadata_only {
+            Some(message)
+        } else {
+            Some(message.and_then(|mut message| {
+                message.load_contents()?;
+                Ok(message)
+            }))
         }
     }
 }
@@ -258,14 +408,515 @@ impl Message {
         &self.path
     }
 
-    /// Returns the message contents
+    /// Returns the unique name of the message, without the flags
+    pub fn uid(&self) -> &str {
+        &self.uid
+    }
+
+    /// Returns the message keywords, as defined in the `dovecot-keywords` file
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+
+    /// Returns the IMAP UID of the message, as stated in the `dovecot-uidlist` file
+    pub fn imap_uid(&self) -> Option<u32> {
+        self.imap_uid
+    }
+
+    /// Returns the message size, as stated in the `S=` file name field
+    /// or the file size otherwise
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the message contents, or an empty slice if the contents
+    /// have not been loaded
     pub fn contents(&self) -> &[u8] {
-        &self.contents
+        self.contents.as_deref().unwrap_or_default()
+    }
+
+    /// Reads the message contents from disk, if they have not been loaded yet
+    pub fn load_contents(&mut self) -> io::Result<&[u8]> {
+        if self.contents.is_none() {
+            self.contents = fs::read(&self.path)?.into();
+        }
+        Ok(self.contents())
     }
 
     /// Unwraps the message contents
     pub fn unwrap_contents(self) -> Vec<u8> {
-        self.contents
+        self.contents.unwrap_or_default()
+    }
+}
+
+impl MessageWriter {
+    /// Creates a new Maildir writer.
+    /// For Maildir++ mailboxes use `Some(".")` as the prefix.
+    /// For Dovecot Maildir mailboxes using LAYOUT=fs, use `None` as the prefix.
+    pub fn new(path: impl Into<PathBuf>, sub_folder_prefix: Option<&str>) -> MessageWriter {
+        MessageWriter {
+            path: path.into(),
+            prefix: sub_folder_prefix.map(|prefix| prefix.to_string()),
+            hostname: std::env::var("HOSTNAME")
+                .ok()
+                .or_else(|| fs::read_to_string("/etc/hostname").ok())
+                .map(|hostname| hostname.trim().to_string())
+                .filter(|hostname| !hostname.is_empty())
+                .unwrap_or_else(|| "localhost".to_string())
+                .replace('/', "\\057")
+                .replace(':', "\\072"),
+        }
+    }
+
+    /// Returns the path of a folder, or the root path for `None` ('INBOX').
+    /// Nested folder names are separated by the prefix on Maildir++
+    /// mailboxes and by `/` on LAYOUT=fs mailboxes, as returned by
+    /// `MessageIterator::name`. Names are stored in modified UTF-7.
+    pub fn folder_path(&self, folder: Option<&str>) -> PathBuf {
+        match (folder.map(encoder_utf7_imap), &self.prefix) {
+            (Some(folder), Some(prefix)) => self.path.join(format!("{}{}", prefix, folder)),
+            (Some(folder), None) => self.path.join(folder),
+            (None, _) => self.path.clone(),
+        }
+    }
+
+    /// Creates a folder, or 'INBOX' for `None`, if it does not exist.
+    pub fn create_folder(&self, folder: Option<&str>) -> io::Result<PathBuf> {
+        let path = self.folder_path(folder);
+        for dir in ["cur", "new", "tmp"] {
+            fs::create_dir_all(path.join(dir))?;
+        }
+        if folder.is_some() && self.prefix.is_some() {
+            fs::write(path.join("maildirfolder"), b"")?;
+        }
+        Ok(path)
+    }
+
+    /// Delivers a message to a folder, or 'INBOX' for `None`, returning the
+    /// path of the new message file. The message is written to `tmp` and then
+    /// moved to `new`, or to `cur` if any flags are set.
+    pub fn deliver(
+        &self,
+        folder: Option<&str>,
+        contents: &[u8],
+        flags: &[Flag],
+    ) -> io::Result<PathBuf> {
+        let path = self.folder_path(folder);
+        if !path.join("tmp").exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Invalid Maildir format, 'tmp' directory not found.",
+            ));
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let name = format!(
+            "{}.M{}P{}Q{}.{},S={}",
+            now.as_secs(),
+            now.subsec_micros(),
+            std::process::id(),
+            DELIVERY_COUNTER.fetch_add(1, Ordering::Relaxed),
+            self.hostname,
+            contents.len()
+        );
+
+        let tmp_path = path.join("tmp").join(&name);
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)?;
+        if let Err(err) = file.write_all(contents).and_then(|_| file.sync_all()) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+
+        let message_path = if flags.is_empty() {
+            path.join("new").join(name)
+        } else {
+            path.join("cur")
+                .join(format!("{}:2,{}", name, format_flags(flags, "")))
+        };
+        fs::rename(&tmp_path, &message_path)?;
+        Ok(message_path)
+    }
+
+    /// Replaces the flags of a message, returning its new path. Messages in
+    /// `new` are moved to `cur`.
+    pub fn set_flags(&self, message_path: &Path, flags: &[Flag]) -> io::Result<PathBuf> {
+        let (folder_path, name) = split_message_path(message_path)?;
+        let (base_name, separator, info) = split_info(name);
+        let new_path = folder_path.join("cur").join(format!(
+            "{}{}2,{}",
+            base_name,
+            separator,
+            format_flags(flags, info)
+        ));
+        if new_path != message_path {
+            fs::rename(message_path, &new_path)?;
+        }
+        Ok(new_path)
+    }
+
+    /// Replaces the keywords of a message, returning its new path. Keywords
+    /// are stored as info letters and added to the folder's
+    /// `dovecot-keywords` file when needed.
+    pub fn set_keywords(&self, message_path: &Path, keywords: &[&str]) -> io::Result<PathBuf> {
+        let (folder_path, name) = split_message_path(message_path)?;
+        let letters = keyword_letters(folder_path, keywords)?;
+        let (base_name, separator, _) = split_info(name);
+        let new_path = folder_path.join("cur").join(format!(
+            "{}{}2,{}",
+            base_name,
+            separator,
+            format_flags(&parse_flags(name), &letters)
+        ));
+        if new_path != message_path {
+            fs::rename(message_path, &new_path)?;
+        }
+        Ok(new_path)
+    }
+
+    /// Moves a message to another folder, or to 'INBOX' for `None`, keeping
+    /// its flags and keywords. Returns the new path of the message.
+    pub fn move_message(&self, message_path: &Path, folder: Option<&str>) -> io::Result<PathBuf> {
+        let (folder_path, name) = split_message_path(message_path)?;
+        let sub_dir = message_path
+            .parent()
+            .and_then(|parent| parent.file_name())
+            .unwrap_or_default();
+        let new_path = self.folder_path(folder).join(sub_dir);
+        if !new_path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "Invalid Maildir format, '{}' directory not found.",
+                    sub_dir.to_string_lossy()
+                ),
+            ));
+        }
+
+        // Keyword letters are assigned per folder
+        let (base_name, separator, info) = split_info(name);
+        let new_path = if info.contains(|ch: char| ch.is_ascii_lowercase()) {
+            let keywords = Keywords::load(folder_path)?;
+            let keywords = info
+                .chars()
+                .filter_map(|letter| keywords.get(letter))
+                .collect::<Vec<_>>();
+            let letters = keyword_letters(&self.folder_path(folder), &keywords)?;
+            new_path.join(format!(
+                "{}{}2,{}",
+                base_name,
+                separator,
+                format_flags(&parse_flags(name), &letters)
+            ))
+        } else {
+            new_path.join(name)
+        };
+        fs::rename(message_path, &new_path)?;
+        Ok(new_path)
+    }
+
+    /// Deletes all messages flagged as `Trashed` in a folder, or 'INBOX' for
+    /// `None`, returning the number of deleted messages.
+    pub fn expunge(&self, folder: Option<&str>) -> io::Result<usize> {
+        let path = self.folder_path(folder);
+        let mut num_deleted = 0;
+
+        for dir in ["cur", "new"] {
+            for entry in fs::read_dir(path.join(dir))? {
+                let entry = entry?;
+                let is_trashed = entry.file_name().to_str().is_some_and(|name| {
+                    !name.starts_with('.') && parse_flags(name).contains(&Flag::Trashed)
+                });
+                if is_trashed && entry.file_type()?.is_file() {
+                    fs::remove_file(entry.path())?;
+                    num_deleted += 1;
+                }
+            }
+        }
+
+        Ok(num_deleted)
+    }
+}
+
+impl FolderTree {
+    /// Builds the folder tree of a Maildir mailbox, 'INBOX' is returned as
+    /// the first top-level folder.
+    /// For Maildir++ mailboxes use `Some(".")` as the prefix.
+    /// For Dovecot Maildir mailboxes using LAYOUT=fs, use `None` as the prefix.
+    pub fn new(
+        path: impl Into<PathBuf>,
+        sub_folder_prefix: Option<&str>,
+    ) -> io::Result<FolderTree> {
+        let delimiter = sub_folder_prefix
+            .and_then(|prefix| prefix.chars().next())
+            .unwrap_or('/');
+        let mut folders = Vec::new();
+
+        for folder in FolderIterator::new(path, sub_folder_prefix)? {
+            let folder = folder?;
+            let full_name = match folder.name() {
+                Some(full_name) => full_name,
+                None => {
+                    let mut inbox = Folder::new("INBOX", None, delimiter);
+                    inbox.path = folder.path().to_path_buf().into();
+                    folders.push(inbox);
+                    continue;
+                }
+            };
+
+            // Add any missing parent folders
+            let mut children = &mut folders;
+            let mut name_end = 0;
+            for name in full_name.split(delimiter) {
+                name_end += name.len();
+                let idx = match children
+                    .iter()
+                    .position(|child| child.full_name.as_deref() == Some(&full_name[..name_end]))
+                {
+                    Some(idx) => idx,
+                    None => {
+                        let mut child = Folder::new(name, Some(&full_name[..name_end]), delimiter);
+                        child.special_use = SpecialUse::from_name(name);
+                        children.push(child);
+                        children.len() - 1
+                    }
+                };
+                if name_end == full_name.len() {
+                    children[idx].path = folder.path().to_path_buf().into();
+                }
+                children = &mut children[idx].children;
+                name_end += delimiter.len_utf8();
+            }
+        }
+
+        sort_folders(&mut folders);
+        Ok(FolderTree { delimiter, folders })
+    }
+
+    /// Returns the hierarchy delimiter
+    pub fn delimiter(&self) -> char {
+        self.delimiter
+    }
+
+    /// Returns the top-level folders
+    pub fn folders(&self) -> &[Folder] {
+        &self.folders
+    }
+
+    /// Returns a folder by its full name, or 'INBOX' for `None`.
+    pub fn get(&self, full_name: Option<&str>) -> Option<&Folder> {
+        self.iter()
+            .find(|folder| folder.full_name.as_deref() == full_name)
+    }
+
+    /// Returns the first folder with the specified special use.
+    pub fn get_special_use(&self, special_use: SpecialUse) -> Option<&Folder> {
+        self.iter()
+            .find(|folder| folder.special_use == Some(special_use))
+    }
+
+    /// Returns all folders, parents before their children.
+    pub fn iter(&self) -> impl Iterator<Item = &Folder> {
+        let mut stack = self.folders.iter().rev().collect::<Vec<_>>();
+        std::iter::from_fn(move || {
+            let folder = stack.pop()?;
+            stack.extend(folder.children.iter().rev());
+            Some(folder)
+        })
+    }
+}
+
+impl Folder {
+    fn new(name: &str, full_name: Option<&str>, delimiter: char) -> Folder {
+        Folder {
+            name: name.to_string(),
+            full_name: full_name.map(|full_name| full_name.to_string()),
+            delimiter,
+            path: None,
+            special_use: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Returns the folder name, without its parents
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the full folder name or None for 'INBOX'
+    pub fn full_name(&self) -> Option<&str> {
+        self.full_name.as_deref()
+    }
+
+    /// Returns the full name of the parent folder, if any
+    pub fn parent(&self) -> Option<&str> {
+        self.full_name
+            .as_deref()?
+            .rsplit_once(self.delimiter)
+            .map(|(parent, _)| parent)
+    }
+
+    /// Returns the hierarchy delimiter
+    pub fn delimiter(&self) -> char {
+        self.delimiter
+    }
+
+    /// Returns the path to the folder, or None if it only exists as the
+    /// parent of other folders
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Returns `true` if the folder can contain messages
+    pub fn is_selectable(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// Returns the special use of the folder, detected from its name
+    pub fn special_use(&self) -> Option<SpecialUse> {
+        self.special_use
+    }
+
+    /// Returns the sub-folders
+    pub fn children(&self) -> &[Folder] {
+        &self.children
+    }
+
+    /// Returns an iterator over the messages in the folder.
+    pub fn messages(&self) -> io::Result<MessageIterator> {
+        match &self.path {
+            Some(path) => MessageIterator::new_(path, self.full_name.clone()),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Folder does not contain messages.",
+            )),
+        }
+    }
+}
+
+fn sort_folders(folders: &mut [Folder]) {
+    folders.sort_unstable_by(|a, b| {
+        (a.full_name.is_some(), &a.name).cmp(&(b.full_name.is_some(), &b.name))
+    });
+    for folder in folders {
+        sort_folders(&mut folder.children);
+    }
+}
+
+// Returns the info letters of the keywords in a folder, adding any
+// missing keywords to its `dovecot-keywords` file.
+fn keyword_letters(folder_path: &Path, keywords: &[&str]) -> io::Result<String> {
+    let mut keyword_list = Keywords::load(folder_path)?;
+    let mut letters = String::with_capacity(keywords.len());
+    let mut has_changes = false;
+    for &keyword in keywords {
+        let letter = match keyword_list.get_letter(keyword) {
+            Some(letter) => letter,
+            None => {
+                has_changes = true;
+                keyword_list.get_or_insert(keyword).ok_or_else(|| {
+                    io::Error::other("No keyword letters available in 'dovecot-keywords'.")
+                })?
+            }
+        };
+        letters.push(letter);
+    }
+    if has_changes {
+        keyword_list.save(folder_path)?;
+    }
+    Ok(letters)
+}
+
+// Uses the file creation date, the modification date or the
+// timestamp at the beginning of the unique name, whichever is available.
+fn internal_date(metadata: &fs::Metadata, uid: &str) -> io::Result<u64> {
+    metadata
+        .created()
+        .or_else(|_| metadata.modified())
+        .ok()
+        .and_then(|date| date.duration_since(UNIX_EPOCH).ok())
+        .map(|date| date.as_secs())
+        .or_else(|| uid.split_once('.')?.0.parse().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Failed to obtain the message internal date.",
+            )
+        })
+}
+
+fn parse_flags(name: &str) -> Vec<Flag> {
+    let mut flags = Vec::new();
+    for &ch in split_info(name).2.as_bytes() {
+        match ch {
+            b'P' => flags.push(Flag::Passed),
+            b'R' => flags.push(Flag::Replied),
+            b'S' => flags.push(Flag::Seen),
+            b'T' => flags.push(Flag::Trashed),
+            b'D' => flags.push(Flag::Draft),
+            b'F' => flags.push(Flag::Flagged),
+            _ => {
+                if !ch.is_ascii_alphanumeric() {
+                    break;
+                }
+            }
+        }
+    }
+    flags
+}
+
+// Builds the info flags in ASCII order, keeping any keyword letters
+// present in the current info.
+fn format_flags(flags: &[Flag], info: &str) -> String {
+    let mut letters = flags
+        .iter()
+        .map(|flag| match flag {
+            Flag::Passed => 'P',
+            Flag::Replied => 'R',
+            Flag::Seen => 'S',
+            Flag::Trashed => 'T',
+            Flag::Draft => 'D',
+            Flag::Flagged => 'F',
+        })
+        .chain(info.chars().filter(|ch| ch.is_ascii_lowercase()))
+        .collect::<Vec<_>>();
+    letters.sort_unstable();
+    letters.dedup();
+    letters.into_iter().collect()
+}
+
+// Splits a message file name into its unique name, the info separator
+// and the info flags.
+pub(crate) fn split_info(name: &str) -> (&str, char, &str) {
+    for separator in [':', ';'] {
+        if let Some((base_name, info)) = name.rsplit_once(&format!("{}2,", separator)) {
+            return (base_name, separator, info);
+        }
+    }
+    (name, ':', "")
+}
+
+// Returns the folder path and file name of a message in `cur` or `new`.
+fn split_message_path(message_path: &Path) -> io::Result<(&Path, &str)> {
+    match (
+        message_path.file_name().and_then(|name| name.to_str()),
+        message_path.parent().and_then(|parent| {
+            if parent.ends_with("cur") || parent.ends_with("new") {
+                parent.parent()
+            } else {
+                None
+            }
+        }),
+    ) {
+        (Some(name), Some(folder_path)) => Ok((folder_path, name)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Message path is not located in a 'cur' or 'new' directory.",
+        )),
     }
 }
 
@@ -273,9 +924,9 @@ impl Message {
 mod tests {
     use std::path::PathBuf;
 
-    use crate::mailbox::maildir::{Flag, Message};
+    use crate::mailbox::maildir::{Flag, Message, MessageIterator};
 
-    use super::FolderIterator;
+    use super::{FolderIterator, FolderTree, MessageWriter, SpecialUse};
 
     #[test]
     fn parse_maildir() {
@@ -290,8 +941,12 @@ mod tests {
                 Message {
                     internal_date: 0,
                     flags: vec![Flag::Seen],
-                    contents: vec![98, 10],
+                    contents: Some(vec![98, 10]),
                     path: "unknown".into(),
+                    uid: "1234567890.M20046P2137.mailserver,S=4542,W=4642".into(),
+                    size: 4542,
+                    keywords: vec!["$Important".to_string()],
+                    imap_uid: Some(1),
                 },
             ),
             (
@@ -299,8 +954,12 @@ mod tests {
                 Message {
                     internal_date: 0,
                     flags: vec![Flag::Seen, Flag::Trashed],
-                    contents: vec![97, 10],
+                    contents: Some(vec![97, 10]),
                     path: "unknown".into(),
+                    uid: "1491941793.M41850P8566V0000000000000015I0000000004F3030E_0.mx1.example.com,S=10956".into(),
+                    size: 10956,
+                    keywords: vec!["Work".to_string(), "Personal".to_string()],
+                    imap_uid: Some(3),
                 },
             ),
             (
@@ -308,8 +967,12 @@ mod tests {
                 Message {
                     internal_date: 0,
                     flags: vec![],
-                    contents: vec![100, 10],
+                    contents: Some(vec![100, 10]),
                     path: "unknown".into(),
+                    uid: "1276528487.M364837P9451.kurkku,S=1355,W=1394".into(),
+                    size: 1355,
+                    keywords: vec![],
+                    imap_uid: None,
                 },
             ),
             (
@@ -317,8 +980,12 @@ mod tests {
                 Message {
                     internal_date: 0,
                     flags: vec![Flag::Trashed, Flag::Draft, Flag::Replied],
-                    contents: vec![99, 10],
+                    contents: Some(vec![99, 10]),
                     path: "unknown".into(),
+                    uid: "1035478339.27041_118.foo.org,S=1000,W=1030".into(),
+                    size: 1000,
+                    keywords: vec![],
+                    imap_uid: None,
                 },
             ),
             (
@@ -326,8 +993,12 @@ mod tests {
                 Message {
                     internal_date: 0,
                     flags: vec![Flag::Replied, Flag::Draft, Flag::Flagged],
-                    contents: vec![102, 10],
+                    contents: Some(vec![102, 10]),
                     path: "unknown".into(),
+                    uid: "1035478339.27041_118.foo.org,S=1000,W=1030".into(),
+                    size: 1000,
+                    keywords: vec![],
+                    imap_uid: None,
                 },
             ),
             (
@@ -335,13 +1006,17 @@ mod tests {
                 Message {
                     internal_date: 0,
                     flags: vec![Flag::Flagged, Flag::Passed],
-                    contents: vec![101, 10],
+                    contents: Some(vec![101, 10]),
                     path: "unknown".into(),
+                    uid: "1276533073.M242911P3632.kurkku".into(),
+                    size: 2,
+                    keywords: vec![],
+                    imap_uid: None,
                 },
             ),
         ];
 
-        for folder in FolderIterator::new(test_dir, ".".into()).unwrap() {
+        for folder in FolderIterator::new(&test_dir, ".".into()).unwrap() {
             let folder = folder.unwrap();
             let name = folder.name().unwrap_or("INBOX").to_string();
 
@@ -357,5 +1032,263 @@ mod tests {
 
         messages.sort_unstable();
         assert_eq!(messages, expected_messages);
+
+        // Metadata only, sorted by internal date
+        let inbox = MessageIterator::new(&test_dir)
+            .unwrap()
+            .metadata_only()
+            .sort_by_internal_date()
+            .map(|message| message.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(inbox.len(), 2);
+        assert!(inbox[0].internal_date() <= inbox[1].internal_date());
+        for mut message in inbox {
+            assert!(message.contents().is_empty());
+            assert_eq!(message.load_contents().unwrap().len(), 2);
+        }
+
+        // Dovecot UIDs
+        let inbox = MessageIterator::new(&test_dir).unwrap();
+        assert_eq!(inbox.uid_validity(), Some(1276528000));
+        assert_eq!(inbox.uid_next(), Some(5));
+        assert_eq!(inbox.keywords().get('l'), Some("Work"));
+
+        // Filter by flag
+        let trashed = MessageIterator::new(&test_dir)
+            .unwrap()
+            .with_flag(Flag::Trashed)
+            .map(|message| message.unwrap().unwrap_contents())
+            .collect::<Vec<_>>();
+        assert_eq!(trashed, vec![b"a\n".to_vec()]);
+        assert_eq!(
+            MessageIterator::new(&test_dir)
+                .unwrap()
+                .without_flag(Flag::Seen)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn write_maildir() {
+        for (prefix, folder) in [(Some("."), "Archive.2023"), (None, "Archive/2023")] {
+            let mut test_dir = std::env::temp_dir();
+            test_dir.push(format!(
+                "mail_parser_maildir_{}_{}",
+                std::process::id(),
+                prefix.is_some()
+            ));
+            let _ = std::fs::remove_dir_all(&test_dir);
+
+            let writer = MessageWriter::new(&test_dir, prefix);
+            writer.create_folder(None).unwrap();
+            writer.create_folder(folder.into()).unwrap();
+
+            // Delivery
+            let path_a = writer.deliver(None, b"a\n", &[]).unwrap();
+            let path_b = writer
+                .deliver(None, b"b\n", &[Flag::Seen, Flag::Flagged])
+                .unwrap();
+            assert!(path_a.parent().unwrap().ends_with("new"));
+            assert!(path_b.to_str().unwrap().ends_with(":2,FS"));
+            assert_ne!(path_a, path_b);
+            assert!(std::fs::read_dir(test_dir.join("tmp"))
+                .unwrap()
+                .next()
+                .is_none());
+
+            // Flag changes, keyword letters are preserved
+            let path_b = writer
+                .set_flags(&path_b, &[Flag::Replied, Flag::Seen])
+                .unwrap();
+            assert!(path_b.to_str().unwrap().ends_with(":2,RS"));
+            let path_b = writer.set_keywords(&path_b, &["Work", "Personal"]).unwrap();
+            assert!(path_b.to_str().unwrap().ends_with(":2,RSab"));
+            let path_b = writer.set_flags(&path_b, &[Flag::Draft]).unwrap();
+            assert!(path_b.to_str().unwrap().ends_with(":2,Dab"));
+            let path_b = writer.set_keywords(&path_b, &["Personal"]).unwrap();
+            assert!(path_b.to_str().unwrap().ends_with(":2,Db"));
+            let path_a = writer.set_flags(&path_a, &[Flag::Trashed]).unwrap();
+            assert!(path_a.parent().unwrap().ends_with("cur"));
+
+            // Moves
+            let path_b = writer.move_message(&path_b, folder.into()).unwrap();
+            assert!(path_b.to_str().unwrap().ends_with(":2,Da"));
+            assert_eq!(
+                path_b.parent().unwrap(),
+                writer.folder_path(folder.into()).join("cur")
+            );
+            assert!(writer.move_message(&path_b, "Unknown".into()).is_err());
+
+            let mut folders = FolderIterator::new(&test_dir, prefix)
+                .unwrap()
+                .map(|folder| {
+                    let folder = folder.unwrap();
+                    (
+                        folder.name().map(|name| name.to_string()),
+                        folder
+                            .map(|message| {
+                                let message = message.unwrap();
+                                (
+                                    message.contents().to_vec(),
+                                    message.flags().to_vec(),
+                                    message.keywords().to_vec(),
+                                )
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            folders.sort_unstable();
+            assert_eq!(
+                folders,
+                vec![
+                    (None, vec![(b"a\n".to_vec(), vec![Flag::Trashed], vec![])]),
+                    (
+                        Some(folder.to_string()),
+                        vec![(
+                            b"b\n".to_vec(),
+                            vec![Flag::Draft],
+                            vec!["Personal".to_string()]
+                        )]
+                    ),
+                ]
+            );
+
+            // Unsupported Dovecot metadata is reported without failing
+            assert!(MessageIterator::new(&test_dir)
+                .unwrap()
+                .metadata_error()
+                .is_none());
+            std::fs::write(test_dir.join("dovecot-uidlist"), b"9 V1 N2\n").unwrap();
+            let inbox = MessageIterator::new(&test_dir).unwrap();
+            assert_eq!(
+                inbox.metadata_error().map(|err| err.kind()),
+                Some(std::io::ErrorKind::InvalidData)
+            );
+            assert_eq!(inbox.uid_validity(), None);
+            assert_eq!(inbox.count(), 1);
+            std::fs::remove_file(test_dir.join("dovecot-uidlist")).unwrap();
+
+            // Expunge, unique names may contain '2,' outside the info
+            let unique_path = test_dir.join("cur").join("1700000000.M2,T.host");
+            std::fs::write(&unique_path, b"c\n").unwrap();
+            assert_eq!(writer.expunge(None).unwrap(), 1);
+            assert_eq!(writer.expunge(folder.into()).unwrap(), 0);
+            assert!(unique_path.exists());
+            std::fs::remove_file(&unique_path).unwrap();
+            assert_eq!(MessageIterator::new(&test_dir).unwrap().count(), 0);
+
+            std::fs::remove_dir_all(&test_dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn maildir_folder_tree() {
+        for prefix in [Some("."), None] {
+            let mut test_dir = std::env::temp_dir();
+            test_dir.push(format!(
+                "mail_parser_folders_{}_{}",
+                std::process::id(),
+                prefix.is_some()
+            ));
+            let _ = std::fs::remove_dir_all(&test_dir);
+            let delimiter = if prefix.is_some() { '.' } else { '/' };
+
+            let writer = MessageWriter::new(&test_dir, prefix);
+            writer.create_folder(None).unwrap();
+            for folder in [
+                "Sent",
+                "Sent.Entwürfe",
+                "Archive.2023",
+                "Spam",
+                "Tom & Jerry",
+            ] {
+                writer
+                    .create_folder(folder.replace('.', &delimiter.to_string()).as_str().into())
+                    .unwrap();
+            }
+            let drafts = format!("Sent{}Entwürfe", delimiter);
+            assert!(writer
+                .folder_path(drafts.as_str().into())
+                .ends_with(if prefix.is_some() {
+                    ".Sent.Entw&APw-rfe"
+                } else {
+                    "Sent/Entw&APw-rfe"
+                }));
+            writer
+                .deliver(drafts.as_str().into(), b"draft\n", &[Flag::Draft])
+                .unwrap();
+
+            let tree = FolderTree::new(&test_dir, prefix).unwrap();
+            assert_eq!(tree.delimiter(), delimiter);
+            assert_eq!(
+                tree.iter()
+                    .map(|folder| (
+                        folder.name(),
+                        folder.full_name().map(|name| name.to_string()),
+                        folder.parent().map(|name| name.to_string()),
+                        folder.is_selectable(),
+                        folder.special_use()
+                    ))
+                    .collect::<Vec<_>>(),
+                vec![
+                    ("INBOX", None, None, true, None),
+                    ("Archive", Some("Archive".to_string()), None, false, None),
+                    (
+                        "2023",
+                        Some(format!("Archive{}2023", delimiter)),
+                        Some("Archive".to_string()),
+                        true,
+                        None
+                    ),
+                    (
+                        "Sent",
+                        Some("Sent".to_string()),
+                        None,
+                        true,
+                        Some(SpecialUse::Sent)
+                    ),
+                    (
+                        "Entwürfe",
+                        Some(drafts.clone()),
+                        Some("Sent".to_string()),
+                        true,
+                        None
+                    ),
+                    (
+                        "Spam",
+                        Some("Spam".to_string()),
+                        None,
+                        true,
+                        Some(SpecialUse::Junk)
+                    ),
+                    (
+                        "Tom & Jerry",
+                        Some("Tom & Jerry".to_string()),
+                        None,
+                        true,
+                        None
+                    ),
+                ]
+            );
+            assert_eq!(
+                tree.get_special_use(SpecialUse::Junk).unwrap().name(),
+                "Spam"
+            );
+            assert_eq!(tree.folders()[2].children().len(), 1);
+            assert!(tree.get(Some("Archive")).unwrap().messages().is_err());
+            assert_eq!(
+                tree.get(Some(&drafts))
+                    .unwrap()
+                    .messages()
+                    .unwrap()
+                    .map(|message| message.unwrap().unwrap_contents())
+                    .collect::<Vec<_>>(),
+                vec![b"draft\n".to_vec()]
+            );
+
+            std::fs::remove_dir_all(&test_dir).unwrap();
+        }
     }
 }
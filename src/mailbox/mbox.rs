@@ -9,15 +9,51 @@
  * except according to those terms.
  */
 
+use super::{mbox_index::IndexEntry, Flag};
 use crate::DateTime;
-use std::io::{BufRead, BufReader, Read};
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Read, Write},
+};
 
 /// Parses an Mbox mailbox from a `Read` stream, returning each message as a
 /// `Vec<u8>`.
 /// supports >From  quoting as defined in the [QMail mbox specification](http://qmail.org/qmail-manual-html/man5/mbox.html).
 pub struct MessageIterator<T: Read> {
-    reader: BufReader<T>,
+    reader: BufReader<ByteCounter<T>>,
     message: Option<Message>,
+    format: Option<MboxFormat>,
+    from_lines: VecDeque<(u64, Vec<u8>)>,
+    // Bytes to read again after an invalid Content-Length
+    pending: Vec<u8>,
+    pending_pos: usize,
+}
+
+struct ByteCounter<T: Read> {
+    reader: T,
+    bytes_read: u64,
+}
+
+/// Writes messages to an Mbox mailbox.
+pub struct MessageWriter<T: Write> {
+    writer: T,
+    format: MboxFormat,
+}
+
+/// Mbox variants, as described in the [mbox man page](https://manpages.debian.org/bullseye/mutt/mbox.5.en.html).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum MboxFormat {
+    /// Lines starting with `From ` are quoted as `>From `. Quoting is not
+    /// reversible, lines starting with `>From ` lose their first `>` when read.
+    Mboxo,
+    /// Lines starting with any number of `>` followed by `From ` are quoted
+    /// with an additional `>`.
+    #[default]
+    Mboxrd,
+    /// Quoted like `Mboxo`, messages include a `Content-Length` header.
+    Mboxcl,
+    /// Not quoted, messages include a `Content-Length` header.
+    Mboxcl2,
 }
 
 /// Mbox message contents and metadata
@@ -25,45 +61,179 @@ pub struct MessageIterator<T: Read> {
 pub struct Message {
     internal_date: u64,
     from: String,
+    flags: Vec<Flag>,
+    keywords: Vec<String>,
+    labels: Vec<String>,
+    is_recent: bool,
     contents: Vec<u8>,
 }
 
 #[derive(Debug)]
 pub struct ParseError {}
 
+const DETECT_BUFFER_SIZE: usize = 64 * 1024;
+
 impl<T> MessageIterator<T>
 where
     T: Read,
 {
     pub fn new(reader: T) -> MessageIterator<T> {
         MessageIterator {
-            reader: BufReader::new(reader),
+            reader: BufReader::new(ByteCounter::new(reader)),
             message: None,
+            format: None,
+            from_lines: VecDeque::with_capacity(2),
+            pending: Vec::new(),
+            pending_pos: 0,
         }
     }
-}
 
-impl<T> Iterator for MessageIterator<T>
-where
-    T: Read,
-{
-    type Item = Result<Message, ParseError>;
+    /// Creates a new iterator that reads an Mbox mailbox of the specified format.
+    /// Unlike `new`, the blank line separating messages is not included in
+    /// the message contents and `Content-Length` headers are removed from
+    /// `Mboxcl` and `Mboxcl2` messages.
+    pub fn with_format(reader: T, format: MboxFormat) -> MessageIterator<T> {
+        MessageIterator {
+            reader: BufReader::new(ByteCounter::new(reader)),
+            message: None,
+            format: format.into(),
+            from_lines: VecDeque::with_capacity(2),
+            pending: Vec::new(),
+            pending_pos: 0,
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut message_line = Vec::with_capacity(80);
+    /// Creates a new iterator that detects the Mbox format from the beginning
+    /// of the mailbox, see `MboxFormat::detect`.
+    pub fn with_auto_detect(reader: T) -> MessageIterator<T> {
+        let mut reader = BufReader::with_capacity(DETECT_BUFFER_SIZE, ByteCounter::new(reader));
+        let format = reader
+            .fill_buf()
+            .map(MboxFormat::detect)
+            .unwrap_or_default();
+
+        MessageIterator {
+            reader,
+            message: None,
+            format: format.into(),
+            from_lines: VecDeque::with_capacity(2),
+            pending: Vec::new(),
+            pending_pos: 0,
+        }
+    }
+
+    /// Returns the Mbox format being read, or `None` if the iterator was created
+    /// with `new`.
+    pub fn detected_format(&self) -> Option<MboxFormat> {
+        self.format
+    }
+
+    fn next_message(&mut self, format: MboxFormat) -> Result<Option<Message>, ParseError> {
+        let mut line = Vec::with_capacity(80);
+
+        // Find the first 'From ' line
+        let mut message = match self.message.take() {
+            Some(message) => message,
+            None => loop {
+                line.clear();
+                if !self.read_line(&mut line)? {
+                    return Ok(None);
+                } else if is_from_line(&line) {
+                    break Message::new(std::str::from_utf8(&line).unwrap_or(""));
+                }
+            },
+        };
+
+        let has_content_length = matches!(format, MboxFormat::Mboxcl | MboxFormat::Mboxcl2);
+        let mut content_length = None;
+        let mut in_headers = true;
 
         loop {
-            match self.reader.read_until(b'\n', &mut message_line) {
-                Ok(bytes_read) => {
-                    if bytes_read == 0 {
-                        break;
+            line.clear();
+            if !self.read_line(&mut line)? {
+                break;
+            } else if is_from_line(&line) {
+                self.message = Message::new(std::str::from_utf8(&line).unwrap_or("")).into();
+                break;
+            }
+
+            if in_headers {
+                if line == b"\n" || line == b"\r\n" {
+                    in_headers = false;
+                    message.contents.extend_from_slice(&line);
+
+                    if let Some(content_length) = content_length {
+                        let mut body = self.read_body(content_length)?;
+
+                        // The Content-Length is valid if the body is followed by blank
+                        // lines and a 'From ' line or the end of the mailbox.
+                        let mut separator = Vec::new();
+                        let is_valid = body.len() as u64 == content_length
+                            && loop {
+                                line.clear();
+                                if !self.read_line(&mut line)? {
+                                    break true;
+                                } else if is_from_line(&line) {
+                                    self.message =
+                                        Message::new(std::str::from_utf8(&line).unwrap_or(""))
+                                            .into();
+                                    break true;
+                                }
+                                separator.extend_from_slice(&line);
+                                if line != b"\n" && line != b"\r\n" {
+                                    break false;
+                                }
+                            };
+
+                        if is_valid {
+                            if format == MboxFormat::Mboxcl {
+                                for line in body.split_inclusive(|&ch| ch == b'\n') {
+                                    unquote_line(&mut message.contents, line, MboxFormat::Mboxo);
+                                }
+                            } else {
+                                message.contents.extend_from_slice(&body);
+                            }
+                            return Ok(Some(message));
+                        }
+
+                        // Otherwise read the body again and split messages at 'From ' lines
+                        body.append(&mut separator);
+                        body.extend_from_slice(&self.pending[self.pending_pos..]);
+                        self.pending = body;
+                        self.pending_pos = 0;
+                    }
+                    continue;
+                } else if has_content_length {
+                    if let Some(value) = get_content_length(&line) {
+                        content_length = value.into();
+                        continue;
                     }
-                }
-                Err(_) => {
-                    return Some(Err(ParseError {}));
                 }
             }
 
+            unquote_line(&mut message.contents, &line, format);
+        }
+
+        // Remove the blank line separating messages
+        if message.contents.ends_with(b"\n\n") || message.contents.ends_with(b"\r\n\n") {
+            message.contents.pop();
+        } else if message.contents.ends_with(b"\r\n\r\n") {
+            message.contents.truncate(message.contents.len() - 2);
+        }
+
+        Ok(Some(message))
+    }
+
+    fn next_unformatted(&mut self) -> Option<Result<Message, ParseError>> {
+        let mut message_line = Vec::with_capacity(80);
+
+        loop {
+            match self.read_line(&mut message_line) {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(err) => return Some(Err(err)),
+            }
+
             let is_from = message_line
                 .get(..5)
                 .map(|line| line == b"From ")
@@ -103,6 +273,115 @@ where
 
         self.message.take().map(Ok)
     }
+
+    /// Returns the next message along with its location in the mailbox.
+    pub(crate) fn next_entry(&mut self) -> Option<Result<(Message, IndexEntry), ParseError>> {
+        let message = match if let Some(format) = self.format {
+            self.next_message(format).transpose()
+        } else {
+            self.next_unformatted()
+        }? {
+            Ok(mut message) => {
+                message.parse_status();
+                message
+            }
+            Err(err) => return Some(Err(err)),
+        };
+
+        // Messages span from their 'From ' line to the next one
+        let (offset, from_line) = self.from_lines.pop_front().unwrap_or_default();
+        let end = self
+            .from_lines
+            .front()
+            .map_or_else(|| self.bytes_consumed(), |(offset, _)| *offset);
+
+        let entry = IndexEntry {
+            offset,
+            length: end - offset,
+            from_line: String::from_utf8_lossy(&from_line).trim_end().to_string(),
+            internal_date: message.internal_date,
+        };
+
+        Some(Ok((message, entry)))
+    }
+
+    fn read_line(&mut self, line: &mut Vec<u8>) -> Result<bool, ParseError> {
+        let start = line.len();
+        let bytes_read = if self.pending_pos < self.pending.len() {
+            let pending = &self.pending[self.pending_pos..];
+            let bytes_read = pending
+                .iter()
+                .position(|&ch| ch == b'\n')
+                .map_or(pending.len(), |pos| pos + 1);
+            line.extend_from_slice(&pending[..bytes_read]);
+            self.pending_pos += bytes_read;
+            bytes_read
+        } else {
+            self.reader
+                .read_until(b'\n', line)
+                .map_err(|_| ParseError {})?
+        };
+
+        if is_from_line(&line[start..]) {
+            self.from_lines.push_back((
+                self.bytes_consumed() - bytes_read as u64,
+                line[start..].to_vec(),
+            ));
+        }
+
+        Ok(bytes_read > 0)
+    }
+
+    // Reads up to `length` bytes, fewer at the end of the mailbox
+    fn read_body(&mut self, length: u64) -> Result<Vec<u8>, ParseError> {
+        let pending = &self.pending[self.pending_pos..];
+        let from_pending = std::cmp::min(pending.len() as u64, length) as usize;
+        let mut body = Vec::with_capacity(std::cmp::min(length as usize, 1 << 20));
+        body.extend_from_slice(&pending[..from_pending]);
+        self.pending_pos += from_pending;
+
+        (&mut self.reader)
+            .take(length - from_pending as u64)
+            .read_to_end(&mut body)
+            .map_err(|_| ParseError {})?;
+        Ok(body)
+    }
+
+    /// Returns the number of bytes consumed from the reader.
+    pub(crate) fn bytes_consumed(&self) -> u64 {
+        self.reader.get_ref().bytes_read
+            - self.reader.buffer().len() as u64
+            - (self.pending.len() - self.pending_pos) as u64
+    }
+}
+
+impl<T> Iterator for MessageIterator<T>
+where
+    T: Read,
+{
+    type Item = Result<Message, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry()
+            .map(|result| result.map(|(message, _)| message))
+    }
+}
+
+impl<T: Read> ByteCounter<T> {
+    fn new(reader: T) -> Self {
+        ByteCounter {
+            reader,
+            bytes_read: 0,
+        }
+    }
+}
+
+impl<T: Read> Read for ByteCounter<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.reader.read(buf)?;
+        self.bytes_read += bytes_read as u64;
+        Ok(bytes_read)
+    }
 }
 
 impl Message {
@@ -197,10 +476,143 @@ impl Message {
         Self {
             internal_date,
             from,
+            flags: Vec::new(),
+            keywords: Vec::new(),
+            labels: Vec::new(),
+            is_recent: true,
             contents: Vec::with_capacity(1024),
         }
     }
 
+    /// Builds a message read from another mailbox format, the flags and keywords
+    /// found in its headers are added to the ones provided.
+    pub(crate) fn from_parts(
+        from_line: &str,
+        contents: Vec<u8>,
+        flags: Vec<Flag>,
+        keywords: Vec<String>,
+    ) -> Self {
+        let mut message = Message::new(from_line);
+        message.contents = contents;
+        message.flags = flags;
+        message.keywords = keywords;
+        message.parse_status();
+        message
+    }
+
+    /// Obtains the message flags, keywords and labels from the Status, X-Status,
+    /// X-Mozilla-Status, X-Mozilla-Status2, X-Mozilla-Keys, X-Keywords and
+    /// X-Gmail-Labels headers.
+    fn parse_status(&mut self) {
+        let mut headers: Vec<(&[u8], String)> = Vec::new();
+        for line in self.contents.split(|&ch| ch == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.is_empty() {
+                break;
+            } else if line[0] == b' ' || line[0] == b'\t' {
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push_str(&String::from_utf8_lossy(line));
+                }
+            } else if let Some(colon) = line.iter().position(|&ch| ch == b':') {
+                headers.push((
+                    &line[..colon],
+                    String::from_utf8_lossy(&line[colon + 1..]).into_owned(),
+                ));
+            }
+        }
+
+        let mut flags = Vec::new();
+        let mut keywords = Vec::new();
+        let mut labels = Vec::new();
+
+        for (name, value) in headers {
+            let value = value.trim();
+
+            if name.eq_ignore_ascii_case(b"Status") || name.eq_ignore_ascii_case(b"X-Status") {
+                for ch in value.chars() {
+                    flags.push(match ch {
+                        // Old messages have been seen by a mail reader, but not read
+                        'O' => {
+                            self.is_recent = false;
+                            continue;
+                        }
+                        'R' => Flag::Seen,
+                        'A' => Flag::Replied,
+                        'F' => Flag::Flagged,
+                        'D' => Flag::Trashed,
+                        'T' => Flag::Draft,
+                        _ => continue,
+                    });
+                }
+            } else if name.eq_ignore_ascii_case(b"X-Mozilla-Status") {
+                let status = u32::from_str_radix(value, 16).unwrap_or(0);
+                for (mask, flag) in [
+                    (0x0001, Flag::Seen),
+                    (0x0002, Flag::Replied),
+                    (0x0004, Flag::Flagged),
+                    (0x0008, Flag::Trashed),
+                    (0x1000, Flag::Passed),
+                ] {
+                    if status & mask != 0 {
+                        flags.push(flag);
+                    }
+                }
+            } else if name.eq_ignore_ascii_case(b"X-Mozilla-Status2") {
+                let status = u32::from_str_radix(value, 16).unwrap_or(0);
+                if status & 0x0020_0000 != 0 {
+                    flags.push(Flag::Trashed);
+                }
+                let label = (status >> 25) & 0x07;
+                if label != 0 {
+                    keywords.push(format!("$label{}", label));
+                }
+            } else if name.eq_ignore_ascii_case(b"X-Mozilla-Keys")
+                || name.eq_ignore_ascii_case(b"X-Keywords")
+            {
+                for keyword in value.split(|ch: char| ch.is_whitespace() || ch == ',') {
+                    if !keyword.is_empty() {
+                        keywords.push(keyword.to_string());
+                    }
+                }
+            } else if name.eq_ignore_ascii_case(b"X-Gmail-Labels") {
+                let mut label = String::new();
+                let mut in_quotes = false;
+                for ch in value.chars().chain([',']) {
+                    match ch {
+                        '"' => in_quotes = !in_quotes,
+                        ',' if !in_quotes => {
+                            let label = std::mem::take(&mut label);
+                            let label = label.trim();
+                            if !label.is_empty() {
+                                match label {
+                                    "Opened" => flags.push(Flag::Seen),
+                                    "Starred" => flags.push(Flag::Flagged),
+                                    "Trash" => flags.push(Flag::Trashed),
+                                    "Draft" | "Drafts" => flags.push(Flag::Draft),
+                                    _ => (),
+                                }
+                                labels.push(label.to_string());
+                            }
+                        }
+                        _ => label.push(ch),
+                    }
+                }
+            }
+        }
+
+        for flag in flags {
+            if !self.flags.contains(&flag) {
+                self.flags.push(flag);
+            }
+        }
+        for keyword in keywords {
+            if !self.keywords.contains(&keyword) {
+                self.keywords.push(keyword);
+            }
+        }
+        self.labels = labels;
+    }
+
     /// Returns the message creation date in UTC seconds since UNIX epoch
     pub fn internal_date(&self) -> u64 {
         self.internal_date
@@ -211,6 +623,27 @@ impl Message {
         &self.from
     }
 
+    /// Returns the message flags
+    pub fn flags(&self) -> &[Flag] {
+        &self.flags
+    }
+
+    /// Returns the message keywords
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+
+    /// Returns the Gmail labels of the message
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Returns `false` if the message is marked as old with the `O` letter of
+    /// the Status or X-Status headers
+    pub fn is_recent(&self) -> bool {
+        self.is_recent
+    }
+
     /// Returns the message contents
     pub fn contents(&self) -> &[u8] {
         &self.contents
@@ -222,9 +655,253 @@ impl Message {
     }
 }
 
+impl MboxFormat {
+    /// Guesses the Mbox format from the beginning of a mailbox.
+    ///
+    /// A `Content-Length` header that points to the next `From ` line indicates
+    /// `Mboxcl2`, or `Mboxcl` when the bodies contain `>From ` but no `From `
+    /// lines. Otherwise, `>>From ` lines indicate `Mboxrd` and `>From ` lines
+    /// `Mboxo`. Defaults to `Mboxrd`, which reads unquoted mailboxes correctly.
+    pub fn detect(data: &[u8]) -> MboxFormat {
+        let mut pos = 0;
+        let mut has_from = false;
+        let mut has_quoted_from = false;
+        let mut has_content_length = false;
+
+        // Find the first 'From ' line
+        while pos < data.len() && !is_from_line(&data[pos..]) {
+            pos = next_line(data, pos);
+        }
+
+        // Look for valid Content-Length headers
+        while pos < data.len() {
+            pos = next_line(data, pos);
+            let mut content_length = None;
+            while pos < data.len() {
+                let line = &data[pos..next_line(data, pos)];
+                pos += line.len();
+                if line == b"\n" || line == b"\r\n" {
+                    break;
+                } else if let Some(value) = get_content_length(line) {
+                    content_length = value.into();
+                }
+            }
+
+            let body_end = match content_length {
+                Some(content_length) => pos.saturating_add(content_length as usize),
+                None => break,
+            };
+            for line in
+                data[pos..std::cmp::min(body_end, data.len())].split_inclusive(|&ch| ch == b'\n')
+            {
+                has_from |= is_from_line(line);
+                has_quoted_from |= line.starts_with(b">From ");
+            }
+
+            // Skip separator lines
+            pos = body_end;
+            while pos < data.len() && (data[pos] == b'\n' || data[pos..].starts_with(b"\r\n")) {
+                pos = next_line(data, pos);
+            }
+            if pos < data.len() && !is_from_line(&data[pos..]) {
+                break;
+            }
+            has_content_length = true;
+        }
+
+        if has_content_length {
+            return if has_quoted_from && !has_from {
+                MboxFormat::Mboxcl
+            } else {
+                MboxFormat::Mboxcl2
+            };
+        }
+
+        let mut has_quoted_from = false;
+        for line in data.split(|&ch| ch == b'\n') {
+            if line.starts_with(b">") && is_quoted_from_line(line) {
+                if line.starts_with(b">>") {
+                    return MboxFormat::Mboxrd;
+                }
+                has_quoted_from = true;
+            }
+        }
+
+        if has_quoted_from {
+            MboxFormat::Mboxo
+        } else {
+            MboxFormat::Mboxrd
+        }
+    }
+}
+
+impl<T> MessageWriter<T>
+where
+    T: Write,
+{
+    /// Creates a new writer for the specified Mbox format.
+    pub fn new(writer: T, format: MboxFormat) -> MessageWriter<T> {
+        MessageWriter { writer, format }
+    }
+
+    /// Appends a message to the mailbox. The `From ` line is built from the
+    /// sender address and the internal date in seconds since UNIX epoch.
+    ///
+    /// Reading the mailbox with `MessageIterator::with_format` returns the same
+    /// contents, except that:
+    ///
+    /// - `Mboxo` and `Mboxcl` read `>From ` lines back as `From `.
+    /// - `Mboxcl` and `Mboxcl2` replace any `Content-Length` header with their
+    ///   own, which is removed when reading, and end headers with a blank line.
+    /// - `Mboxo` and `Mboxrd` add a line break to contents that do not end with one.
+    pub fn write_message(
+        &mut self,
+        from: &str,
+        internal_date: u64,
+        contents: &[u8],
+    ) -> io::Result<()> {
+        // Write 'From ' line
+        let from = from
+            .chars()
+            .filter(|ch| !ch.is_whitespace())
+            .collect::<String>();
+        writeln!(
+            self.writer,
+            "From {} {}",
+            if !from.is_empty() {
+                from.as_str()
+            } else {
+                "MAILER-DAEMON"
+            },
+            to_asctime(internal_date)
+        )?;
+
+        let quote_format = match self.format {
+            MboxFormat::Mboxo | MboxFormat::Mboxcl => Some(MboxFormat::Mboxo),
+            MboxFormat::Mboxrd => Some(MboxFormat::Mboxrd),
+            MboxFormat::Mboxcl2 => None,
+        };
+        let mut lines = contents.split_inclusive(|&ch| ch == b'\n');
+
+        if matches!(self.format, MboxFormat::Mboxcl | MboxFormat::Mboxcl2) {
+            // Write headers, replacing any existing Content-Length header
+            let mut eol: &[u8] = b"\n";
+            for line in lines.by_ref() {
+                if line == b"\n" || line == b"\r\n" {
+                    eol = line;
+                    break;
+                } else if get_content_length(line).is_none() {
+                    self.writer.write_all(line)?;
+                    if !line.ends_with(b"\n") {
+                        self.writer.write_all(b"\n")?;
+                    }
+                }
+            }
+
+            let mut body = Vec::with_capacity(contents.len());
+            for line in lines {
+                quote_line(&mut body, line, quote_format)?;
+            }
+
+            write!(self.writer, "Content-Length: {}", body.len())?;
+            self.writer.write_all(eol)?;
+            self.writer.write_all(eol)?;
+            self.writer.write_all(&body)?;
+        } else {
+            for line in lines {
+                quote_line(&mut self.writer, line, quote_format)?;
+            }
+            if !contents.is_empty() && !contents.ends_with(b"\n") {
+                self.writer.write_all(b"\n")?;
+            }
+        }
+
+        // Write blank separator line
+        self.writer.write_all(b"\n")
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> T {
+        self.writer
+    }
+}
+
+fn next_line(data: &[u8], pos: usize) -> usize {
+    data[pos..]
+        .iter()
+        .position(|&ch| ch == b'\n')
+        .map_or(data.len(), |end| pos + end + 1)
+}
+
+pub(crate) fn is_from_line(line: &[u8]) -> bool {
+    line.starts_with(b"From ")
+}
+
+/// Returns `true` if the line matches `>*From `.
+fn is_quoted_from_line(line: &[u8]) -> bool {
+    line.iter()
+        .position(|&ch| ch != b'>')
+        .is_some_and(|pos| line[pos..].starts_with(b"From "))
+}
+
+fn quote_line(writer: &mut impl Write, line: &[u8], format: Option<MboxFormat>) -> io::Result<()> {
+    let needs_quote = match format {
+        Some(MboxFormat::Mboxrd) => is_quoted_from_line(line),
+        Some(_) => is_from_line(line),
+        None => false,
+    };
+    if needs_quote {
+        writer.write_all(b">")?;
+    }
+    writer.write_all(line)
+}
+
+fn unquote_line(contents: &mut Vec<u8>, line: &[u8], format: MboxFormat) {
+    let is_quoted = match format {
+        MboxFormat::Mboxrd => line.first() == Some(&b'>') && is_quoted_from_line(line),
+        MboxFormat::Mboxo | MboxFormat::Mboxcl => line.starts_with(b">From "),
+        MboxFormat::Mboxcl2 => false,
+    };
+    contents.extend_from_slice(if is_quoted { &line[1..] } else { line });
+}
+
+fn get_content_length(line: &[u8]) -> Option<u64> {
+    let value = line
+        .get(..15)
+        .filter(|name| name.eq_ignore_ascii_case(b"Content-Length:"))
+        .map(|_| &line[15..])?;
+    std::str::from_utf8(value).ok()?.trim().parse().ok()
+}
+
+/// Returns a date in `asctime` format, such as `Sat Jan  3 01:05:34 1996`.
+fn to_asctime(timestamp: u64) -> String {
+    let dt = DateTime::from_timestamp(timestamp as i64);
+    format!(
+        "{} {} {:2} {:02}:{:02}:{:02} {}",
+        ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"][(timestamp / 86400 % 7) as usize],
+        ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"]
+            [(dt.month as usize).saturating_sub(1) % 12],
+        dt.day,
+        dt.hour,
+        dt.minute,
+        dt.second,
+        dt.year
+    )
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::mailbox::mbox::Message;
+    use std::{fs, path::PathBuf};
+
+    use crate::mailbox::{
+        mbox::{MboxFormat, Message, MessageWriter},
+        Flag,
+    };
 
     use super::MessageIterator;
 
@@ -253,21 +930,37 @@ Message 4
             Message {
                 internal_date: 820631134,
                 from: "god@heaven.af.mil".to_string(),
+                flags: vec![],
+                keywords: vec![],
+                labels: vec![],
+                is_recent: true,
                 contents: b"Message 1\n\n".to_vec(),
             },
             Message {
                 internal_date: 1027453163,
                 from: "cras@irccrew.org".to_string(),
+                flags: vec![],
+                keywords: vec![],
+                labels: vec![],
+                is_recent: true,
                 contents: b"Message 2\n\n".to_vec(),
             },
             Message {
                 internal_date: 1028640874,
                 from: "test@test.com".to_string(),
+                flags: vec![],
+                keywords: vec![],
+                labels: vec![],
+                is_recent: true,
                 contents: b"Message 3\nFrom hello\n>From world\n>>From test\n\n".to_vec(),
             },
             Message {
                 internal_date: 1516030200,
                 from: "other@domain.com".to_string(),
+                flags: vec![],
+                keywords: vec![],
+                labels: vec![],
+                is_recent: true,
                 contents: b"Message 4\n> From\n>F\n".to_vec(),
             },
         ];
@@ -275,5 +968,249 @@ Message 4
         for (message, expected_messages) in parser.zip(expected_messages) {
             assert_eq!(message.unwrap(), expected_messages);
         }
+
+        // Status flags, keywords and labels
+        let message = br#"From user@domain.com Sat Jan  3 01:05:34 1996
+Status: RO
+X-Status: AF
+Subject: Flags
+
+From user@domain.com Sat Jan  3 01:05:34 1996
+X-Mozilla-Status: 1009
+X-Mozilla-Status2: 04200000
+X-Mozilla-Keys: $label1 custom
+Subject: Thunderbird
+
+From user@domain.com Sat Jan  3 01:05:34 1996
+X-Gmail-Labels: Inbox,Opened,Starred,"Work, Projects",
+ Category Updates
+Subject: Gmail
+
+"#;
+
+        let messages = MessageIterator::new(&message[..])
+            .map(|message| message.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages[0].flags(),
+            &[Flag::Seen, Flag::Replied, Flag::Flagged]
+        );
+        assert!(!messages[0].is_recent());
+        assert!(messages[1].is_recent());
+        assert_eq!(
+            messages[1].flags(),
+            &[Flag::Seen, Flag::Trashed, Flag::Passed]
+        );
+        assert_eq!(messages[1].keywords(), &["$label2", "$label1", "custom"]);
+        assert_eq!(messages[2].flags(), &[Flag::Seen, Flag::Flagged]);
+        assert_eq!(
+            messages[2].labels(),
+            &[
+                "Inbox",
+                "Opened",
+                "Starred",
+                "Work, Projects",
+                "Category Updates"
+            ]
+        );
+    }
+
+    #[test]
+    fn write_mbox() {
+        let messages = [
+            (
+                "god@heaven.af.mil",
+                820631134,
+                &b"Subject: Message 1\n\nFrom the beginning\n\n>From quoted\n"[..],
+            ),
+            (
+                "",
+                1027453163,
+                &b"Subject: Message 2\r\nContent-Length: 1234\r\n\r\n>>From here\r\nFrom there\r\n"
+                    [..],
+            ),
+            ("test@test.com", 1028640874, &b"Subject: Message 3\n\n"[..]),
+            ("", 0, &b"Subject: Message 4\r\n\r\nNo line break"[..]),
+            ("", 0, &b"Subject: Message 5"[..]),
+        ];
+
+        for format in [
+            MboxFormat::Mboxo,
+            MboxFormat::Mboxrd,
+            MboxFormat::Mboxcl,
+            MboxFormat::Mboxcl2,
+        ] {
+            let mut writer = MessageWriter::new(Vec::new(), format);
+            for (from, internal_date, contents) in messages {
+                writer.write_message(from, internal_date, contents).unwrap();
+            }
+            let mbox = writer.into_inner();
+
+            let mut parser = MessageIterator::with_format(&mbox[..], format);
+            for (from, internal_date, contents) in messages {
+                let message = parser.next().unwrap().unwrap();
+                assert_eq!(
+                    message.from(),
+                    if !from.is_empty() {
+                        from
+                    } else {
+                        "MAILER-DAEMON"
+                    }
+                );
+                assert_eq!(message.internal_date(), internal_date);
+
+                // Mboxo can't tell quoted and unquoted '>From ' lines apart,
+                // and Content-Length headers are reserved for the mailbox.
+                let mut expected = String::from_utf8_lossy(contents).into_owned();
+                if matches!(format, MboxFormat::Mboxcl | MboxFormat::Mboxcl2) {
+                    expected = expected.replace("Content-Length: 1234\r\n", "");
+                    if !expected.contains("\n\n") && !expected.contains("\r\n\r\n") {
+                        expected.push_str("\n\n");
+                    }
+                } else if !expected.ends_with('\n') {
+                    expected.push('\n');
+                }
+                if matches!(format, MboxFormat::Mboxo | MboxFormat::Mboxcl) {
+                    expected = expected
+                        .split_inclusive('\n')
+                        .map(|line| {
+                            line.strip_prefix('>')
+                                .filter(|line| line.starts_with("From "))
+                                .unwrap_or(line)
+                        })
+                        .collect();
+                }
+                assert_eq!(
+                    String::from_utf8_lossy(message.contents()),
+                    expected,
+                    "{:?}",
+                    format
+                );
+            }
+            assert!(parser.next().is_none());
+        }
+
+        let mut writer = MessageWriter::new(Vec::new(), MboxFormat::Mboxcl2);
+        writer
+            .write_message("john@doe.com", 0, b"Subject: Hi\n\nFrom me\n")
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            concat!(
+                "From john@doe.com Thu Jan  1 00:00:00 1970\n",
+                "Subject: Hi\n",
+                "Content-Length: 8\n",
+                "\n",
+                "From me\n",
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn detect_mbox_format() {
+        let mut test_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_dir.push("tests");
+        test_dir.push("mbox");
+
+        for (file_name, expected_format) in [
+            ("mboxo.mbox", MboxFormat::Mboxo),
+            ("mboxrd.mbox", MboxFormat::Mboxrd),
+            ("mboxcl.mbox", MboxFormat::Mboxcl),
+            ("mboxcl2.mbox", MboxFormat::Mboxcl2),
+        ] {
+            let mbox = fs::read(test_dir.join(file_name)).unwrap();
+            let parser = MessageIterator::with_auto_detect(&mbox[..]);
+            assert_eq!(
+                parser.detected_format(),
+                Some(expected_format),
+                "{}",
+                file_name
+            );
+
+            let messages = parser.map(|message| message.unwrap()).collect::<Vec<_>>();
+            assert_eq!(messages.len(), 3, "{}", file_name);
+            assert_eq!(messages[0].from(), "alice@example.com");
+            assert_eq!(messages[1].internal_date(), 1516093200);
+            assert_eq!(
+                String::from_utf8_lossy(messages[0].contents()),
+                concat!(
+                    "From: alice@example.com\n",
+                    "Subject: First\n",
+                    "\n",
+                    "Hello,\n",
+                    "From now on we meet on Mondays.\n",
+                    ">From the minutes\n"
+                )
+                .replacen(
+                    ">From",
+                    if expected_format == MboxFormat::Mboxo || expected_format == MboxFormat::Mboxcl
+                    {
+                        "From"
+                    } else {
+                        ">From"
+                    },
+                    1
+                ),
+                "{}",
+                file_name
+            );
+            assert!(
+                messages[1]
+                    .contents()
+                    .ends_with(b"\n\nFrom the desk of Bob.\n\n"),
+                "{}",
+                file_name
+            );
+            assert!(
+                messages[2].contents().ends_with(b"\n\nBye.\n"),
+                "{}",
+                file_name
+            );
+        }
+
+        // Invalid Content-Length headers are ignored
+        let mbox = concat!(
+            "From alice@example.com Mon Jan 15 15:30:00 2018\n",
+            "Content-Length: 5\n",
+            "\n",
+            "Hello\n",
+            "World\n",
+            "\n",
+            "From bob@example.com Tue Jan 16 09:00:00 2018\n",
+            "Content-Length: 6\n",
+            "\n",
+            "Hello\n",
+            "\n",
+        );
+        assert_eq!(MboxFormat::detect(mbox.as_bytes()), MboxFormat::Mboxrd);
+        let messages = MessageIterator::with_format(mbox.as_bytes(), MboxFormat::Mboxcl2)
+            .map(|message| message.unwrap().unwrap_contents())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![b"\nHello\nWorld\n".to_vec(), b"\nHello\n".to_vec()]
+        );
+
+        // An oversized Content-Length does not swallow the next messages
+        let mbox = concat!(
+            "From alice@example.com Mon Jan 15 15:30:00 2018\n",
+            "Content-Length: 40\n",
+            "\n",
+            "Hello\n",
+            "\n",
+            "From bob@example.com Tue Jan 16 09:00:00 2018\n",
+            "\n",
+            "World\n",
+            "\n",
+        );
+        let mut parser = MessageIterator::with_format(mbox.as_bytes(), MboxFormat::Mboxcl2);
+        let message = parser.next().unwrap().unwrap();
+        assert_eq!(message.from(), "alice@example.com");
+        assert_eq!(message.contents(), b"\nHello\n");
+        let message = parser.next().unwrap().unwrap();
+        assert_eq!(message.from(), "bob@example.com");
+        assert_eq!(message.contents(), b"\nWorld\n");
+        assert!(parser.next().is_none());
     }
 }
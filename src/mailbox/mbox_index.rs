@@ -0,0 +1,302 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+
+use super::mbox::{MboxFormat, Message, MessageIterator, ParseError};
+
+const INDEX_HEADER: &str = "mbox-index 1";
+
+/// Byte offsets of the messages in an Mbox mailbox, used to read any message
+/// without scanning the whole mailbox.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MboxIndex {
+    format: MboxFormat,
+    size: u64,
+    entries: Vec<IndexEntry>,
+}
+
+/// Location and metadata of a message in an Mbox mailbox
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct IndexEntry {
+    /// Offset of the message `From ` line
+    pub offset: u64,
+    /// Length of the message, including its `From ` line and separator
+    pub length: u64,
+    /// The `From ` line, without the line ending
+    pub from_line: String,
+    /// Message creation date in seconds since UNIX epoch
+    pub internal_date: u64,
+}
+
+impl MboxIndex {
+    /// Indexes all the messages in an Mbox mailbox.
+    pub fn build(reader: impl Read, format: MboxFormat) -> Result<MboxIndex, ParseError> {
+        let mut index = MboxIndex {
+            format,
+            size: 0,
+            entries: Vec::new(),
+        };
+        index.index_from(reader, 0)?;
+        Ok(index)
+    }
+
+    /// Indexes the messages appended to the mailbox since the index was
+    /// last updated, returning the number of new messages. The last indexed
+    /// message is indexed again as its length might have changed. If the
+    /// mailbox is smaller than when it was indexed, it is indexed from the start.
+    pub fn update<R: Read + Seek>(&mut self, reader: &mut R) -> Result<usize, ParseError> {
+        let size = reader.seek(SeekFrom::End(0)).map_err(|_| ParseError {})?;
+        let num_entries = self.entries.len();
+        let start = if size >= self.size {
+            self.entries.pop().map_or(0, |entry| entry.offset)
+        } else {
+            self.entries.clear();
+            0
+        };
+
+        reader
+            .seek(SeekFrom::Start(start))
+            .map_err(|_| ParseError {})?;
+        self.index_from(reader, start)?;
+
+        Ok(self.entries.len().saturating_sub(num_entries))
+    }
+
+    fn index_from(&mut self, reader: impl Read, start: u64) -> Result<(), ParseError> {
+        let mut it = MessageIterator::with_format(reader, self.format);
+        while let Some(result) = it.next_entry() {
+            let (_, mut entry) = result?;
+            entry.offset += start;
+            self.entries.push(entry);
+        }
+        self.size = start + it.bytes_consumed();
+        Ok(())
+    }
+
+    /// Reads the message at the specified index entry.
+    pub fn read_message<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        entry: &IndexEntry,
+    ) -> Result<Message, ParseError> {
+        let mut contents = Vec::with_capacity(entry.length as usize);
+        reader
+            .seek(SeekFrom::Start(entry.offset))
+            .and_then(|_| reader.take(entry.length).read_to_end(&mut contents))
+            .map_err(|_| ParseError {})?;
+
+        MessageIterator::with_format(&contents[..], self.format)
+            .next()
+            .unwrap_or(Err(ParseError {}))
+    }
+
+    /// Returns the index entry of the message at the specified position.
+    pub fn get(&self, pos: usize) -> Option<&IndexEntry> {
+        self.entries.get(pos)
+    }
+
+    /// Returns all index entries
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// Returns the number of indexed messages
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no indexed messages
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the Mbox format of the indexed mailbox
+    pub fn format(&self) -> MboxFormat {
+        self.format
+    }
+
+    /// Returns the size of the mailbox when it was last indexed
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Writes the index in a text format that can be loaded with `read_from`.
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{} {} {}",
+            INDEX_HEADER,
+            match self.format {
+                MboxFormat::Mboxo => "mboxo",
+                MboxFormat::Mboxrd => "mboxrd",
+                MboxFormat::Mboxcl => "mboxcl",
+                MboxFormat::Mboxcl2 => "mboxcl2",
+            },
+            self.size
+        )?;
+        for entry in &self.entries {
+            writeln!(
+                writer,
+                "{} {} {} {}",
+                entry.offset, entry.length, entry.internal_date, entry.from_line
+            )?;
+        }
+        writer.flush()
+    }
+
+    /// Loads an index written with `write_to`.
+    pub fn read_from(reader: impl Read) -> io::Result<MboxIndex> {
+        let invalid_data = || io::Error::new(io::ErrorKind::InvalidData, "Invalid Mbox index.");
+        let mut lines = BufReader::new(reader).lines();
+
+        let header = lines.next().ok_or_else(invalid_data)??;
+        let (format, size) = header
+            .strip_prefix(INDEX_HEADER)
+            .and_then(|header| header.trim().split_once(' '))
+            .ok_or_else(invalid_data)?;
+        let mut index = MboxIndex {
+            format: match format {
+                "mboxo" => MboxFormat::Mboxo,
+                "mboxrd" => MboxFormat::Mboxrd,
+                "mboxcl" => MboxFormat::Mboxcl,
+                "mboxcl2" => MboxFormat::Mboxcl2,
+                _ => return Err(invalid_data()),
+            },
+            size: size.parse().map_err(|_| invalid_data())?,
+            entries: Vec::new(),
+        };
+
+        for line in lines {
+            let line = line?;
+            let mut parts = line.splitn(4, ' ');
+            let mut next_number = || -> io::Result<u64> {
+                parts
+                    .next()
+                    .and_then(|part| part.parse().ok())
+                    .ok_or_else(invalid_data)
+            };
+            index.entries.push(IndexEntry {
+                offset: next_number()?,
+                length: next_number()?,
+                internal_date: next_number()?,
+                from_line: parts.next().unwrap_or_default().to_string(),
+            });
+        }
+
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor, path::PathBuf};
+
+    use crate::mailbox::mbox::{MboxFormat, MessageIterator, MessageWriter};
+
+    use super::MboxIndex;
+
+    #[test]
+    fn index_mbox() {
+        let mut test_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_dir.push("tests");
+        test_dir.push("mbox");
+
+        for (file_name, format) in [
+            ("mboxo.mbox", MboxFormat::Mboxo),
+            ("mboxrd.mbox", MboxFormat::Mboxrd),
+            ("mboxcl.mbox", MboxFormat::Mboxcl),
+            ("mboxcl2.mbox", MboxFormat::Mboxcl2),
+        ] {
+            let mbox = fs::read(test_dir.join(file_name)).unwrap();
+            let index = MboxIndex::build(&mbox[..], format).unwrap();
+            let messages = MessageIterator::with_format(&mbox[..], format)
+                .map(|message| message.unwrap())
+                .collect::<Vec<_>>();
+
+            assert_eq!(index.len(), messages.len(), "{}", file_name);
+            assert_eq!(index.size(), mbox.len() as u64);
+            assert_eq!(index.get(0).unwrap().offset, 0);
+            assert_eq!(
+                index.get(1).unwrap().from_line,
+                "From bob@example.com Tue Jan 16 09:00:00 2018"
+            );
+            assert_eq!(
+                index
+                    .entries()
+                    .iter()
+                    .map(|entry| entry.length)
+                    .sum::<u64>(),
+                mbox.len() as u64
+            );
+
+            // Read messages directly
+            let mut reader = Cursor::new(&mbox);
+            for (entry, message) in index.entries().iter().zip(&messages).rev() {
+                assert_eq!(entry.internal_date, message.internal_date());
+                assert_eq!(
+                    &index.read_message(&mut reader, entry).unwrap(),
+                    message,
+                    "{}",
+                    file_name
+                );
+            }
+
+            // Persist and reload the index
+            let mut bytes = Vec::new();
+            index.write_to(&mut bytes).unwrap();
+            assert_eq!(MboxIndex::read_from(&bytes[..]).unwrap(), index);
+        }
+
+        // Incremental indexing
+        let mut writer = MessageWriter::new(Cursor::new(Vec::new()), MboxFormat::Mboxcl2);
+        writer
+            .write_message("a@example.com", 0, b"Subject: 1\n\nFrom here\n")
+            .unwrap();
+        let mut index =
+            MboxIndex::build(&writer.into_inner().into_inner()[..], MboxFormat::Mboxcl2).unwrap();
+        assert_eq!(index.len(), 1);
+
+        let mut mbox = Vec::new();
+        let mut writer = MessageWriter::new(&mut mbox, MboxFormat::Mboxcl2);
+        for (from, subject) in [
+            ("a@example.com", "1"),
+            ("b@example.com", "2"),
+            ("c@example.com", "3"),
+        ] {
+            writer
+                .write_message(
+                    from,
+                    0,
+                    format!("Subject: {}\n\nFrom here\n", subject).as_bytes(),
+                )
+                .unwrap();
+        }
+        let mut reader = Cursor::new(&mbox);
+        assert_eq!(index.update(&mut reader).unwrap(), 2);
+        assert_eq!(
+            index,
+            MboxIndex::build(&mbox[..], MboxFormat::Mboxcl2).unwrap()
+        );
+        assert_eq!(
+            index
+                .read_message(&mut reader, index.get(2).unwrap())
+                .unwrap()
+                .contents(),
+            b"Subject: 3\n\nFrom here\n"
+        );
+
+        // Truncated mailboxes are indexed again
+        mbox.truncate(index.get(1).unwrap().offset as usize);
+        assert_eq!(index.update(&mut Cursor::new(&mbox)).unwrap(), 0);
+        assert_eq!(index.len(), 1);
+    }
+}
@@ -0,0 +1,641 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::UNIX_EPOCH,
+};
+
+pub use super::Flag;
+
+const SEQUENCES_FILE: &str = ".mh_sequences";
+
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// MH folder iterator
+pub struct FolderIterator {
+    it_stack: Vec<fs::ReadDir>,
+    name_stack: Vec<String>,
+}
+
+/// MH message iterator
+pub struct MessageIterator {
+    name: Option<String>,
+    path: PathBuf,
+    numbers: std::vec::IntoIter<u32>,
+    sequences: Sequences,
+}
+
+/// MH message contents and metadata
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+pub struct Message {
+    number: u32,
+    internal_date: u64,
+    sequences: Vec<String>,
+    contents: Vec<u8>,
+    path: PathBuf,
+}
+
+/// Named message sequences of an MH folder, as stored in its
+/// `.mh_sequences` file.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Sequences {
+    // Disjoint ranges of message numbers, keyed by their first number
+    sequences: BTreeMap<String, BTreeMap<u32, u32>>,
+}
+
+/// Adds and removes messages in an MH mailbox.
+pub struct MessageWriter {
+    path: PathBuf,
+}
+
+impl FolderIterator {
+    /// Creates a new MH folder iterator. Every directory below `path` is
+    /// returned as a folder, nested folder names are separated by `/`.
+    pub fn new(path: impl Into<PathBuf>) -> io::Result<FolderIterator> {
+        Ok(FolderIterator {
+            it_stack: vec![fs::read_dir(path.into())?],
+            name_stack: Vec::new(),
+        })
+    }
+}
+
+impl Iterator for FolderIterator {
+    type Item = io::Result<MessageIterator>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = match self.it_stack.last_mut()?.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(err)) => return Some(Err(err)),
+                None => {
+                    self.it_stack.pop();
+                    self.name_stack.pop();
+                    continue;
+                }
+            };
+
+            let path = entry.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) if !name.starts_with(['.', ',']) => name.to_string(),
+                _ => continue,
+            };
+
+            match fs::read_dir(&path) {
+                Ok(next_it) => {
+                    self.it_stack.push(next_it);
+                    self.name_stack.push(name);
+                }
+                Err(err) => return Some(Err(err)),
+            }
+
+            return Some(MessageIterator::new_(
+                &path,
+                self.name_stack.join("/").into(),
+            ));
+        }
+    }
+}
+
+impl MessageIterator {
+    /// Creates a new MH message iterator
+    pub fn new(path: impl Into<PathBuf>) -> io::Result<MessageIterator> {
+        MessageIterator::new_(&path.into(), None)
+    }
+
+    fn new_(path: &Path, name: Option<String>) -> io::Result<MessageIterator> {
+        Ok(MessageIterator {
+            name,
+            path: path.to_path_buf(),
+            numbers: message_numbers(path)?.into_iter(),
+            sequences: Sequences::load(path)?,
+        })
+    }
+
+    /// Returns the folder name
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the path to the folder
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the sequences of the folder
+    pub fn sequences(&self) -> &Sequences {
+        &self.sequences
+    }
+}
+
+impl Iterator for MessageIterator {
+    type Item = io::Result<Message>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let number = self.numbers.next()?;
+            let path = self.path.join(number.to_string());
+            let contents = match fs::read(&path) {
+                Ok(contents) => contents,
+                // The message was removed after listing the folder
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Some(Err(err)),
+            };
+            let internal_date = match fs::metadata(&path)
+                .and_then(|metadata| metadata.created().or_else(|_| metadata.modified()))
+                .and_then(|date| {
+                    date.duration_since(UNIX_EPOCH)
+                        .map(|date| date.as_secs())
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+                }) {
+                Ok(internal_date) => internal_date,
+                Err(err) => return Some(Err(err)),
+            };
+
+            return Some(Ok(Message {
+                number,
+                internal_date,
+                sequences: self
+                    .sequences
+                    .names()
+                    .filter(|name| self.sequences.contains(name, number))
+                    .map(|name| name.to_string())
+                    .collect(),
+                contents,
+                path,
+            }));
+        }
+    }
+}
+
+impl Message {
+    /// Returns the message number
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    /// Returns the message creation date in seconds since UNIX epoch
+    pub fn internal_date(&self) -> u64 {
+        self.internal_date
+    }
+
+    /// Returns the names of the sequences that include the message
+    pub fn sequences(&self) -> &[String] {
+        &self.sequences
+    }
+
+    /// Returns the message flags, derived from the `unseen`, `flagged`,
+    /// `replied` and `forwarded` sequences
+    pub fn flags(&self) -> Vec<Flag> {
+        let mut flags = Vec::new();
+        if !self.sequences.iter().any(|name| name == "unseen") {
+            flags.push(Flag::Seen);
+        }
+        for name in &self.sequences {
+            match name.as_str() {
+                "flagged" => flags.push(Flag::Flagged),
+                "replied" => flags.push(Flag::Replied),
+                "forwarded" => flags.push(Flag::Passed),
+                _ => (),
+            }
+        }
+        flags
+    }
+
+    /// Returns the path to the message file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the message contents
+    pub fn contents(&self) -> &[u8] {
+        &self.contents
+    }
+
+    /// Unwraps the message contents
+    pub fn unwrap_contents(self) -> Vec<u8> {
+        self.contents
+    }
+}
+
+impl Sequences {
+    /// Creates an empty sequence list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the `.mh_sequences` file of a folder. Returns an empty list
+    /// if the file does not exist.
+    pub fn load(folder_path: &Path) -> io::Result<Sequences> {
+        match fs::File::open(folder_path.join(SEQUENCES_FILE)) {
+            Ok(file) => Sequences::read_from(file),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Sequences::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes the `.mh_sequences` file of a folder, or removes it if
+    /// there are no sequences.
+    pub fn save(&self, folder_path: &Path) -> io::Result<()> {
+        let path = folder_path.join(SEQUENCES_FILE);
+        if self.sequences.is_empty() {
+            return match fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+
+        let tmp_path = folder_path.join(format!("{}.tmp", SEQUENCES_FILE));
+        let mut writer = io::BufWriter::new(fs::File::create(&tmp_path)?);
+        self.write_to(&mut writer)?;
+        writer.get_ref().sync_all()?;
+        fs::rename(tmp_path, path)
+    }
+
+    /// Parses the contents of a `.mh_sequences` file.
+    pub fn read_from(reader: impl Read) -> io::Result<Sequences> {
+        let mut sequences = Sequences::default();
+        let mut last_name: Option<String> = None;
+
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            let (name, ranges) = if line.starts_with([' ', '\t']) {
+                // Continuation line
+                match &last_name {
+                    Some(name) => (name.clone(), line.as_str()),
+                    None => continue,
+                }
+            } else if let Some((name, ranges)) = line.split_once(':') {
+                (name.trim().to_string(), ranges)
+            } else {
+                continue;
+            };
+
+            let numbers = sequences.sequences.entry(name.clone()).or_default();
+            for range in ranges.split_ascii_whitespace() {
+                let (from, to) = range.split_once('-').unwrap_or((range, range));
+                if let (Ok(from), Ok(to)) = (from.parse::<u32>(), to.parse::<u32>()) {
+                    if from <= to {
+                        insert_range(numbers, from, to);
+                    }
+                }
+            }
+            last_name = name.into();
+        }
+
+        sequences.sequences.retain(|_, numbers| !numbers.is_empty());
+        Ok(sequences)
+    }
+
+    /// Writes the sequences in the `.mh_sequences` format.
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        for (name, numbers) in &self.sequences {
+            write!(writer, "{}:", name)?;
+            for (&from, &to) in numbers {
+                if from == to {
+                    write!(writer, " {}", from)?;
+                } else {
+                    write!(writer, " {}-{}", from, to)?;
+                }
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+
+    /// Returns the ranges of message numbers in a sequence.
+    pub fn get<'y>(&'y self, name: &str) -> impl Iterator<Item = RangeInclusive<u32>> + 'y {
+        self.sequences
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|(&from, &to)| from..=to)
+    }
+
+    /// Returns `true` if a message is in a sequence.
+    pub fn contains(&self, name: &str, number: u32) -> bool {
+        self.sequences.get(name).is_some_and(|numbers| {
+            numbers
+                .range(..=number)
+                .next_back()
+                .is_some_and(|(_, &to)| number <= to)
+        })
+    }
+
+    /// Adds a message to a sequence.
+    pub fn add(&mut self, name: &str, number: u32) {
+        insert_range(
+            self.sequences.entry(name.to_string()).or_default(),
+            number,
+            number,
+        );
+    }
+
+    /// Removes a message from a sequence.
+    pub fn remove(&mut self, name: &str, number: u32) {
+        if let Some(numbers) = self.sequences.get_mut(name) {
+            remove_number(numbers, number);
+            if numbers.is_empty() {
+                self.sequences.remove(name);
+            }
+        }
+    }
+
+    /// Removes a message from all sequences.
+    pub fn remove_message(&mut self, number: u32) {
+        for numbers in self.sequences.values_mut() {
+            remove_number(numbers, number);
+        }
+        self.sequences.retain(|_, numbers| !numbers.is_empty());
+    }
+
+    /// Returns the names of all sequences
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.sequences.keys().map(|name| name.as_str())
+    }
+
+    /// Returns `true` if there are no sequences
+    pub fn is_empty(&self) -> bool {
+        self.sequences.is_empty()
+    }
+}
+
+impl MessageWriter {
+    /// Creates a new MH writer.
+    pub fn new(path: impl Into<PathBuf>) -> MessageWriter {
+        MessageWriter { path: path.into() }
+    }
+
+    /// Returns the path of a folder, nested folder names are separated by `/`.
+    pub fn folder_path(&self, folder: &str) -> PathBuf {
+        self.path.join(folder)
+    }
+
+    /// Creates a folder if it does not exist.
+    pub fn create_folder(&self, folder: &str) -> io::Result<PathBuf> {
+        let path = self.folder_path(folder);
+        fs::create_dir_all(&path)?;
+        Ok(path)
+    }
+
+    /// Adds a message to a folder and to the specified sequences, returning
+    /// the number assigned to the message.
+    pub fn add_message(
+        &self,
+        folder: &str,
+        contents: &[u8],
+        sequences: &[&str],
+    ) -> io::Result<u32> {
+        let path = self.folder_path(folder);
+        let mut number = message_numbers(&path)?.last().copied().unwrap_or(0);
+
+        // The message is written to a temporary file first, so that readers
+        // never see partial contents
+        let tmp_path = path.join(format!(
+            ".tmp.{}.{}",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)?;
+        if let Err(err) = file.write_all(contents).and_then(|_| file.sync_all()) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+
+        // Linking fails if another process added a message with the same
+        // number, unlike a rename which would replace it
+        let number = loop {
+            number += 1;
+            match fs::hard_link(&tmp_path, path.join(number.to_string())) {
+                Ok(()) => break number,
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => (),
+                Err(err) => {
+                    let _ = fs::remove_file(&tmp_path);
+                    return Err(err);
+                }
+            }
+        };
+        fs::remove_file(&tmp_path)?;
+
+        if !sequences.is_empty() {
+            let mut folder_sequences = Sequences::load(&path)?;
+            for name in sequences {
+                folder_sequences.add(name, number);
+            }
+            folder_sequences.save(&path)?;
+        }
+
+        Ok(number)
+    }
+
+    /// Removes a message from a folder and from all sequences.
+    pub fn remove_message(&self, folder: &str, number: u32) -> io::Result<()> {
+        let path = self.folder_path(folder);
+        fs::remove_file(path.join(number.to_string()))?;
+
+        let mut sequences = Sequences::load(&path)?;
+        if sequences
+            .names()
+            .any(|name| sequences.contains(name, number))
+        {
+            sequences.remove_message(number);
+            sequences.save(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Adds or removes a message from a sequence.
+    pub fn update_sequence(
+        &self,
+        folder: &str,
+        number: u32,
+        sequence: &str,
+        is_member: bool,
+    ) -> io::Result<()> {
+        let path = self.folder_path(folder);
+        let mut sequences = Sequences::load(&path)?;
+        if sequences.contains(sequence, number) != is_member {
+            if is_member {
+                sequences.add(sequence, number);
+            } else {
+                sequences.remove(sequence, number);
+            }
+            sequences.save(&path)?;
+        }
+        Ok(())
+    }
+}
+
+// Adds a range of numbers, merging it with overlapping and adjacent ranges
+fn insert_range(numbers: &mut BTreeMap<u32, u32>, mut from: u32, mut to: u32) {
+    if let Some((&prev_from, &prev_to)) = numbers.range(..=from).next_back() {
+        if prev_to.saturating_add(1) >= from {
+            from = prev_from;
+            to = to.max(prev_to);
+        }
+    }
+    while let Some((&next_from, &next_to)) = numbers.range(from..).next() {
+        if next_from > to.saturating_add(1) {
+            break;
+        }
+        to = to.max(next_to);
+        numbers.remove(&next_from);
+    }
+    numbers.insert(from, to);
+}
+
+// Removes a number, splitting the range that contains it
+fn remove_number(numbers: &mut BTreeMap<u32, u32>, number: u32) {
+    if let Some((&from, &to)) = numbers.range(..=number).next_back() {
+        if number <= to {
+            numbers.remove(&from);
+            if from < number {
+                numbers.insert(from, number - 1);
+            }
+            if number < to {
+                numbers.insert(number + 1, to);
+            }
+        }
+    }
+}
+
+// Returns the message numbers in a folder in ascending order
+fn message_numbers(path: &Path) -> io::Result<Vec<u32>> {
+    let mut numbers = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if let Some(number) = entry
+            .file_name()
+            .to_str()
+            .filter(|name| !name.starts_with('0'))
+            .and_then(|name| name.parse::<u32>().ok())
+        {
+            if entry.file_type()?.is_file() {
+                numbers.push(number);
+            }
+        }
+    }
+    numbers.sort_unstable();
+    Ok(numbers)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{Flag, FolderIterator, MessageWriter, Sequences};
+
+    #[test]
+    fn read_write_mh() {
+        let sequences = Sequences::read_from(
+            &b"unseen: 1-3 7\nflagged: 2\ncur: 7\nlong: 10 12\n 14-15\nbogus\n"[..],
+        )
+        .unwrap();
+        assert!(sequences.contains("unseen", 2));
+        assert!(!sequences.contains("unseen", 4));
+        assert!(sequences.contains("long", 15));
+        assert!(!sequences.contains("long", 13));
+        assert_eq!(
+            sequences.get("unseen").collect::<Vec<_>>(),
+            vec![1..=3, 7..=7]
+        );
+        let mut bytes = Vec::new();
+        sequences.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "cur: 7\nflagged: 2\nlong: 10 12 14-15\nunseen: 1-3 7\n"
+        );
+
+        // Ranges are not expanded
+        let mut sequences = Sequences::read_from(&b"unseen: 5-4294967295 1-2 3\n"[..]).unwrap();
+        sequences.remove("unseen", 100);
+        sequences.add("unseen", 4);
+        assert_eq!(
+            sequences.get("unseen").collect::<Vec<_>>(),
+            vec![1..=99, 101..=u32::MAX]
+        );
+
+        let mut test_dir = std::env::temp_dir();
+        test_dir.push(format!("mail_parser_mh_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&test_dir);
+
+        let writer = MessageWriter::new(&test_dir);
+        writer.create_folder("inbox").unwrap();
+        writer.create_folder("work/projects").unwrap();
+        for (folder, contents, sequences) in [
+            ("inbox", "a\n", &["unseen"][..]),
+            ("inbox", "b\n", &[][..]),
+            ("inbox", "c\n", &["unseen", "flagged"][..]),
+            ("work/projects", "d\n", &[][..]),
+        ] {
+            writer
+                .add_message(folder, contents.as_bytes(), sequences)
+                .unwrap();
+        }
+        writer.remove_message("inbox", 1).unwrap();
+        writer.update_sequence("inbox", 2, "replied", true).unwrap();
+        writer.update_sequence("inbox", 3, "unseen", false).unwrap();
+        assert_eq!(writer.add_message("inbox", b"e\n", &[]).unwrap(), 4);
+        assert_eq!(
+            fs::read_to_string(test_dir.join("inbox").join(".mh_sequences")).unwrap(),
+            "flagged: 3\nreplied: 2\n"
+        );
+        assert_eq!(fs::read_dir(test_dir.join("inbox")).unwrap().count(), 4);
+
+        let mut folders = FolderIterator::new(&test_dir)
+            .unwrap()
+            .map(|folder| {
+                let folder = folder.unwrap();
+                (
+                    folder.name().unwrap().to_string(),
+                    folder
+                        .map(|message| {
+                            let message = message.unwrap();
+                            (message.number(), message.flags(), message.unwrap_contents())
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        folders.sort_unstable();
+        assert_eq!(
+            folders,
+            vec![
+                (
+                    "inbox".to_string(),
+                    vec![
+                        (2, vec![Flag::Seen, Flag::Replied], b"b\n".to_vec()),
+                        (3, vec![Flag::Seen, Flag::Flagged], b"c\n".to_vec()),
+                        (4, vec![Flag::Seen], b"e\n".to_vec()),
+                    ]
+                ),
+                ("work".to_string(), vec![]),
+                (
+                    "work/projects".to_string(),
+                    vec![(1, vec![Flag::Seen], b"d\n".to_vec())]
+                ),
+            ]
+        );
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::io::{BufRead, BufReader, Read};
+
+use super::mbox::{is_from_line, Message, ParseError};
+
+/// Parses an MMDF mailbox from a Read stream, messages are enclosed by
+/// `^A^A^A^A` separator lines.
+pub struct MessageIterator<T: Read> {
+    reader: BufReader<T>,
+}
+
+impl<T> MessageIterator<T>
+where
+    T: Read,
+{
+    /// Creates a new MMDF message iterator
+    pub fn new(reader: T) -> MessageIterator<T> {
+        MessageIterator {
+            reader: BufReader::new(reader),
+        }
+    }
+}
+
+impl<T> Iterator for MessageIterator<T>
+where
+    T: Read,
+{
+    type Item = Result<Message, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = Vec::with_capacity(80);
+        let mut contents: Option<Vec<u8>> = None;
+
+        loop {
+            line.clear();
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(_) => return Some(Err(ParseError {})),
+            }
+
+            if is_separator(&line) {
+                if let Some(contents) = contents.take() {
+                    return Some(Ok(build_message(contents)));
+                }
+                contents = Vec::with_capacity(1024).into();
+            } else if let Some(contents) = &mut contents {
+                contents.extend_from_slice(&line);
+            }
+        }
+
+        // Messages not terminated by a separator
+        contents
+            .filter(|contents| !contents.is_empty())
+            .map(|contents| Ok(build_message(contents)))
+    }
+}
+
+fn is_separator(line: &[u8]) -> bool {
+    matches!(line, b"\x01\x01\x01\x01\n" | b"\x01\x01\x01\x01\r\n")
+}
+
+// Some writers include an mbox 'From ' line after the separator
+fn build_message(mut contents: Vec<u8>) -> Message {
+    if is_from_line(&contents) {
+        let line_end = contents
+            .iter()
+            .position(|&ch| ch == b'\n')
+            .map_or(contents.len(), |pos| pos + 1);
+        let from_line = String::from_utf8_lossy(&contents[..line_end]).into_owned();
+        contents.drain(..line_end);
+        Message::from_parts(&from_line, contents, Vec::new(), Vec::new())
+    } else {
+        Message::from_parts("", contents, Vec::new(), Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mailbox::Flag;
+
+    use super::MessageIterator;
+
+    #[test]
+    fn parse_mmdf() {
+        let mmdf = concat!(
+            "\x01\x01\x01\x01\n",
+            "From alice@example.com Mon Jan 15 10:30:00 2018\n",
+            "Status: RO\n",
+            "Subject: First\n",
+            "\n",
+            "From the first message.\n",
+            "\x01\x01\x01\x01\n",
+            "\x01\x01\x01\x01\n",
+            "Subject: Second\n",
+            "\n",
+            "Second message.\n",
+            "\x01\x01\x01\x01\n",
+        );
+
+        let messages = MessageIterator::new(mmdf.as_bytes())
+            .map(|message| message.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].from(), "alice@example.com");
+        assert_eq!(messages[0].internal_date(), 1516012200);
+        assert_eq!(messages[0].flags(), &[Flag::Seen]);
+        assert_eq!(
+            messages[0].contents(),
+            b"Status: RO\nSubject: First\n\nFrom the first message.\n"
+        );
+        assert_eq!(messages[1].from(), "");
+        assert_eq!(
+            messages[1].contents(),
+            b"Subject: Second\n\nSecond message.\n"
+        );
+    }
+}
@@ -1,2 +1,68 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+pub mod babyl;
+pub mod dovecot;
+pub mod emlx;
 pub mod maildir;
 pub mod mbox;
+pub mod mbox_index;
+pub mod mh;
+pub mod mmdf;
+pub mod pst;
+
+/// Flags of a mailbox message
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+pub enum Flag {
+    Passed,
+    Replied,
+    Seen,
+    Trashed,
+    Draft,
+    Flagged,
+}
+
+/// Special-use mailbox attributes
+/// ([RFC 6154](https://datatracker.ietf.org/doc/html/rfc6154))
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+pub enum SpecialUse {
+    Sent,
+    Drafts,
+    Trash,
+    Junk,
+}
+
+impl SpecialUse {
+    /// Detects the special use of a folder from its commonly used names.
+    pub fn from_name(name: &str) -> Option<SpecialUse> {
+        const NAMES: &[(&str, SpecialUse)] = &[
+            ("sent", SpecialUse::Sent),
+            ("sent items", SpecialUse::Sent),
+            ("sent mail", SpecialUse::Sent),
+            ("sent messages", SpecialUse::Sent),
+            ("drafts", SpecialUse::Drafts),
+            ("draft", SpecialUse::Drafts),
+            ("trash", SpecialUse::Trash),
+            ("deleted", SpecialUse::Trash),
+            ("deleted items", SpecialUse::Trash),
+            ("deleted messages", SpecialUse::Trash),
+            ("junk", SpecialUse::Junk),
+            ("junk e-mail", SpecialUse::Junk),
+            ("junk email", SpecialUse::Junk),
+            ("spam", SpecialUse::Junk),
+            ("bulk mail", SpecialUse::Junk),
+        ];
+        NAMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name.trim()))
+            .map(|(_, special_use)| *special_use)
+    }
+}
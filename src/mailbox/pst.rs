@@ -0,0 +1,2129 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+    vec,
+};
+
+use crate::{
+    decoders::{base64::encode_base64, charsets::map::get_charset_decoder},
+    Addr, DateTime, HeaderValue,
+};
+
+use super::Flag;
+
+const NID_ROOT_FOLDER: u32 = 0x122;
+const NID_TYPE_HIERARCHY_TABLE: u32 = 0x0d;
+const NID_TYPE_CONTENTS_TABLE: u32 = 0x0e;
+const NID_ATTACHMENT_TABLE: u32 = 0x671;
+const NID_RECIPIENT_TABLE: u32 = 0x692;
+
+const PT_OBJECT: u16 = 0x000d;
+
+const PR_IMPORTANCE: u16 = 0x0017;
+const PR_MESSAGE_CLASS: u16 = 0x001a;
+const PR_SUBJECT: u16 = 0x0037;
+const PR_CLIENT_SUBMIT_TIME: u16 = 0x0039;
+const PR_SENT_REPRESENTING_NAME: u16 = 0x0042;
+const PR_SENT_REPRESENTING_ADDRTYPE: u16 = 0x0064;
+const PR_SENT_REPRESENTING_EMAIL_ADDRESS: u16 = 0x0065;
+const PR_TRANSPORT_MESSAGE_HEADERS: u16 = 0x007d;
+const PR_RECIPIENT_TYPE: u16 = 0x0c15;
+const PR_SENDER_NAME: u16 = 0x0c1a;
+const PR_SENDER_ADDRTYPE: u16 = 0x0c1e;
+const PR_SENDER_EMAIL_ADDRESS: u16 = 0x0c1f;
+const PR_MESSAGE_DELIVERY_TIME: u16 = 0x0e06;
+const PR_MESSAGE_FLAGS: u16 = 0x0e07;
+const PR_BODY: u16 = 0x1000;
+const PR_HTML: u16 = 0x1013;
+const PR_INTERNET_MESSAGE_ID: u16 = 0x1035;
+const PR_INTERNET_REFERENCES: u16 = 0x1039;
+const PR_IN_REPLY_TO_ID: u16 = 0x1042;
+const PR_LAST_VERB_EXECUTED: u16 = 0x1081;
+const PR_FLAG_STATUS: u16 = 0x1090;
+const PR_DISPLAY_NAME: u16 = 0x3001;
+const PR_ADDRTYPE: u16 = 0x3002;
+const PR_EMAIL_ADDRESS: u16 = 0x3003;
+const PR_CREATION_TIME: u16 = 0x3007;
+const PR_CONTAINER_CLASS: u16 = 0x3613;
+const PR_ATTACH_DATA: u16 = 0x3701;
+const PR_ATTACH_FILENAME: u16 = 0x3704;
+const PR_ATTACH_LONG_FILENAME: u16 = 0x3707;
+const PR_ATTACH_MIME_TAG: u16 = 0x370e;
+const PR_ATTACH_CONTENT_ID: u16 = 0x3712;
+const PR_SMTP_ADDRESS: u16 = 0x39fe;
+const PR_INTERNET_CODEPAGE: u16 = 0x3fde;
+const PR_SENDER_SMTP_ADDRESS: u16 = 0x5d01;
+const PR_SENT_REPRESENTING_SMTP_ADDRESS: u16 = 0x5d02;
+const PR_LTP_ROW_ID: u16 = 0x67f2;
+
+// Upper bound on B-tree, block tree and embedded message nesting, guards
+// against loops in corrupted files.
+const MAX_DEPTH: usize = 16;
+
+/// A read-only Outlook Personal Folders (PST) or Offline Storage (OST) file,
+/// in either the Unicode or the ANSI format.
+///
+/// Files protected with permutative encoding (Outlook's "compressible
+/// encryption") are decoded transparently, files using cyclic encoding are
+/// not supported.
+///
+/// OST files with 4 KiB pages (version 36), which are written by Outlook
+/// 2013 and later and may store compressed blocks, are not supported and
+/// are rejected with [`io::ErrorKind::Unsupported`].
+pub struct PstFile<R> {
+    reader: RefCell<R>,
+    is_unicode: bool,
+    is_encrypted: bool,
+    nbt_root: u64,
+    bbt_root: u64,
+}
+
+/// Iterates the folders of a PST file depth-first, starting with the root
+/// folder.
+pub struct FolderIterator<'x, R> {
+    pst: &'x PstFile<R>,
+    stack: Vec<(u32, Option<String>)>,
+    visited: HashSet<u32>,
+}
+
+/// Iterates the messages of a PST folder.
+pub struct MessageIterator<'x, R> {
+    pst: &'x PstFile<R>,
+    name: Option<String>,
+    container_class: Option<String>,
+    messages: vec::IntoIter<u32>,
+}
+
+/// A MAPI property value
+#[derive(Debug, PartialEq, Clone)]
+pub enum PropertyValue {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    /// Number of seconds since the Unix epoch
+    Time(i64),
+    String(String),
+    Binary(Vec<u8>),
+    /// Node id of an embedded object
+    Object(u32),
+    IntegerList(Vec<i64>),
+    StringList(Vec<String>),
+    BinaryList(Vec<Vec<u8>>),
+}
+
+/// Recipient type of a PST message recipient
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RecipientType {
+    To,
+    Cc,
+    Bcc,
+}
+
+/// A message read from a PST file
+#[derive(Debug, PartialEq, Clone)]
+pub struct Message {
+    nid: u32,
+    properties: Vec<(u16, PropertyValue)>,
+    recipients: Vec<Recipient>,
+    attachments: Vec<Attachment>,
+    flags: Vec<Flag>,
+}
+
+/// A recipient of a PST message
+#[derive(Debug, PartialEq, Clone)]
+pub struct Recipient {
+    properties: Vec<(u16, PropertyValue)>,
+}
+
+/// An attachment of a PST message
+#[derive(Debug, PartialEq, Clone)]
+pub struct Attachment {
+    properties: Vec<(u16, PropertyValue)>,
+    message: Option<Box<Message>>,
+}
+
+// Node data decoded as a heap, along with the node's subnodes
+struct NodeData {
+    heap: Heap,
+    subnodes: BTreeMap<u32, (u64, u64)>,
+}
+
+struct Heap {
+    blocks: Vec<Vec<u8>>,
+    client_sig: u8,
+    user_root: u32,
+}
+
+impl PstFile<BufReader<File>> {
+    /// Opens a PST or OST file
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        PstFile::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R> PstFile<R>
+where
+    R: Read + Seek,
+{
+    /// Reads the header of a PST or OST file
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; 514];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut header)?;
+        if &header[..4] != b"!BDN" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a PST or OST file",
+            ));
+        }
+
+        let is_unicode = match le_u16(&header, 10).unwrap_or(0) {
+            14 | 15 => false,
+            23 => true,
+            36 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "OST files with 4K pages are not supported",
+                ))
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unknown PST file version",
+                ))
+            }
+        };
+        let (crypt_method, nbt_root, bbt_root) = if is_unicode {
+            (
+                header[513],
+                le_u64(&header, 224).unwrap_or(0),
+                le_u64(&header, 240).unwrap_or(0),
+            )
+        } else {
+            (
+                header[461],
+                le_u32(&header, 188).unwrap_or(0) as u64,
+                le_u32(&header, 196).unwrap_or(0) as u64,
+            )
+        };
+        let is_encrypted = match crypt_method {
+            0 => false,
+            1 => true,
+            2 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "PST files with cyclic encoding are not supported",
+                ))
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unknown PST encoding method",
+                ))
+            }
+        };
+
+        Ok(PstFile {
+            reader: RefCell::new(reader),
+            is_unicode,
+            is_encrypted,
+            nbt_root,
+            bbt_root,
+        })
+    }
+
+    /// Returns `true` if this is a Unicode PST file, `false` if it uses the
+    /// older ANSI format.
+    pub fn is_unicode(&self) -> bool {
+        self.is_unicode
+    }
+
+    /// Returns an iterator over all folders in the file
+    pub fn folders(&self) -> FolderIterator<'_, R> {
+        FolderIterator {
+            pst: self,
+            stack: vec![(NID_ROOT_FOLDER, None)],
+            visited: HashSet::new(),
+        }
+    }
+
+    /// Reads the message stored under the specified node id
+    pub fn read_message(&self, nid: u32) -> io::Result<Message> {
+        let (bid_data, bid_sub) = self.find_node(nid)?;
+        self.read_message_node(nid, bid_data, bid_sub, 0)
+    }
+
+    fn read_message_node(
+        &self,
+        nid: u32,
+        bid_data: u64,
+        bid_sub: u64,
+        depth: usize,
+    ) -> io::Result<Message> {
+        let node = self.read_node(bid_data, bid_sub)?;
+        let properties = self.read_properties(&node)?;
+
+        // Damaged recipient or attachment tables do not prevent reading
+        // the rest of the message.
+        let recipients = self
+            .read_subnode_table(&node, NID_RECIPIENT_TABLE)
+            .into_iter()
+            .map(|properties| Recipient { properties })
+            .collect();
+        let mut attachments = Vec::new();
+        if depth < MAX_DEPTH {
+            for row in self.read_subnode_table(&node, NID_ATTACHMENT_TABLE) {
+                if let Some(&(bid_data, bid_sub)) = get_integer(&row, PR_LTP_ROW_ID)
+                    .and_then(|nid| node.subnodes.get(&(nid as u32)))
+                {
+                    if let Ok(attachment) = self.read_attachment(bid_data, bid_sub, depth) {
+                        attachments.push(attachment);
+                    }
+                }
+            }
+        }
+
+        let message_flags = get_integer(&properties, PR_MESSAGE_FLAGS).unwrap_or(0);
+        let mut flags = Vec::new();
+        if message_flags & 0x01 != 0 {
+            flags.push(Flag::Seen);
+        }
+        match get_integer(&properties, PR_LAST_VERB_EXECUTED) {
+            Some(102 | 103) => flags.push(Flag::Replied),
+            Some(104) => flags.push(Flag::Passed),
+            _ => (),
+        }
+        if message_flags & 0x08 != 0 {
+            flags.push(Flag::Draft);
+        }
+        if get_integer(&properties, PR_FLAG_STATUS) == Some(2) {
+            flags.push(Flag::Flagged);
+        }
+        flags.sort_unstable();
+
+        Ok(Message {
+            nid,
+            properties,
+            recipients,
+            attachments,
+            flags,
+        })
+    }
+
+    fn read_attachment(&self, bid_data: u64, bid_sub: u64, depth: usize) -> io::Result<Attachment> {
+        let node = self.read_node(bid_data, bid_sub)?;
+        let properties = self.read_properties(&node)?;
+        let message = match get_property(&properties, PR_ATTACH_DATA) {
+            Some(PropertyValue::Object(nid)) => node
+                .subnodes
+                .get(nid)
+                .and_then(|&(bid_data, bid_sub)| {
+                    self.read_message_node(*nid, bid_data, bid_sub, depth + 1)
+                        .ok()
+                })
+                .map(Box::new),
+            _ => None,
+        };
+
+        Ok(Attachment {
+            properties,
+            message,
+        })
+    }
+
+    fn read_folder(
+        &self,
+        nid: u32,
+        parent_name: Option<String>,
+    ) -> io::Result<(MessageIterator<'_, R>, Vec<u32>)> {
+        let (bid_data, bid_sub) = self.find_node(nid)?;
+        let properties = self.read_properties(&self.read_node(bid_data, bid_sub)?)?;
+        let name = if nid != NID_ROOT_FOLDER {
+            let name = get_string(&properties, PR_DISPLAY_NAME).unwrap_or_default();
+            Some(match parent_name {
+                Some(parent_name) => format!("{}/{}", parent_name, name),
+                None => name.to_string(),
+            })
+        } else {
+            None
+        };
+
+        // Folders without a hierarchy or contents table are empty
+        let table_nids = |nid_type: u32| {
+            self.read_nid_table(nid & !0x1f | nid_type)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|row| get_integer(row, PR_LTP_ROW_ID))
+                .map(|nid| nid as u32)
+                .collect::<Vec<_>>()
+        };
+
+        Ok((
+            MessageIterator {
+                pst: self,
+                name,
+                container_class: get_string(&properties, PR_CONTAINER_CLASS)
+                    .map(|class| class.to_string()),
+                messages: table_nids(NID_TYPE_CONTENTS_TABLE).into_iter(),
+            },
+            table_nids(NID_TYPE_HIERARCHY_TABLE),
+        ))
+    }
+
+    fn read_nid_table(&self, nid: u32) -> io::Result<Vec<Vec<(u16, PropertyValue)>>> {
+        let (bid_data, bid_sub) = self.find_node(nid)?;
+        self.read_table(&self.read_node(bid_data, bid_sub)?)
+    }
+
+    fn read_subnode_table(&self, node: &NodeData, nid: u32) -> Vec<Vec<(u16, PropertyValue)>> {
+        node.subnodes
+            .get(&nid)
+            .and_then(|&(bid_data, bid_sub)| {
+                self.read_node(bid_data, bid_sub)
+                    .and_then(|node| self.read_table(&node))
+                    .ok()
+            })
+            .unwrap_or_default()
+    }
+
+    fn read_properties(&self, node: &NodeData) -> io::Result<Vec<(u16, PropertyValue)>> {
+        if node.heap.client_sig != 0xbc {
+            return Err(corrupted());
+        }
+
+        let mut properties = Vec::new();
+        for (key, value) in node.heap.bth_records(node.heap.user_root)? {
+            let id = le_u16(key, 0).ok_or_else(corrupted)?;
+            let prop_type = le_u16(value, 0).ok_or_else(corrupted)?;
+            let hnid = le_u32(value, 2).ok_or_else(corrupted)?;
+
+            // Values up to 4 bytes long are stored inline, corrupted
+            // values are skipped.
+            let bytes = match fixed_size(prop_type) {
+                Some(size) if size <= 4 => hnid.to_le_bytes()[..size].to_vec(),
+                _ if prop_type == PT_OBJECT => {
+                    if let Ok(nid) = self.object_nid(node, hnid) {
+                        properties.push((id, PropertyValue::Object(nid)));
+                    }
+                    continue;
+                }
+                _ => match self.read_hnid(node, hnid) {
+                    Ok(bytes) => bytes,
+                    Err(_) => continue,
+                },
+            };
+            if let Some(value) = PropertyValue::parse(prop_type, &bytes) {
+                properties.push((id, value));
+            }
+        }
+
+        Ok(properties)
+    }
+
+    fn read_table(&self, node: &NodeData) -> io::Result<Vec<Vec<(u16, PropertyValue)>>> {
+        let info = node
+            .heap
+            .get(node.heap.user_root)
+            .filter(|info| node.heap.client_sig == 0x7c && info.len() >= 22 && info[0] == 0x7c)
+            .ok_or_else(corrupted)?;
+        let column_count = info[1] as usize;
+        let ceb_start = le_u16(info, 6).ok_or_else(corrupted)? as usize;
+        let row_size = le_u16(info, 8).ok_or_else(corrupted)? as usize;
+        let hid_row_index = le_u32(info, 10).ok_or_else(corrupted)?;
+        let hnid_rows = le_u32(info, 14).ok_or_else(corrupted)?;
+        if row_size == 0 || ceb_start > row_size {
+            return Err(corrupted());
+        }
+
+        let mut columns = Vec::with_capacity(column_count);
+        for column in 0..column_count {
+            let pos = 22 + column * 8;
+            let tag = le_u32(info, pos).ok_or_else(corrupted)?;
+            let offset = le_u16(info, pos + 4).ok_or_else(corrupted)? as usize;
+            let size = *info.get(pos + 6).ok_or_else(corrupted)? as usize;
+            let bit = *info.get(pos + 7).ok_or_else(corrupted)? as usize;
+            columns.push((tag, offset, size, bit));
+        }
+
+        // Rows never span blocks, the row index holds the number of rows
+        let blocks = if hnid_rows == 0 {
+            Vec::new()
+        } else if hnid_rows & 0x1f == 0 {
+            vec![node.heap.get(hnid_rows).ok_or_else(corrupted)?.to_vec()]
+        } else {
+            let &(bid_data, _) = node.subnodes.get(&hnid_rows).ok_or_else(corrupted)?;
+            self.read_data_tree(bid_data, 0)?
+        };
+        let row_count = node
+            .heap
+            .bth_records(hid_row_index)
+            .map_or(usize::MAX, |records| records.len());
+
+        let mut rows = Vec::new();
+        for row in blocks
+            .iter()
+            .flat_map(|block| block.chunks_exact(row_size))
+            .take(row_count)
+        {
+            let mut properties = Vec::with_capacity(columns.len());
+            for &(tag, offset, size, bit) in &columns {
+                if row[ceb_start..]
+                    .get(bit / 8)
+                    .is_none_or(|ceb| ceb & (1 << (7 - (bit % 8))) == 0)
+                {
+                    continue;
+                }
+                let cell = match row.get(offset..offset + size) {
+                    Some(cell) => cell,
+                    None => continue,
+                };
+                let id = (tag >> 16) as u16;
+                let prop_type = tag as u16;
+
+                let bytes = match fixed_size(prop_type) {
+                    Some(size) => match cell.get(..size) {
+                        Some(bytes) => bytes.to_vec(),
+                        None => continue,
+                    },
+                    None => {
+                        let hnid = match le_u32(cell, 0) {
+                            Some(hnid) => hnid,
+                            None => continue,
+                        };
+                        if prop_type == PT_OBJECT {
+                            if let Ok(nid) = self.object_nid(node, hnid) {
+                                properties.push((id, PropertyValue::Object(nid)));
+                            }
+                            continue;
+                        }
+                        match self.read_hnid(node, hnid) {
+                            Ok(bytes) => bytes,
+                            Err(_) => continue,
+                        }
+                    }
+                };
+                if let Some(value) = PropertyValue::parse(prop_type, &bytes) {
+                    properties.push((id, value));
+                }
+            }
+            rows.push(properties);
+        }
+
+        Ok(rows)
+    }
+
+    // Object values reference a subnode either directly or through a heap
+    // item holding the subnode id and the object size.
+    fn object_nid(&self, node: &NodeData, hnid: u32) -> io::Result<u32> {
+        if hnid & 0x1f != 0 {
+            Ok(hnid)
+        } else {
+            node.heap
+                .get(hnid)
+                .and_then(|bytes| le_u32(bytes, 0))
+                .ok_or_else(corrupted)
+        }
+    }
+
+    fn read_hnid(&self, node: &NodeData, hnid: u32) -> io::Result<Vec<u8>> {
+        if hnid == 0 {
+            Ok(Vec::new())
+        } else if hnid & 0x1f == 0 {
+            node.heap
+                .get(hnid)
+                .map(|bytes| bytes.to_vec())
+                .ok_or_else(corrupted)
+        } else {
+            let &(bid_data, _) = node.subnodes.get(&hnid).ok_or_else(corrupted)?;
+            Ok(self.read_data_tree(bid_data, 0)?.concat())
+        }
+    }
+
+    fn read_node(&self, bid_data: u64, bid_sub: u64) -> io::Result<NodeData> {
+        let heap = Heap::new(self.read_data_tree(bid_data, 0)?)?;
+        let mut subnodes = BTreeMap::new();
+        self.read_subnodes(bid_sub, &mut subnodes, 0)?;
+        Ok(NodeData { heap, subnodes })
+    }
+
+    fn read_subnodes(
+        &self,
+        bid: u64,
+        subnodes: &mut BTreeMap<u32, (u64, u64)>,
+        depth: usize,
+    ) -> io::Result<()> {
+        if bid == 0 {
+            return Ok(());
+        } else if depth > MAX_DEPTH {
+            return Err(corrupted());
+        }
+
+        let block = self.read_block(bid)?;
+        if block.len() < 4 || block[0] != 0x02 {
+            return Err(corrupted());
+        }
+        let id_size = self.id_size();
+        let count = le_u16(&block, 2).unwrap_or(0) as usize;
+        let start = if self.is_unicode { 8 } else { 4 };
+
+        if block[1] == 0 {
+            for entry in 0..count {
+                let pos = start + entry * id_size * 3;
+                subnodes.insert(
+                    self.read_id(&block, pos)? as u32,
+                    (
+                        self.read_id(&block, pos + id_size)?,
+                        self.read_id(&block, pos + id_size * 2)?,
+                    ),
+                );
+            }
+        } else {
+            for entry in 0..count {
+                let pos = start + entry * id_size * 2;
+                self.read_subnodes(self.read_id(&block, pos + id_size)?, subnodes, depth + 1)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the data blocks of a node, XBLOCKs and XXBLOCKs are expanded
+    fn read_data_tree(&self, bid: u64, depth: usize) -> io::Result<Vec<Vec<u8>>> {
+        if bid == 0 {
+            return Ok(Vec::new());
+        }
+
+        let block = self.read_block(bid)?;
+        if bid & 0x02 == 0 {
+            return Ok(vec![block]);
+        } else if depth >= 2 || block.len() < 8 || block[0] != 0x01 {
+            return Err(corrupted());
+        }
+
+        let id_size = self.id_size();
+        let count = le_u16(&block, 2).unwrap_or(0) as usize;
+        let mut blocks = Vec::with_capacity(count);
+        for entry in 0..count {
+            blocks.extend(
+                self.read_data_tree(self.read_id(&block, 8 + entry * id_size)?, depth + 1)?,
+            );
+        }
+
+        Ok(blocks)
+    }
+
+    fn read_block(&self, bid: u64) -> io::Result<Vec<u8>> {
+        let entry = self.btree_lookup(self.bbt_root, bid & !0x01, false)?;
+        let id_size = self.id_size();
+        let offset = self.read_id(&entry, id_size)?;
+        let size = le_u16(&entry, id_size * 2).ok_or_else(corrupted)? as usize;
+        let mut block = self.read_at(offset, size)?;
+
+        // Only external blocks are encoded
+        if self.is_encrypted && bid & 0x02 == 0 {
+            for byte in block.iter_mut() {
+                *byte = PERMUTE_DECODE[*byte as usize];
+            }
+        }
+
+        Ok(block)
+    }
+
+    fn find_node(&self, nid: u32) -> io::Result<(u64, u64)> {
+        let entry = self.btree_lookup(self.nbt_root, nid as u64, true)?;
+        let id_size = self.id_size();
+        Ok((
+            self.read_id(&entry, id_size)?,
+            self.read_id(&entry, id_size * 2)?,
+        ))
+    }
+
+    fn btree_lookup(&self, mut offset: u64, key: u64, is_nbt: bool) -> io::Result<Vec<u8>> {
+        let id_size = self.id_size();
+        let (count_pos, size_pos, level_pos) = if self.is_unicode {
+            (488, 490, 491)
+        } else {
+            (496, 498, 499)
+        };
+        let matches_key = |entry_key: u64| {
+            if is_nbt {
+                entry_key as u32 == key as u32
+            } else {
+                entry_key & !0x01 == key
+            }
+        };
+
+        for _ in 0..MAX_DEPTH {
+            let page = self.read_at(offset, 512)?;
+            let count = page[count_pos] as usize;
+            let size = page[size_pos] as usize;
+            if size < id_size * 2 || count * size > count_pos {
+                return Err(corrupted());
+            }
+            let entries = page[..count * size].chunks_exact(size);
+
+            if page[level_pos] == 0 {
+                for entry in entries {
+                    if matches_key(self.read_id(entry, 0)?) {
+                        return Ok(entry.to_vec());
+                    }
+                }
+                break;
+            }
+
+            let mut next_offset = None;
+            for entry in entries {
+                let entry_key = self.read_id(entry, 0)?;
+                if (if is_nbt {
+                    entry_key as u32 as u64
+                } else {
+                    entry_key & !0x01
+                }) > key
+                {
+                    break;
+                }
+                next_offset = self.read_id(entry, id_size * 2)?.into();
+            }
+            offset = match next_offset {
+                Some(next_offset) => next_offset,
+                None => break,
+            };
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            if is_nbt {
+                "Node not found"
+            } else {
+                "Block not found"
+            },
+        ))
+    }
+
+    fn read_at(&self, offset: u64, size: usize) -> io::Result<Vec<u8>> {
+        let mut reader = self.reader.borrow_mut();
+        let mut buf = vec![0u8; size];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_id(&self, bytes: &[u8], pos: usize) -> io::Result<u64> {
+        if self.is_unicode {
+            le_u64(bytes, pos)
+        } else {
+            le_u32(bytes, pos).map(|id| id as u64)
+        }
+        .ok_or_else(corrupted)
+    }
+
+    fn id_size(&self) -> usize {
+        if self.is_unicode {
+            8
+        } else {
+            4
+        }
+    }
+}
+
+impl<'x, R> Iterator for FolderIterator<'x, R>
+where
+    R: Read + Seek,
+{
+    type Item = io::Result<MessageIterator<'x, R>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (nid, parent_name) = self.stack.pop()?;
+            if !self.visited.insert(nid) {
+                continue;
+            }
+
+            return Some(
+                self.pst
+                    .read_folder(nid, parent_name)
+                    .map(|(messages, children)| {
+                        for child in children.into_iter().rev() {
+                            self.stack.push((child, messages.name.clone()));
+                        }
+                        messages
+                    }),
+            );
+        }
+    }
+}
+
+impl<'x, R> MessageIterator<'x, R> {
+    /// Returns the folder name, or `None` for the root folder. Names of
+    /// subfolders are separated by `/`.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the folder's container class, such as `IPF.Note` for mail
+    /// folders or `IPF.Contact` for contacts.
+    pub fn container_class(&self) -> Option<&str> {
+        self.container_class.as_deref()
+    }
+}
+
+impl<'x, R> Iterator for MessageIterator<'x, R>
+where
+    R: Read + Seek,
+{
+    type Item = io::Result<Message>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.messages.next().map(|nid| self.pst.read_message(nid))
+    }
+}
+
+impl Message {
+    /// Returns the message's node id
+    pub fn nid(&self) -> u32 {
+        self.nid
+    }
+
+    /// Returns all MAPI properties of the message, keyed by property id
+    pub fn properties(&self) -> &[(u16, PropertyValue)] {
+        &self.properties
+    }
+
+    /// Returns the value of a MAPI property
+    pub fn property(&self, id: u16) -> Option<&PropertyValue> {
+        get_property(&self.properties, id)
+    }
+
+    /// Returns the message class, such as `IPM.Note`
+    pub fn message_class(&self) -> Option<&str> {
+        get_string(&self.properties, PR_MESSAGE_CLASS)
+    }
+
+    /// Returns the message subject
+    pub fn subject(&self) -> Option<&str> {
+        get_string(&self.properties, PR_SUBJECT).map(|subject| {
+            // Subjects may start with a marker followed by the prefix length
+            if let Some(subject) = subject.strip_prefix('\u{1}') {
+                let mut chars = subject.chars();
+                chars.next();
+                chars.as_str()
+            } else {
+                subject
+            }
+        })
+    }
+
+    /// Returns the address the message was sent from
+    pub fn from(&self) -> HeaderValue<'_> {
+        get_address(
+            &self.properties,
+            PR_SENT_REPRESENTING_NAME,
+            PR_SENT_REPRESENTING_SMTP_ADDRESS,
+            PR_SENT_REPRESENTING_ADDRTYPE,
+            PR_SENT_REPRESENTING_EMAIL_ADDRESS,
+        )
+        .or_else(|| {
+            get_address(
+                &self.properties,
+                PR_SENDER_NAME,
+                PR_SENDER_SMTP_ADDRESS,
+                PR_SENDER_ADDRTYPE,
+                PR_SENDER_EMAIL_ADDRESS,
+            )
+        })
+        .map(HeaderValue::Address)
+        .unwrap_or_default()
+    }
+
+    /// Returns the `To` recipients
+    pub fn to(&self) -> HeaderValue<'_> {
+        self.recipient_addresses(RecipientType::To)
+    }
+
+    /// Returns the `Cc` recipients
+    pub fn cc(&self) -> HeaderValue<'_> {
+        self.recipient_addresses(RecipientType::Cc)
+    }
+
+    /// Returns the `Bcc` recipients
+    pub fn bcc(&self) -> HeaderValue<'_> {
+        self.recipient_addresses(RecipientType::Bcc)
+    }
+
+    /// Returns the date the message was sent
+    pub fn date(&self) -> Option<DateTime> {
+        get_time(&self.properties, PR_CLIENT_SUBMIT_TIME)
+            .or_else(|| get_time(&self.properties, PR_MESSAGE_DELIVERY_TIME))
+            .map(DateTime::from_timestamp)
+    }
+
+    /// Returns the delivery time in seconds since the Unix epoch
+    pub fn internal_date(&self) -> u64 {
+        get_time(&self.properties, PR_MESSAGE_DELIVERY_TIME)
+            .or_else(|| get_time(&self.properties, PR_CLIENT_SUBMIT_TIME))
+            .or_else(|| get_time(&self.properties, PR_CREATION_TIME))
+            .map_or(0, |time| time.max(0) as u64)
+    }
+
+    /// Returns the Message-ID without angle brackets
+    pub fn message_id(&self) -> Option<&str> {
+        get_string(&self.properties, PR_INTERNET_MESSAGE_ID).map(strip_brackets)
+    }
+
+    /// Returns the In-Reply-To message id without angle brackets
+    pub fn in_reply_to(&self) -> Option<&str> {
+        get_string(&self.properties, PR_IN_REPLY_TO_ID).map(strip_brackets)
+    }
+
+    /// Returns the message ids listed in the References header
+    pub fn references(&self) -> Vec<&str> {
+        get_string(&self.properties, PR_INTERNET_REFERENCES)
+            .map(|references| {
+                references
+                    .split_ascii_whitespace()
+                    .map(strip_brackets)
+                    .filter(|id| !id.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the original Internet headers, if the message was received
+    /// through SMTP.
+    pub fn transport_headers(&self) -> Option<&str> {
+        get_string(&self.properties, PR_TRANSPORT_MESSAGE_HEADERS)
+    }
+
+    /// Returns the plain text body
+    pub fn text_body(&self) -> Option<&str> {
+        get_string(&self.properties, PR_BODY)
+    }
+
+    /// Returns the HTML body
+    pub fn html_body(&self) -> Option<Cow<'_, str>> {
+        match get_property(&self.properties, PR_HTML)? {
+            PropertyValue::String(html) => Some(html.as_str().into()),
+            PropertyValue::Binary(html) => Some(
+                decode_codepage(get_integer(&self.properties, PR_INTERNET_CODEPAGE), html).into(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Returns the message flags
+    pub fn flags(&self) -> &[Flag] {
+        &self.flags
+    }
+
+    /// Returns the message recipients
+    pub fn recipients(&self) -> &[Recipient] {
+        &self.recipients
+    }
+
+    /// Returns the message attachments
+    pub fn attachments(&self) -> &[Attachment] {
+        &self.attachments
+    }
+
+    /// Builds an RFC 822 message from the message properties. The original
+    /// Internet headers are used when available, MIME structure headers are
+    /// always regenerated.
+    pub fn to_rfc822(&self) -> Vec<u8> {
+        let mut message = Vec::with_capacity(1024);
+        self.write_rfc822(&mut message, 0);
+        message
+    }
+
+    fn write_rfc822(&self, out: &mut Vec<u8>, level: usize) {
+        if let Some(headers) = self.transport_headers().filter(|h| !h.trim().is_empty()) {
+            write_transport_headers(out, headers);
+        } else {
+            write_address_header(out, "From", &self.from());
+            write_address_header(out, "To", &self.to());
+            write_address_header(out, "Cc", &self.cc());
+            write_address_header(out, "Bcc", &self.bcc());
+            if let Some(subject) = self.subject() {
+                write_header(out, "Subject", &encode_text(subject));
+            }
+            if let Some(date) = get_time(&self.properties, PR_CLIENT_SUBMIT_TIME)
+                .or_else(|| get_time(&self.properties, PR_MESSAGE_DELIVERY_TIME))
+            {
+                write_header(out, "Date", &format_date(date));
+            }
+            if let Some(message_id) = self.message_id() {
+                write_header(out, "Message-ID", &format!("<{}>", message_id));
+            }
+            if let Some(in_reply_to) = self.in_reply_to() {
+                write_header(out, "In-Reply-To", &format!("<{}>", in_reply_to));
+            }
+            let references = self.references();
+            if !references.is_empty() {
+                write_header(
+                    out,
+                    "References",
+                    &references
+                        .iter()
+                        .map(|id| format!("<{}>", id))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                );
+            }
+            match get_integer(&self.properties, PR_IMPORTANCE) {
+                Some(0) => write_header(out, "Importance", "low"),
+                Some(2) => write_header(out, "Importance", "high"),
+                _ => (),
+            }
+        }
+        write_header(out, "MIME-Version", "1.0");
+
+        if self.attachments.is_empty() {
+            self.write_body(out, level);
+        } else {
+            let boundary = format!("----=_Part_{:x}_{}_mixed", self.nid, level);
+            write_header(
+                out,
+                "Content-Type",
+                &format!("multipart/mixed; boundary=\"{}\"", boundary),
+            );
+            out.extend_from_slice(b"\r\n");
+            for (pos, attachment) in std::iter::once(None)
+                .chain(self.attachments.iter().map(Some))
+                .enumerate()
+            {
+                if pos > 0 {
+                    out.extend_from_slice(b"\r\n");
+                }
+                out.extend_from_slice(b"--");
+                out.extend_from_slice(boundary.as_bytes());
+                out.extend_from_slice(b"\r\n");
+                match attachment {
+                    Some(attachment) => attachment.write_part(out, level),
+                    None => self.write_body(out, level),
+                }
+            }
+            out.extend_from_slice(b"\r\n--");
+            out.extend_from_slice(boundary.as_bytes());
+            out.extend_from_slice(b"--\r\n");
+        }
+    }
+
+    fn recipient_addresses(&self, recipient_type: RecipientType) -> HeaderValue<'_> {
+        let mut addresses = self
+            .recipients
+            .iter()
+            .filter(|recipient| recipient.recipient_type() == Some(recipient_type))
+            .filter_map(|recipient| recipient.address())
+            .collect::<Vec<_>>();
+        match addresses.len() {
+            0 => HeaderValue::Empty,
+            1 => HeaderValue::Address(addresses.pop().unwrap()),
+            _ => HeaderValue::AddressList(addresses),
+        }
+    }
+
+    fn write_body(&self, out: &mut Vec<u8>, level: usize) {
+        let text = self.text_body();
+        let html = self.html_body();
+
+        match (text, html) {
+            (Some(text), Some(html)) => {
+                let boundary = format!("----=_Part_{:x}_{}_alternative", self.nid, level);
+                write_header(
+                    out,
+                    "Content-Type",
+                    &format!("multipart/alternative; boundary=\"{}\"", boundary),
+                );
+                for (subtype, body) in [("plain", text), ("html", html.as_ref())] {
+                    out.extend_from_slice(b"\r\n--");
+                    out.extend_from_slice(boundary.as_bytes());
+                    out.extend_from_slice(b"\r\n");
+                    write_text_part(out, subtype, body);
+                }
+                out.extend_from_slice(b"\r\n--");
+                out.extend_from_slice(boundary.as_bytes());
+                out.extend_from_slice(b"--\r\n");
+            }
+            (None, Some(html)) => write_text_part(out, "html", &html),
+            (text, None) => write_text_part(out, "plain", text.unwrap_or_default()),
+        }
+    }
+}
+
+impl Recipient {
+    /// Returns all MAPI properties of the recipient
+    pub fn properties(&self) -> &[(u16, PropertyValue)] {
+        &self.properties
+    }
+
+    /// Returns the recipient type
+    pub fn recipient_type(&self) -> Option<RecipientType> {
+        match get_integer(&self.properties, PR_RECIPIENT_TYPE)? & 0x0f {
+            1 => Some(RecipientType::To),
+            2 => Some(RecipientType::Cc),
+            3 => Some(RecipientType::Bcc),
+            _ => None,
+        }
+    }
+
+    /// Returns the recipient's name and SMTP address
+    pub fn address(&self) -> Option<Addr<'_>> {
+        get_address(
+            &self.properties,
+            PR_DISPLAY_NAME,
+            PR_SMTP_ADDRESS,
+            PR_ADDRTYPE,
+            PR_EMAIL_ADDRESS,
+        )
+    }
+}
+
+impl Attachment {
+    /// Returns all MAPI properties of the attachment
+    pub fn properties(&self) -> &[(u16, PropertyValue)] {
+        &self.properties
+    }
+
+    /// Returns the attachment's file name
+    pub fn name(&self) -> Option<&str> {
+        get_string(&self.properties, PR_ATTACH_LONG_FILENAME)
+            .or_else(|| get_string(&self.properties, PR_ATTACH_FILENAME))
+            .or_else(|| get_string(&self.properties, PR_DISPLAY_NAME))
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Returns the attachment's MIME type
+    pub fn content_type(&self) -> Option<&str> {
+        get_string(&self.properties, PR_ATTACH_MIME_TAG).filter(|mime| !mime.is_empty())
+    }
+
+    /// Returns the attachment's Content-ID without angle brackets
+    pub fn content_id(&self) -> Option<&str> {
+        get_string(&self.properties, PR_ATTACH_CONTENT_ID)
+            .map(strip_brackets)
+            .filter(|cid| !cid.is_empty())
+    }
+
+    /// Returns the attachment's contents, empty for embedded messages
+    pub fn contents(&self) -> &[u8] {
+        match get_property(&self.properties, PR_ATTACH_DATA) {
+            Some(PropertyValue::Binary(contents)) => contents,
+            _ => &[],
+        }
+    }
+
+    /// Returns the embedded message, if this attachment is a message
+    pub fn message(&self) -> Option<&Message> {
+        self.message.as_deref()
+    }
+
+    fn write_part(&self, out: &mut Vec<u8>, level: usize) {
+        let disposition = if self.content_id().is_some() {
+            "inline"
+        } else {
+            "attachment"
+        };
+        let disposition = match self.name() {
+            Some(name) => format!("{}; {}", disposition, encode_parameter("filename", name)),
+            None => disposition.to_string(),
+        };
+
+        if let Some(message) = &self.message {
+            write_header(out, "Content-Type", "message/rfc822");
+            write_header(out, "Content-Disposition", &disposition);
+            out.extend_from_slice(b"\r\n");
+            message.write_rfc822(out, level + 1);
+        } else {
+            let content_type = self.content_type().unwrap_or("application/octet-stream");
+            write_header(
+                out,
+                "Content-Type",
+                &match self.name() {
+                    Some(name) => format!("{}; {}", content_type, encode_parameter("name", name)),
+                    None => content_type.to_string(),
+                },
+            );
+            write_header(out, "Content-Transfer-Encoding", "base64");
+            write_header(out, "Content-Disposition", &disposition);
+            if let Some(content_id) = self.content_id() {
+                write_header(out, "Content-ID", &format!("<{}>", content_id));
+            }
+            out.extend_from_slice(b"\r\n");
+            out.extend_from_slice(&encode_base64(self.contents(), b"\r\n"));
+        }
+    }
+}
+
+impl PropertyValue {
+    fn parse(prop_type: u16, bytes: &[u8]) -> Option<PropertyValue> {
+        Some(match prop_type {
+            0x0002 => PropertyValue::Integer(le_u16(bytes, 0)? as i16 as i64),
+            0x0003 | 0x000a => PropertyValue::Integer(le_u32(bytes, 0)? as i32 as i64),
+            0x0006 | 0x0014 => PropertyValue::Integer(le_u64(bytes, 0)? as i64),
+            0x0004 => PropertyValue::Float(f32::from_bits(le_u32(bytes, 0)?) as f64),
+            0x0005 | 0x0007 => PropertyValue::Float(f64::from_bits(le_u64(bytes, 0)?)),
+            0x000b => PropertyValue::Boolean(*bytes.first()? != 0),
+            0x0040 => PropertyValue::Time(filetime_to_timestamp(le_u64(bytes, 0)?)),
+            0x001f => PropertyValue::String(decode_utf16(bytes)),
+            0x001e => PropertyValue::String(decode_string8(bytes)),
+            0x0048 | 0x0102 => PropertyValue::Binary(bytes.to_vec()),
+            0x1002 => PropertyValue::IntegerList(
+                bytes
+                    .chunks_exact(2)
+                    .map(|value| i16::from_le_bytes([value[0], value[1]]) as i64)
+                    .collect(),
+            ),
+            0x1003 => PropertyValue::IntegerList(
+                (0..bytes.len() / 4)
+                    .filter_map(|pos| le_u32(bytes, pos * 4))
+                    .map(|value| value as i32 as i64)
+                    .collect(),
+            ),
+            0x1014 => PropertyValue::IntegerList(
+                (0..bytes.len() / 8)
+                    .filter_map(|pos| le_u64(bytes, pos * 8))
+                    .map(|value| value as i64)
+                    .collect(),
+            ),
+            0x101f => PropertyValue::StringList(
+                split_multi_value(bytes)?
+                    .into_iter()
+                    .map(decode_utf16)
+                    .collect(),
+            ),
+            0x101e => PropertyValue::StringList(
+                split_multi_value(bytes)?
+                    .into_iter()
+                    .map(decode_string8)
+                    .collect(),
+            ),
+            0x1102 => PropertyValue::BinaryList(
+                split_multi_value(bytes)?
+                    .into_iter()
+                    .map(|value| value.to_vec())
+                    .collect(),
+            ),
+            _ => return None,
+        })
+    }
+
+    /// Returns the value as a string, if it is one
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PropertyValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as an integer, if it is one
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            PropertyValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a byte slice, if it is binary
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            PropertyValue::Binary(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl Heap {
+    fn new(blocks: Vec<Vec<u8>>) -> io::Result<Heap> {
+        let header = blocks.first().ok_or_else(corrupted)?;
+        if header.len() < 12 || header[2] != 0xec {
+            return Err(corrupted());
+        }
+        Ok(Heap {
+            client_sig: header[3],
+            user_root: le_u32(header, 4).ok_or_else(corrupted)?,
+            blocks,
+        })
+    }
+
+    fn get(&self, hid: u32) -> Option<&[u8]> {
+        if hid & 0x1f != 0 {
+            return None;
+        }
+        let index = ((hid >> 5) & 0x7ff) as usize;
+        let block = self.blocks.get((hid >> 16) as usize)?;
+        let page_map = le_u16(block, 0)? as usize;
+        if index == 0 || index > le_u16(block, page_map)? as usize {
+            return None;
+        }
+        let start = le_u16(block, page_map + 2 + index * 2)? as usize;
+        let end = le_u16(block, page_map + 4 + index * 2)? as usize;
+        block.get(start..end)
+    }
+
+    // Returns the leaf records of a BTH as (key, data) pairs
+    fn bth_records(&self, hid: u32) -> io::Result<Vec<(&[u8], &[u8])>> {
+        let header = self
+            .get(hid)
+            .filter(|header| header.len() >= 8 && header[0] == 0xb5)
+            .ok_or_else(corrupted)?;
+        let key_size = header[1] as usize;
+        let data_size = header[2] as usize;
+        let levels = header[3] as usize;
+        if key_size == 0 || levels > MAX_DEPTH {
+            return Err(corrupted());
+        }
+
+        let mut records = Vec::new();
+        let mut pending = vec![(le_u32(header, 4).ok_or_else(corrupted)?, levels)];
+        while let Some((hid, level)) = pending.pop() {
+            if hid == 0 {
+                continue;
+            }
+            let items = self.get(hid).ok_or_else(corrupted)?;
+            if level == 0 {
+                records.extend(
+                    items
+                        .chunks_exact(key_size + data_size)
+                        .map(|record| record.split_at(key_size)),
+                );
+            } else {
+                for record in items.chunks_exact(key_size + 4).rev() {
+                    pending.push((le_u32(record, key_size).ok_or_else(corrupted)?, level - 1));
+                }
+            }
+        }
+
+        Ok(records)
+    }
+}
+
+fn fixed_size(prop_type: u16) -> Option<usize> {
+    match prop_type {
+        0x000b => Some(1),
+        0x0002 => Some(2),
+        0x0003 | 0x0004 | 0x000a => Some(4),
+        0x0005 | 0x0006 | 0x0007 | 0x0014 | 0x0040 => Some(8),
+        _ => None,
+    }
+}
+
+// Multi-valued variable size properties start with the number of values
+// followed by the offset of each value.
+fn split_multi_value(bytes: &[u8]) -> Option<Vec<&[u8]>> {
+    let count = le_u32(bytes, 0)? as usize;
+    if count > bytes.len() / 4 {
+        return None;
+    }
+    (0..count)
+        .map(|pos| {
+            let start = le_u32(bytes, 4 + pos * 4)? as usize;
+            let end = if pos + 1 < count {
+                le_u32(bytes, 8 + pos * 4)? as usize
+            } else {
+                bytes.len()
+            };
+            bytes.get(start..end)
+        })
+        .collect()
+}
+
+fn get_property(properties: &[(u16, PropertyValue)], id: u16) -> Option<&PropertyValue> {
+    properties
+        .iter()
+        .find(|(prop_id, _)| *prop_id == id)
+        .map(|(_, value)| value)
+}
+
+fn get_string(properties: &[(u16, PropertyValue)], id: u16) -> Option<&str> {
+    get_property(properties, id).and_then(|value| value.as_str())
+}
+
+fn get_integer(properties: &[(u16, PropertyValue)], id: u16) -> Option<i64> {
+    match get_property(properties, id)? {
+        PropertyValue::Integer(value) => Some(*value),
+        PropertyValue::Boolean(value) => Some(*value as i64),
+        _ => None,
+    }
+}
+
+fn get_time(properties: &[(u16, PropertyValue)], id: u16) -> Option<i64> {
+    match get_property(properties, id)? {
+        PropertyValue::Time(value) => Some(*value),
+        _ => None,
+    }
+}
+
+// Exchange (EX) addresses are only used when no SMTP address is available
+fn get_address(
+    properties: &[(u16, PropertyValue)],
+    name: u16,
+    smtp_address: u16,
+    address_type: u16,
+    email_address: u16,
+) -> Option<Addr<'_>> {
+    let address = get_string(properties, smtp_address)
+        .filter(|address| !address.is_empty())
+        .or_else(|| {
+            get_string(properties, email_address).filter(|address| {
+                address.contains('@')
+                    && get_string(properties, address_type)
+                        .is_none_or(|address_type| address_type.eq_ignore_ascii_case("SMTP"))
+            })
+        });
+    let name = get_string(properties, name).filter(|name| !name.is_empty());
+    if address.is_some() || name.is_some() {
+        Some(Addr {
+            name: name.map(Cow::Borrowed),
+            address: address.map(Cow::Borrowed),
+        })
+    } else {
+        None
+    }
+}
+
+fn write_header(out: &mut Vec<u8>, name: &str, value: &str) {
+    out.extend_from_slice(name.as_bytes());
+    out.extend_from_slice(b": ");
+    out.extend_from_slice(value.as_bytes());
+    out.extend_from_slice(b"\r\n");
+}
+
+fn write_address_header(out: &mut Vec<u8>, name: &str, value: &HeaderValue) {
+    let addresses = match value {
+        HeaderValue::Address(address) => std::slice::from_ref(address),
+        HeaderValue::AddressList(addresses) => addresses.as_slice(),
+        _ => return,
+    };
+    let value = addresses
+        .iter()
+        .filter_map(|address| {
+            let email = address.address.as_deref()?;
+            Some(match address.name.as_deref() {
+                Some(name) if name != email => format!("{} <{}>", encode_phrase(name), email),
+                _ => format!("<{}>", email),
+            })
+        })
+        .collect::<Vec<_>>();
+    if !value.is_empty() {
+        write_header(out, name, &value.join(", "));
+    }
+}
+
+// Copies the original headers leaving out those describing the MIME
+// structure, which is rebuilt from the message properties.
+fn write_transport_headers(out: &mut Vec<u8>, headers: &str) {
+    let mut skip = false;
+    for line in headers.lines() {
+        if line.is_empty() {
+            break;
+        } else if !line.starts_with([' ', '\t']) {
+            let name = line.split(':').next().unwrap_or_default().trim();
+            skip = ["MIME-Version", "Content-Type", "Content-Transfer-Encoding"]
+                .iter()
+                .any(|header| name.eq_ignore_ascii_case(header));
+        }
+        if !skip {
+            out.extend_from_slice(line.as_bytes());
+            out.extend_from_slice(b"\r\n");
+        }
+    }
+}
+
+fn write_text_part(out: &mut Vec<u8>, subtype: &str, text: &str) {
+    write_header(
+        out,
+        "Content-Type",
+        &format!("text/{}; charset=\"utf-8\"", subtype),
+    );
+    if text.is_ascii() && text.lines().all(|line| line.len() <= 998) {
+        write_header(out, "Content-Transfer-Encoding", "7bit");
+        out.extend_from_slice(b"\r\n");
+        out.extend_from_slice(text.as_bytes());
+    } else {
+        write_header(out, "Content-Transfer-Encoding", "base64");
+        out.extend_from_slice(b"\r\n");
+        out.extend_from_slice(&encode_base64(text.as_bytes(), b"\r\n"));
+    }
+}
+
+fn encode_text(text: &str) -> Cow<'_, str> {
+    if text
+        .chars()
+        .all(|ch| ch.is_ascii() && !ch.is_ascii_control())
+    {
+        text.into()
+    } else {
+        format!(
+            "=?utf-8?B?{}?=",
+            String::from_utf8(encode_base64(text.as_bytes(), b"")).unwrap_or_default()
+        )
+        .into()
+    }
+}
+
+fn encode_phrase(text: &str) -> Cow<'_, str> {
+    if !text.is_ascii() || text.chars().any(|ch| ch.is_ascii_control()) {
+        encode_text(text)
+    } else if text.contains(|ch: char| "()<>[]:;@\\,.\"".contains(ch)) {
+        format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"")).into()
+    } else {
+        text.into()
+    }
+}
+
+// Non-ASCII parameters are encoded following RFC 2231
+fn encode_parameter(name: &str, value: &str) -> String {
+    if value
+        .chars()
+        .all(|ch| ch.is_ascii() && !ch.is_ascii_control())
+    {
+        format!(
+            "{}=\"{}\"",
+            name,
+            value.replace('\\', "\\\\").replace('"', "\\\"")
+        )
+    } else {
+        let mut encoded = format!("{}*=utf-8''", name);
+        for &byte in value.as_bytes() {
+            if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+                encoded.push(byte as char);
+            } else {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        encoded
+    }
+}
+
+fn format_date(timestamp: i64) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let date = DateTime::from_timestamp(timestamp);
+    format!(
+        "{}, {} {} {:04} {:02}:{:02}:{:02} +0000",
+        DAYS[timestamp.div_euclid(86400).rem_euclid(7) as usize],
+        date.day,
+        MONTHS[(date.month as usize).saturating_sub(1) % 12],
+        date.year,
+        date.hour,
+        date.minute,
+        date.second
+    )
+}
+
+fn strip_brackets(id: &str) -> &str {
+    id.trim().trim_start_matches('<').trim_end_matches('>')
+}
+
+fn filetime_to_timestamp(filetime: u64) -> i64 {
+    (filetime / 10_000_000) as i64 - 11_644_473_600
+}
+
+fn decode_utf16(bytes: &[u8]) -> String {
+    char::decode_utf16(
+        bytes
+            .chunks_exact(2)
+            .map(|ch| u16::from_le_bytes([ch[0], ch[1]])),
+    )
+    .map(|ch| ch.unwrap_or(char::REPLACEMENT_CHARACTER))
+    .collect::<String>()
+    .trim_end_matches('\0')
+    .to_string()
+}
+
+fn decode_string8(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.trim_end_matches('\0').to_string(),
+        Err(_) => decode_codepage(Some(1252), bytes)
+            .trim_end_matches('\0')
+            .to_string(),
+    }
+}
+
+fn decode_codepage(codepage: Option<i64>, bytes: &[u8]) -> String {
+    let charset = match codepage.unwrap_or(65001) {
+        20127 => "us-ascii".to_string(),
+        cp @ 1250..=1258 => format!("windows-{}", cp),
+        cp @ 28591..=28599 => format!("iso-8859-{}", cp - 28590),
+        28603 => "iso-8859-13".to_string(),
+        28605 => "iso-8859-15".to_string(),
+        932 => "shift_jis".to_string(),
+        936 => "gb2312".to_string(),
+        949 | 51949 => "euc-kr".to_string(),
+        950 => "big5".to_string(),
+        20866 => "koi8-r".to_string(),
+        21866 => "koi8-u".to_string(),
+        50220..=50222 => "iso-2022-jp".to_string(),
+        51932 => "euc-jp".to_string(),
+        54936 => "gb18030".to_string(),
+        _ => return String::from_utf8_lossy(bytes).into_owned(),
+    };
+    get_charset_decoder(charset.as_bytes())
+        .map(|decoder| decoder(bytes))
+        .unwrap_or_else(|| String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn le_u16(bytes: &[u8], pos: usize) -> Option<u16> {
+    bytes
+        .get(pos..pos + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn le_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+    bytes
+        .get(pos..pos + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn le_u64(bytes: &[u8], pos: usize) -> Option<u64> {
+    bytes.get(pos..pos + 8).map(|bytes| {
+        let mut value = [0u8; 8];
+        value.copy_from_slice(bytes);
+        u64::from_le_bytes(value)
+    })
+}
+
+fn corrupted() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Corrupted PST structure")
+}
+
+// Inverse of the NDB_CRYPT_PERMUTE substitution table
+static PERMUTE_DECODE: [u8; 256] = [
+    0x47, 0xf1, 0xb4, 0xe6, 0x0b, 0x6a, 0x72, 0x48, 0x85, 0x4e, 0x9e, 0xeb, 0xe2, 0xf8, 0x94, 0x53,
+    0xe0, 0xbb, 0xa0, 0x02, 0xe8, 0x5a, 0x09, 0xab, 0xdb, 0xe3, 0xba, 0xc6, 0x7c, 0xc3, 0x10, 0xdd,
+    0x39, 0x05, 0x96, 0x30, 0xf5, 0x37, 0x60, 0x82, 0x8c, 0xc9, 0x13, 0x4a, 0x6b, 0x1d, 0xf3, 0xfb,
+    0x8f, 0x26, 0x97, 0xca, 0x91, 0x17, 0x01, 0xc4, 0x32, 0x2d, 0x6e, 0x31, 0x95, 0xff, 0xd9, 0x23,
+    0xd1, 0x00, 0x5e, 0x79, 0xdc, 0x44, 0x3b, 0x1a, 0x28, 0xc5, 0x61, 0x57, 0x20, 0x90, 0x3d, 0x83,
+    0xb9, 0x43, 0xbe, 0x67, 0xd2, 0x46, 0x42, 0x76, 0xc0, 0x6d, 0x5b, 0x7e, 0xb2, 0x0f, 0x16, 0x29,
+    0x3c, 0xa9, 0x03, 0x54, 0x0d, 0xda, 0x5d, 0xdf, 0xf6, 0xb7, 0xc7, 0x62, 0xcd, 0x8d, 0x06, 0xd3,
+    0x69, 0x5c, 0x86, 0xd6, 0x14, 0xf7, 0xa5, 0x66, 0x75, 0xac, 0xb1, 0xe9, 0x45, 0x21, 0x70, 0x0c,
+    0x87, 0x9f, 0x74, 0xa4, 0x22, 0x4c, 0x6f, 0xbf, 0x1f, 0x56, 0xaa, 0x2e, 0xb3, 0x78, 0x33, 0x50,
+    0xb0, 0xa3, 0x92, 0xbc, 0xcf, 0x19, 0x1c, 0xa7, 0x63, 0xcb, 0x1e, 0x4d, 0x3e, 0x4b, 0x1b, 0x9b,
+    0x4f, 0xe7, 0xf0, 0xee, 0xad, 0x3a, 0xb5, 0x59, 0x04, 0xea, 0x40, 0x55, 0x25, 0x51, 0xe5, 0x7a,
+    0x89, 0x38, 0x68, 0x52, 0x7b, 0xfc, 0x27, 0xae, 0xd7, 0xbd, 0xfa, 0x07, 0xf4, 0xcc, 0x8e, 0x5f,
+    0xef, 0x35, 0x9c, 0x84, 0x2b, 0x15, 0xd5, 0x77, 0x34, 0x49, 0xb6, 0x12, 0x0a, 0x7f, 0x71, 0x88,
+    0xfd, 0x9d, 0x18, 0x41, 0x7d, 0x93, 0xd8, 0x58, 0x2c, 0xce, 0xfe, 0x24, 0xaf, 0xde, 0xb8, 0x36,
+    0xc8, 0xa1, 0x80, 0xa6, 0x99, 0x98, 0xa8, 0x2f, 0x0e, 0x81, 0x65, 0x73, 0xe4, 0xc2, 0xa2, 0x8a,
+    0xd4, 0xe1, 0x11, 0xd0, 0x08, 0x8b, 0x2a, 0xf2, 0xed, 0x9a, 0x64, 0x3f, 0xc1, 0x6c, 0xf9, 0xec,
+];
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        borrow::Cow,
+        io::{Cursor, ErrorKind},
+    };
+
+    use crate::{mailbox::Flag, Addr, HeaderValue, MimeHeaders};
+
+    use super::{PstFile, RecipientType, PERMUTE_DECODE};
+
+    // Builds a minimal Unicode or ANSI PST file with permutative encoding,
+    // B-tree pages hold at most `max_entries` entries.
+    struct PstBuilder {
+        is_ansi: bool,
+        max_entries: usize,
+        blocks: Vec<u8>,
+        bbt: Vec<(u64, u64, u16)>,
+        nbt: Vec<(u32, u64, u64)>,
+        next_bid: u64,
+    }
+
+    impl PstBuilder {
+        fn new(is_ansi: bool, max_entries: usize) -> Self {
+            PstBuilder {
+                is_ansi,
+                max_entries,
+                blocks: Vec::new(),
+                bbt: Vec::new(),
+                nbt: Vec::new(),
+                next_bid: 0,
+            }
+        }
+
+        fn block(&mut self, data: &[u8], is_internal: bool) -> u64 {
+            let mut encode = [0u8; 256];
+            for (pos, &byte) in PERMUTE_DECODE.iter().enumerate() {
+                encode[byte as usize] = pos as u8;
+            }
+
+            self.next_bid += 4;
+            let bid = self.next_bid | if is_internal { 0x02 } else { 0 };
+            let offset = 1024 + self.blocks.len();
+            if is_internal {
+                self.blocks.extend_from_slice(data);
+            } else {
+                self.blocks
+                    .extend(data.iter().map(|&byte| encode[byte as usize]));
+            }
+            let trailer_size = if self.is_ansi { 12 } else { 16 };
+            let size = (data.len() + trailer_size).div_ceil(64) * 64;
+            self.blocks.resize(offset - 1024 + size - trailer_size, 0);
+            self.blocks
+                .extend_from_slice(&(data.len() as u16).to_le_bytes());
+            self.blocks.extend_from_slice(&[0u8; 2]);
+            if self.is_ansi {
+                self.blocks.extend_from_slice(&(bid as u32).to_le_bytes());
+                self.blocks.extend_from_slice(&[0u8; 4]);
+            } else {
+                self.blocks.extend_from_slice(&[0u8; 4]);
+                self.blocks.extend_from_slice(&bid.to_le_bytes());
+            }
+            self.bbt.push((bid, offset as u64, data.len() as u16));
+            bid
+        }
+
+        fn node(&mut self, nid: u32, data: &[u8], subnodes: &[(u32, &[u8])]) {
+            let bid_data = self.block(data, false);
+            let bid_sub = if !subnodes.is_empty() {
+                let mut block = vec![0x02, 0x00];
+                block.extend_from_slice(&(subnodes.len() as u16).to_le_bytes());
+                if !self.is_ansi {
+                    block.extend_from_slice(&[0u8; 4]);
+                }
+                for (nid, data) in subnodes {
+                    let bid = self.block(data, false);
+                    self.push_id(&mut block, *nid as u64);
+                    self.push_id(&mut block, bid);
+                    self.push_id(&mut block, 0);
+                }
+                self.block(&block, true)
+            } else {
+                0
+            };
+            self.nbt.push((nid, bid_data, bid_sub));
+        }
+
+        // Registers a node whose data block is missing
+        fn broken_node(&mut self, nid: u32) {
+            self.nbt.push((nid, 0xfff0, 0));
+        }
+
+        fn build(mut self) -> Vec<u8> {
+            self.nbt.sort_unstable();
+            self.bbt.sort_unstable();
+
+            let mut file = vec![0u8; 1024];
+            file[..4].copy_from_slice(b"!BDN");
+            file.extend_from_slice(&self.blocks);
+
+            let nbt = self
+                .nbt
+                .iter()
+                .map(|&(nid, bid_data, bid_sub)| {
+                    let mut entry = Vec::new();
+                    self.push_id(&mut entry, nid as u64);
+                    self.push_id(&mut entry, bid_data);
+                    self.push_id(&mut entry, bid_sub);
+                    entry.extend_from_slice(&[0u8; 4]);
+                    if !self.is_ansi {
+                        entry.extend_from_slice(&[0u8; 4]);
+                    }
+                    (nid as u64, entry)
+                })
+                .collect();
+            let nbt_root = self.btree(&mut file, nbt, 0x81);
+            let bbt = self
+                .bbt
+                .iter()
+                .map(|&(bid, offset, size)| {
+                    let mut entry = Vec::new();
+                    self.push_id(&mut entry, bid);
+                    self.push_id(&mut entry, offset);
+                    entry.extend_from_slice(&size.to_le_bytes());
+                    entry.extend_from_slice(&1u16.to_le_bytes());
+                    if !self.is_ansi {
+                        entry.extend_from_slice(&[0u8; 4]);
+                    }
+                    (bid, entry)
+                })
+                .collect();
+            let bbt_root = self.btree(&mut file, bbt, 0x80);
+
+            if self.is_ansi {
+                file[10..12].copy_from_slice(&14u16.to_le_bytes());
+                file[188..192].copy_from_slice(&(nbt_root as u32).to_le_bytes());
+                file[196..200].copy_from_slice(&(bbt_root as u32).to_le_bytes());
+                file[461] = 1;
+            } else {
+                file[10..12].copy_from_slice(&23u16.to_le_bytes());
+                file[224..232].copy_from_slice(&nbt_root.to_le_bytes());
+                file[240..248].copy_from_slice(&bbt_root.to_le_bytes());
+                file[513] = 1;
+            }
+            file
+        }
+
+        // Writes the pages of a B-tree level by level, returns the offset
+        // of the root page.
+        fn btree(
+            &self,
+            file: &mut Vec<u8>,
+            mut entries: Vec<(u64, Vec<u8>)>,
+            page_type: u8,
+        ) -> u64 {
+            let entries_size = if self.is_ansi { 496 } else { 488 };
+            let mut level = 0;
+            loop {
+                let entry_size = entries[0].1.len();
+                let max_entries = self.max_entries.min(entries_size / entry_size);
+                let mut parents = Vec::new();
+                for chunk in entries.chunks(max_entries) {
+                    let offset = file.len() as u64;
+                    let mut page = chunk
+                        .iter()
+                        .flat_map(|(_, entry)| entry.iter().copied())
+                        .collect::<Vec<_>>();
+                    page.resize(entries_size, 0);
+                    page.extend_from_slice(&[
+                        chunk.len() as u8,
+                        (entries_size / entry_size) as u8,
+                        entry_size as u8,
+                        level,
+                    ]);
+                    page.resize(512 - if self.is_ansi { 12 } else { 16 }, 0);
+                    page.extend_from_slice(&[page_type, page_type]);
+                    page.resize(512, 0);
+                    file.extend_from_slice(&page);
+
+                    let mut entry = Vec::new();
+                    self.push_id(&mut entry, chunk[0].0);
+                    self.push_id(&mut entry, 0);
+                    self.push_id(&mut entry, offset);
+                    parents.push((chunk[0].0, entry));
+                }
+                if parents.len() == 1 {
+                    return file.len() as u64 - 512;
+                }
+                entries = parents;
+                level += 1;
+            }
+        }
+
+        fn push_id(&self, bytes: &mut Vec<u8>, id: u64) {
+            if self.is_ansi {
+                bytes.extend_from_slice(&(id as u32).to_le_bytes());
+            } else {
+                bytes.extend_from_slice(&id.to_le_bytes());
+            }
+        }
+    }
+
+    // Builds a heap whose first item is the user root
+    fn heap(client_sig: u8, items: &[Vec<u8>]) -> Vec<u8> {
+        let mut heap = vec![0, 0, 0xec, client_sig, 0x20, 0, 0, 0, 0, 0, 0, 0];
+        let mut offsets = vec![heap.len() as u16];
+        for item in items {
+            heap.extend_from_slice(item);
+            offsets.push(heap.len() as u16);
+        }
+        let page_map = heap.len() as u16;
+        heap[..2].copy_from_slice(&page_map.to_le_bytes());
+        heap.extend_from_slice(&(items.len() as u16).to_le_bytes());
+        heap.extend_from_slice(&[0, 0]);
+        for offset in offsets {
+            heap.extend_from_slice(&offset.to_le_bytes());
+        }
+        heap
+    }
+
+    fn hid(index: usize) -> [u8; 4] {
+        ((index as u32) << 5).to_le_bytes()
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|ch| ch.to_le_bytes())
+            .collect()
+    }
+
+    // ANSI strings are stored using the Windows-1252 code page
+    fn ansi(text: &str) -> Vec<u8> {
+        text.chars()
+            .map(|ch| if ch == 'é' { 0xe9 } else { ch as u8 })
+            .collect()
+    }
+
+    fn filetime(timestamp: u64) -> Vec<u8> {
+        ((timestamp + 11_644_473_600) * 10_000_000)
+            .to_le_bytes()
+            .to_vec()
+    }
+
+    fn property_context(properties: &[(u16, u16, Vec<u8>)]) -> Vec<u8> {
+        let mut records = Vec::new();
+        let mut items = vec![vec![0xb5, 2, 6, 0], Vec::new()];
+        items[0].extend_from_slice(&hid(2));
+        for (id, prop_type, value) in properties {
+            records.extend_from_slice(&id.to_le_bytes());
+            records.extend_from_slice(&prop_type.to_le_bytes());
+            if matches!(prop_type, 0x0003 | 0x000b) {
+                let mut inline = value.clone();
+                inline.resize(4, 0);
+                records.extend_from_slice(&inline);
+            } else {
+                items.push(value.clone());
+                records.extend_from_slice(&hid(items.len()));
+            }
+        }
+        items[1] = records;
+        heap(0xbc, &items)
+    }
+
+    // All columns are four bytes wide, holding either an integer or a HID
+    fn table_context(columns: &[(u16, u16)], rows: &[Vec<Vec<u8>>]) -> Vec<u8> {
+        let row_size = columns.len() * 4 + columns.len().div_ceil(8);
+        let mut info = vec![0x7c, columns.len() as u8];
+        for _ in 0..3 {
+            info.extend_from_slice(&((columns.len() * 4) as u16).to_le_bytes());
+        }
+        info.extend_from_slice(&(row_size as u16).to_le_bytes());
+        info.extend_from_slice(&hid(2));
+        info.extend_from_slice(&hid(4));
+        info.extend_from_slice(&[0u8; 4]);
+        for (pos, (id, prop_type)) in columns.iter().enumerate() {
+            info.extend_from_slice(&prop_type.to_le_bytes());
+            info.extend_from_slice(&id.to_le_bytes());
+            info.extend_from_slice(&((pos * 4) as u16).to_le_bytes());
+            info.extend_from_slice(&[4, pos as u8]);
+        }
+
+        let mut row_index_header = vec![0xb5, 4, 4, 0];
+        row_index_header.extend_from_slice(&hid(3));
+        let mut items = vec![info, row_index_header, Vec::new(), Vec::new()];
+        for (row_num, row) in rows.iter().enumerate() {
+            items[2].extend_from_slice(&(row_num as u32).to_le_bytes());
+            items[2].extend_from_slice(&(row_num as u32).to_le_bytes());
+            let mut row_data = Vec::with_capacity(row_size);
+            for ((_, prop_type), value) in columns.iter().zip(row) {
+                if *prop_type == 0x0003 {
+                    row_data.extend_from_slice(value);
+                } else {
+                    items.push(value.clone());
+                    row_data.extend_from_slice(&hid(items.len()));
+                }
+            }
+            row_data.resize(row_size, 0xff);
+            items[3].extend_from_slice(&row_data);
+        }
+        heap(0x7c, &items)
+    }
+
+    #[test]
+    fn read_pst() {
+        // Unicode and ANSI files, with single page and multi-level B-trees
+        for (is_ansi, max_entries) in [
+            (false, usize::MAX),
+            (true, usize::MAX),
+            (false, 2),
+            (true, 3),
+        ] {
+            read_pst_file(is_ansi, max_entries);
+        }
+
+        // OST files with 4K pages are rejected
+        let mut file = vec![0u8; 1024];
+        file[..4].copy_from_slice(b"!BDN");
+        file[10..12].copy_from_slice(&36u16.to_le_bytes());
+        assert_eq!(
+            PstFile::new(Cursor::new(file)).err().map(|err| err.kind()),
+            Some(ErrorKind::Unsupported)
+        );
+    }
+
+    fn read_pst_file(is_ansi: bool, max_entries: usize) {
+        let (string_type, string) = if is_ansi {
+            (0x001e, ansi as fn(&str) -> Vec<u8>)
+        } else {
+            (0x001f, utf16 as fn(&str) -> Vec<u8>)
+        };
+        let mut pst = PstBuilder::new(is_ansi, max_entries);
+        pst.node(
+            0x122,
+            &property_context(&[(0x3001, string_type, string(""))]),
+            &[],
+        );
+        pst.node(
+            0x12d,
+            &table_context(
+                &[(0x67f2, 0x0003), (0x3001, string_type)],
+                &[vec![0x8022u32.to_le_bytes().to_vec(), string("Inbox")]],
+            ),
+            &[],
+        );
+        pst.node(
+            0x8022,
+            &property_context(&[
+                (0x3001, string_type, string("Inbox")),
+                (0x3613, string_type, string("IPF.Note")),
+            ]),
+            &[],
+        );
+        pst.node(
+            0x802e,
+            &table_context(
+                &[(0x67f2, 0x0003)],
+                &[
+                    vec![0x200024u32.to_le_bytes().to_vec()],
+                    vec![0x200044u32.to_le_bytes().to_vec()],
+                ],
+            ),
+            &[],
+        );
+        pst.node(
+            0x200024,
+            &property_context(&[
+                (0x001a, string_type, string("IPM.Note")),
+                (
+                    0x0037,
+                    string_type,
+                    string("\u{1}\u{4}Re: Quarterly café report"),
+                ),
+                (0x0039, 0x0040, filetime(1516012200)),
+                (0x0042, string_type, string("Alice Smith")),
+                (0x0064, string_type, string("SMTP")),
+                (0x0065, string_type, string("alice@example.com")),
+                (0x0e06, 0x0040, filetime(1516012260)),
+                (0x0e07, 0x0003, 1u32.to_le_bytes().to_vec()),
+                (0x1000, string_type, string("Hello Bob,\r\nsee attached.")),
+                (0x1013, 0x0102, b"<p>Hello Bob</p>".to_vec()),
+                (0x1035, string_type, string("<1234@example.com>")),
+                (0x1090, 0x0003, 2u32.to_le_bytes().to_vec()),
+                (0x3fde, 0x0003, 65001u32.to_le_bytes().to_vec()),
+            ]),
+            &[
+                (
+                    0x671,
+                    &table_context(
+                        &[(0x67f2, 0x0003), (0x3707, string_type)],
+                        &[vec![0x8025u32.to_le_bytes().to_vec(), string("report.txt")]],
+                    ),
+                ),
+                (
+                    0x692,
+                    &table_context(
+                        &[
+                            (0x67f2, 0x0003),
+                            (0x0c15, 0x0003),
+                            (0x3001, string_type),
+                            (0x39fe, string_type),
+                        ],
+                        &[
+                            vec![
+                                0u32.to_le_bytes().to_vec(),
+                                1u32.to_le_bytes().to_vec(),
+                                string("Bob"),
+                                string("bob@example.com"),
+                            ],
+                            vec![
+                                1u32.to_le_bytes().to_vec(),
+                                2u32.to_le_bytes().to_vec(),
+                                string("Carol"),
+                                string("carol@example.com"),
+                            ],
+                        ],
+                    ),
+                ),
+                (
+                    0x8025,
+                    &property_context(&[
+                        (0x3701, 0x0102, b"Quarterly numbers\r\n".to_vec()),
+                        (0x3705, 0x0003, 1u32.to_le_bytes().to_vec()),
+                        (0x3707, string_type, string("report.txt")),
+                        (0x370e, string_type, string("text/plain")),
+                    ]),
+                ),
+            ],
+        );
+        pst.broken_node(0x200044);
+
+        let pst = PstFile::new(Cursor::new(pst.build())).unwrap();
+        assert_eq!(pst.is_unicode(), !is_ansi);
+
+        let mut folders = pst.folders().map(|folder| folder.unwrap());
+        let root = folders.next().unwrap();
+        assert_eq!(root.name(), None);
+        assert_eq!(root.count(), 0);
+        let mut inbox = folders.next().unwrap();
+        assert_eq!(inbox.name(), Some("Inbox"));
+        assert_eq!(inbox.container_class(), Some("IPF.Note"));
+        assert!(folders.next().is_none());
+
+        // Corrupted messages are reported without ending the iteration
+        let message = inbox.next().unwrap().unwrap();
+        assert!(inbox.next().unwrap().is_err());
+        assert!(inbox.next().is_none());
+
+        assert_eq!(message.nid(), 0x200024);
+        assert_eq!(message.message_class(), Some("IPM.Note"));
+        assert_eq!(message.subject(), Some("Re: Quarterly café report"));
+        assert_eq!(
+            message.from(),
+            HeaderValue::Address(Addr {
+                name: Some("Alice Smith".into()),
+                address: Some("alice@example.com".into())
+            })
+        );
+        assert_eq!(
+            message.to(),
+            HeaderValue::Address(Addr {
+                name: Some("Bob".into()),
+                address: Some("bob@example.com".into())
+            })
+        );
+        assert_eq!(
+            message.recipients()[1].recipient_type(),
+            Some(RecipientType::Cc)
+        );
+        assert_eq!(message.bcc(), HeaderValue::Empty);
+        assert_eq!(message.date().unwrap().to_timestamp(), 1516012200);
+        assert_eq!(message.internal_date(), 1516012260);
+        assert_eq!(message.message_id(), Some("1234@example.com"));
+        assert_eq!(message.flags(), &[Flag::Seen, Flag::Flagged]);
+        assert_eq!(message.html_body(), Some(Cow::from("<p>Hello Bob</p>")));
+        assert_eq!(message.attachments().len(), 1);
+        assert_eq!(message.attachments()[0].name(), Some("report.txt"));
+        assert_eq!(
+            message.attachments()[0].contents(),
+            b"Quarterly numbers\r\n"
+        );
+
+        let rfc822 = message.to_rfc822();
+        let parsed = crate::Message::parse(&rfc822).unwrap();
+        assert_eq!(parsed.get_subject(), Some("Re: Quarterly café report"));
+        assert_eq!(parsed.get_from(), &message.from());
+        assert_eq!(
+            parsed.get_cc(),
+            &HeaderValue::Address(Addr {
+                name: Some("Carol".into()),
+                address: Some("carol@example.com".into())
+            })
+        );
+        assert_eq!(parsed.get_date().unwrap().to_timestamp(), 1516012200);
+        assert_eq!(parsed.get_message_id(), Some("1234@example.com"));
+        assert_eq!(
+            parsed.get_text_part(0).unwrap().get_text_contents(),
+            Some("Hello Bob,\r\nsee attached.")
+        );
+        assert_eq!(parsed.get_html_body(0).unwrap(), "<p>Hello Bob</p>");
+        let attachment = parsed.get_attachment(0).unwrap();
+        assert_eq!(attachment.get_attachment_name(), Some("report.txt"));
+        assert_eq!(attachment.get_contents(), b"Quarterly numbers\r\n");
+    }
+}
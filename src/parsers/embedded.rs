@@ -0,0 +1,186 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use crate::{
+    decoders::{
+        binhex::{decode_binhex_block, is_binhex_start},
+        next_line,
+        uuencode::{decode_uuencode_block, is_uuencode_start},
+        yenc::{decode_yenc_block, is_yenc_start},
+        DecodedFile,
+    },
+    ContentType, Encoding, Header, HeaderName, HeaderValue, Message, MessagePart, MimeHeaders,
+    PartType, RfcHeader,
+};
+
+impl<'x> Message<'x> {
+    /// Returns the uuencoded, yEnc and BinHex files embedded in the text parts
+    /// of the message as synthetic attachments.
+    pub fn get_embedded_files(&self) -> Vec<MessagePart<'static>> {
+        let mut files = Vec::new();
+        let base_offset = self.parts.first().map_or(0, |p| p.offset_header);
+
+        for part in &self.parts {
+            let contents = match &part.body {
+                PartType::Text(text) => {
+                    // Use the raw contents when available, as yEnc is 8-bit data
+                    // that might not survive the charset conversion.
+                    if part.encoding == Encoding::None {
+                        part.offset_body
+                            .checked_sub(base_offset)
+                            .zip(part.offset_end.checked_sub(base_offset))
+                            .and_then(|(start, end)| self.raw_message.get(start..end))
+                            .unwrap_or_else(|| text.as_bytes())
+                    } else {
+                        text.as_bytes()
+                    }
+                }
+                PartType::Binary(bin) | PartType::InlineBinary(bin)
+                    if part.get_content_type().is_some_and(|ct| {
+                        ct.get_type().eq_ignore_ascii_case("application")
+                            && ct
+                                .get_subtype()
+                                .is_some_and(|s| s.eq_ignore_ascii_case("mac-binhex40"))
+                    }) =>
+                {
+                    bin.as_ref()
+                }
+                _ => continue,
+            };
+
+            for file in decode_embedded_files(contents) {
+                files.push(MessagePart {
+                    headers: embedded_file_headers(file.name),
+                    is_encoding_problem: false,
+                    body: PartType::Binary(file.contents.into()),
+                    encoding: Encoding::None,
+                    offset_header: part.offset_body,
+                    offset_body: part.offset_body,
+                    offset_end: part.offset_end,
+                });
+            }
+        }
+
+        files
+    }
+}
+
+/// Extracts all the uuencoded, yEnc and BinHex blocks found in a text.
+pub fn decode_embedded_files(data: &[u8]) -> Vec<DecodedFile> {
+    let mut files = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let block = &data[pos..];
+        let (bytes_read, file) = if is_uuencode_start(block) {
+            decode_uuencode_block(block)
+        } else if is_yenc_start(block) {
+            decode_yenc_block(block)
+        } else if is_binhex_start(block) {
+            decode_binhex_block(block)
+        } else {
+            (0, None)
+        };
+
+        if let Some(file) = file {
+            files.push(file);
+            pos += bytes_read;
+        } else {
+            pos = next_line(data, pos).1;
+        }
+    }
+
+    files
+}
+
+fn embedded_file_headers(name: Option<String>) -> Vec<Header<'static>> {
+    vec![
+        Header {
+            name: HeaderName::Rfc(RfcHeader::ContentType),
+            value: HeaderValue::ContentType(ContentType {
+                c_type: "application".into(),
+                c_subtype: Some("octet-stream".into()),
+                attributes: name
+                    .as_ref()
+                    .map(|name| vec![("name".into(), name.clone().into())]),
+            }),
+            offset_start: 0,
+            offset_end: 0,
+        },
+        Header {
+            name: HeaderName::Rfc(RfcHeader::ContentDisposition),
+            value: HeaderValue::ContentType(ContentType {
+                c_type: "attachment".into(),
+                c_subtype: None,
+                attributes: name.map(|name| vec![("filename".into(), name.into())]),
+            }),
+            offset_start: 0,
+            offset_end: 0,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::{Message, MimeHeaders};
+
+    #[test]
+    fn embedded_files() {
+        let input = b"From: user@domain.com
+Subject: Files
+Content-Type: text/plain
+
+Here is the file:
+
+begin 644 cat.txt
+#0V%T
+`
+end
+
+And another one:
+=ybegin line=128 size=3 name=dog.txt
+n\x99\x91
+=yend size=3
+
+begin 644 broken.txt
+this is not uuencoded
+end
+";
+
+        let message = Message::parse(&input[..]).unwrap();
+        let files = message.get_embedded_files();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].get_attachment_name(), Some("cat.txt"));
+        assert_eq!(files[0].get_contents(), b"Cat");
+        assert_eq!(files[1].get_attachment_name(), Some("dog.txt"));
+        assert_eq!(files[1].get_contents(), b"Dog");
+
+        // BinHex attachments
+        let mut test_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_file.push("tests");
+        test_file.push("legacy");
+        test_file.push("016.eml");
+
+        let raw_message = fs::read(test_file).unwrap();
+        let message = Message::parse(&raw_message).unwrap();
+        let files = message.get_embedded_files();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].get_attachment_name(), Some("blueball.png"));
+        assert!(files[0].get_contents().starts_with(b"\x89PNG"));
+        assert!(files[1]
+            .get_attachment_name()
+            .unwrap()
+            .starts_with("HasenundFr"));
+        assert!(files[1].get_contents().starts_with(b"Die Hasen und die Fr"));
+    }
+}
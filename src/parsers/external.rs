@@ -0,0 +1,217 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::borrow::Cow;
+
+use crate::{AccessType, ExternalBody, HeaderValue, MessagePart, MimeHeaders};
+
+use super::{
+    fields::date::parse_date, header::parse_headers, message::MessageStream, mime::skip_crlf,
+};
+
+impl<'x> MessagePart<'x> {
+    /// Returns the reference contained in a `message/external-body` part.
+    pub fn get_external_body(&self) -> Option<ExternalBody<'_>> {
+        let content_type = self.get_content_type()?;
+        if content_type.get_type() != "message"
+            || !content_type
+                .get_subtype()
+                .is_some_and(|s| s.eq_ignore_ascii_case("external-body"))
+        {
+            return None;
+        }
+
+        let attribute = |name: &str| content_type.get_attribute(name).map(Cow::from);
+        let access_type = content_type.get_attribute("access-type").unwrap_or("");
+
+        let access_type = if access_type.eq_ignore_ascii_case("ftp")
+            || access_type.eq_ignore_ascii_case("anon-ftp")
+        {
+            match (attribute("name"), attribute("site")) {
+                (Some(name), Some(site)) => {
+                    let directory = attribute("directory");
+                    let mode = attribute("mode");
+                    if access_type.eq_ignore_ascii_case("ftp") {
+                        AccessType::Ftp {
+                            name,
+                            site,
+                            directory,
+                            mode,
+                        }
+                    } else {
+                        AccessType::AnonFtp {
+                            name,
+                            site,
+                            directory,
+                            mode,
+                        }
+                    }
+                }
+                _ => AccessType::Other(access_type.into()),
+            }
+        } else if access_type.eq_ignore_ascii_case("tftp") {
+            match (attribute("name"), attribute("site")) {
+                (Some(name), Some(site)) => AccessType::Tftp {
+                    name,
+                    site,
+                    mode: attribute("mode"),
+                },
+                _ => AccessType::Other(access_type.into()),
+            }
+        } else if access_type.eq_ignore_ascii_case("local-file") {
+            match attribute("name") {
+                Some(name) => AccessType::LocalFile {
+                    name,
+                    site: attribute("site"),
+                },
+                None => AccessType::Other(access_type.into()),
+            }
+        } else if access_type.eq_ignore_ascii_case("mail-server") {
+            match attribute("server") {
+                Some(server) => AccessType::MailServer {
+                    server,
+                    subject: attribute("subject"),
+                },
+                None => AccessType::Other(access_type.into()),
+            }
+        } else if access_type.eq_ignore_ascii_case("url") {
+            // Whitespace in URLs has to be ignored (RFC 2017 - Section 2)
+            match content_type.get_attribute("url") {
+                Some(url) if url.contains(char::is_whitespace) => AccessType::Url(
+                    url.chars()
+                        .filter(|ch| !ch.is_whitespace())
+                        .collect::<String>()
+                        .into(),
+                ),
+                Some(url) => AccessType::Url(url.into()),
+                None => AccessType::Other(access_type.into()),
+            }
+        } else if access_type.eq_ignore_ascii_case("content-id") {
+            AccessType::ContentId
+        } else {
+            AccessType::Other(access_type.into())
+        };
+
+        // Parse the phantom headers and body
+        let contents = self.get_contents();
+        let mut stream = MessageStream::new(contents);
+        let mut headers = Vec::new();
+        let body = if parse_headers(&mut headers, &mut stream) {
+            skip_crlf(&mut stream);
+            String::from_utf8_lossy(&contents[stream.pos..])
+        } else {
+            "".into()
+        };
+
+        Some(ExternalBody {
+            access_type,
+            expiration: content_type.get_attribute("expiration").and_then(|date| {
+                let date = format!("{}\n", date);
+                match parse_date(&mut MessageStream::new(date.as_bytes())) {
+                    HeaderValue::DateTime(date) if date.is_valid() => Some(date),
+                    _ => None,
+                }
+            }),
+            size: content_type
+                .get_attribute("size")
+                .and_then(|size| size.trim().parse().ok()),
+            permission: attribute("permission"),
+            headers,
+            body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::{AccessType, Message, MimeHeaders};
+
+    #[test]
+    fn parse_external_body() {
+        let mut test_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_file.push("tests");
+        test_file.push("rfc");
+        test_file.push("001.eml");
+
+        let raw_message = fs::read(test_file).unwrap();
+        let message = Message::parse(&raw_message).unwrap();
+
+        assert!(message.get_part(0).unwrap().get_external_body().is_none());
+
+        let external = message.get_part(1).unwrap().get_external_body().unwrap();
+        assert_eq!(
+            external.access_type,
+            AccessType::AnonFtp {
+                name: "BodyFormats.ps".into(),
+                site: "thumper.bellcore.com".into(),
+                directory: Some("pub".into()),
+                mode: Some("image".into()),
+            }
+        );
+        assert_eq!(
+            external.expiration.as_ref().unwrap().to_rfc3339(),
+            "1991-06-14T19:13:14-04:00"
+        );
+        assert_eq!(
+            external.get_content_type().unwrap().get_subtype(),
+            Some("postscript")
+        );
+        assert_eq!(
+            external.get_content_id(),
+            Some("id42@guppylake.bellcore.com")
+        );
+
+        let external = message.get_part(2).unwrap().get_external_body().unwrap();
+        assert_eq!(
+            external.access_type,
+            AccessType::LocalFile {
+                name: "/u/nsb/writing/rfcs/RFC-MIME.ps".into(),
+                site: Some("thumper.bellcore.com".into()),
+            }
+        );
+
+        let external = message.get_part(3).unwrap().get_external_body().unwrap();
+        assert!(matches!(
+            external.access_type,
+            AccessType::MailServer { .. }
+        ));
+        assert_eq!(external.body.trim(), "get RFC-MIME.DOC");
+
+        let message = Message::parse(
+            concat!(
+                "Content-Type: message/external-body; access-type=URL;\n",
+                "    size=1234; permission=read;\n",
+                "    URL=\"http://www.example.com/the-rain-in-spain/\n",
+                "         falls-mainly-on-the-plain.html\"\n",
+                "\n",
+                "Content-Type: text/html\n",
+                "\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let external = message.get_root_part().get_external_body().unwrap();
+        assert_eq!(
+            external.access_type,
+            AccessType::Url(
+                "http://www.example.com/the-rain-in-spain/falls-mainly-on-the-plain.html".into()
+            )
+        );
+        assert_eq!(external.size, Some(1234));
+        assert_eq!(external.permission.as_deref(), Some("read"));
+        assert_eq!(
+            external.get_content_type().unwrap().get_subtype(),
+            Some("html")
+        );
+    }
+}
@@ -83,6 +83,33 @@ impl DateTime {
             + ((self.tz_hour as i64 * 3600 + self.tz_minute as i64 * 60)
                 * if self.tz_before_gmt { 1 } else { -1 })
     }
+
+    /// Returns a UTC datetime from the number of seconds since the Unix epoch.
+    pub fn from_timestamp(timestamp: i64) -> Self {
+        // Ported from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let (days, seconds) = (timestamp.div_euclid(86400), timestamp.rem_euclid(86400));
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds % 3600 / 60) as u8,
+            second: (seconds % 60) as u8,
+            tz_before_gmt: false,
+            tz_hour: 0,
+            tz_minute: 0,
+        }
+    }
 }
 
 impl PartialOrd for DateTime {
@@ -269,7 +296,7 @@ mod tests {
 
     use crate::{
         parsers::{fields::date::parse_date, message::MessageStream},
-        HeaderValue,
+        DateTime, HeaderValue,
     };
 
     #[test]
@@ -340,6 +367,11 @@ mod tests {
                     assert_eq!(expected_result, datetime.to_rfc3339());
 
                     if datetime.is_valid() {
+                        assert_eq!(
+                            DateTime::from_timestamp(datetime.to_timestamp()).to_timestamp(),
+                            datetime.to_timestamp()
+                        );
+
                         if let LocalResult::Single(chrono_datetime)
                         | LocalResult::Ambiguous(chrono_datetime, _) = FixedOffset::west_opt(
                             ((datetime.tz_hour as i32 * 3600i32) + datetime.tz_minute as i32 * 60)
@@ -11,7 +11,9 @@
 
 use std::borrow::Cow;
 
-use crate::{Header, HeaderName, HeaderValue, RfcHeader};
+use crate::{
+    Group, Header, HeaderForm, HeaderName, HeaderParser, HeaderValue, MessageParser, RfcHeader,
+};
 
 use super::{
     fields::{
@@ -35,6 +37,15 @@ pub enum HeaderParserResult<'x> {
 }
 
 pub fn parse_headers<'x>(headers: &mut Vec<Header<'x>>, stream: &mut MessageStream<'x>) -> bool {
+    parse_headers_with(headers, stream, &MessageParser::default())
+}
+
+/// Parses header fields, using the custom header parsers registered with `parser`.
+pub fn parse_headers_with<'x>(
+    headers: &mut Vec<Header<'x>>,
+    stream: &mut MessageStream<'x>,
+    parser: &MessageParser,
+) -> bool {
     loop {
         let (bytes_read, result) = parse_header_name(&stream.data[stream.pos..]);
         stream.pos += bytes_read;
@@ -53,13 +64,29 @@ pub fn parse_headers<'x>(headers: &mut Vec<Header<'x>>, stream: &mut MessageStre
             }
             HeaderParserResult::Other(name) => {
                 let from_offset = stream.pos;
-                parse_and_ignore(stream);
+                let value = match parser.get_header_parser(&name) {
+                    Some(HeaderParser::Text) => parse_unstructured(stream),
+                    Some(HeaderParser::TextList) => parse_comma_separared(stream),
+                    Some(HeaderParser::Address) => parse_address(stream),
+                    Some(HeaderParser::Id) => parse_id(stream),
+                    Some(HeaderParser::Date) => parse_date(stream),
+                    Some(HeaderParser::ContentType) => parse_content_type(stream),
+                    Some(HeaderParser::Raw) => parse_raw(stream),
+                    Some(HeaderParser::Custom(parser)) => {
+                        parse_and_ignore(stream);
+                        parser(&stream.data[from_offset..stream.pos])
+                    }
+                    None => {
+                        parse_and_ignore(stream);
+                        HeaderValue::Text(String::from_utf8_lossy(
+                            &stream.data[from_offset..stream.pos],
+                        ))
+                    }
+                };
 
                 headers.push(Header {
                     name: HeaderName::Other(name),
-                    value: HeaderValue::Text(String::from_utf8_lossy(
-                        &stream.data[from_offset..stream.pos],
-                    )),
+                    value,
                     offset_start: from_offset,
                     offset_end: stream.pos,
                 });
@@ -138,9 +165,70 @@ impl From<RfcHeader> for u8 {
     }
 }
 
+/// Parses a raw header value, starting after the colon, in the requested form.
+pub fn parse_header_form<'x>(raw: &'x [u8], form: HeaderForm) -> HeaderValue<'x> {
+    let mut stream = MessageStream::new(raw);
+
+    match form {
+        HeaderForm::Raw => {
+            let raw = raw.strip_suffix(b"\n").unwrap_or(raw);
+            HeaderValue::Text(String::from_utf8_lossy(
+                raw.strip_suffix(b"\r").unwrap_or(raw),
+            ))
+        }
+        HeaderForm::Text => parse_unstructured(&mut stream),
+        HeaderForm::Addresses => match parse_address(&mut stream) {
+            HeaderValue::Group(group) => HeaderValue::AddressList(group.addresses),
+            HeaderValue::GroupList(groups) => HeaderValue::AddressList(
+                groups
+                    .into_iter()
+                    .flat_map(|group| group.addresses)
+                    .collect(),
+            ),
+            value => value,
+        },
+        HeaderForm::GroupedAddresses => match parse_address(&mut stream) {
+            HeaderValue::Address(address) => HeaderValue::Group(Group {
+                name: None,
+                addresses: vec![address],
+            }),
+            HeaderValue::AddressList(addresses) => HeaderValue::Group(Group {
+                name: None,
+                addresses,
+            }),
+            value => value,
+        },
+        HeaderForm::MessageIds => parse_id(&mut stream),
+        HeaderForm::Date => parse_date(&mut stream),
+        HeaderForm::URLs => {
+            let mut urls = match parse_address(&mut stream) {
+                HeaderValue::Address(address) => vec![address],
+                HeaderValue::AddressList(addresses) => addresses,
+                HeaderValue::Group(group) => group.addresses,
+                HeaderValue::GroupList(groups) => groups
+                    .into_iter()
+                    .flat_map(|group| group.addresses)
+                    .collect(),
+                _ => Vec::new(),
+            }
+            .into_iter()
+            .filter_map(|address| address.address)
+            .collect::<Vec<_>>();
+            match urls.len() {
+                0 => HeaderValue::Empty,
+                1 => HeaderValue::Text(urls.pop().unwrap()),
+                _ => HeaderValue::TextList(urls),
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{parsers::header::parse_header_name, RfcHeader};
+    use crate::{
+        parsers::header::parse_header_name, Addr, DateTime, Group, HeaderForm, HeaderParser,
+        HeaderValue, Message, MessageParser, RfcHeader,
+    };
 
     use super::HeaderParserResult;
 
@@ -171,6 +259,199 @@ mod tests {
             assert_eq!(input.1, result, "Failed to parse '{:?}'", input.0);
         }
     }
+
+    #[test]
+    fn header_form_parse() {
+        let message = Message::parse(
+            concat!(
+                "X-Sender: Friends: jane@example.com;, John <john@example.com>\r\n",
+                "X-Subject: =?utf-8?q?Gr=C3=BC=C3=9Fe?=\r\n  again \r\n",
+                "X-Ids: <a@example.com> <b@example.com>\r\n",
+                "X-Date: Mon, 15 Jan 2018 10:00:00 +0000\r\n",
+                "List-Post: <mailto:list@example.com>\r\n",
+                "X-Tenant: 1\r\n",
+                "X-Tenant: 2\r\n",
+                "Content-Type: message/rfc822\r\n\r\n",
+                "Subject: nested\r\n",
+                "X-Url: <https://example.com>, <mailto:a@example.com>\r\n\r\n",
+                "Body\r\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        for (name, form, expected) in [
+            (
+                "x-subject",
+                HeaderForm::Raw,
+                HeaderValue::Text(" =?utf-8?q?Gr=C3=BC=C3=9Fe?=\r\n  again ".into()),
+            ),
+            (
+                "X-Subject",
+                HeaderForm::Text,
+                HeaderValue::Text("Grüße again".into()),
+            ),
+            (
+                "X-Sender",
+                HeaderForm::Addresses,
+                HeaderValue::AddressList(vec![
+                    Addr::new(None, "jane@example.com"),
+                    Addr::new(Some("John"), "john@example.com"),
+                ]),
+            ),
+            (
+                "X-Sender",
+                HeaderForm::GroupedAddresses,
+                HeaderValue::GroupList(vec![
+                    Group::new("Friends", vec![Addr::new(None, "jane@example.com")]),
+                    Group {
+                        name: None,
+                        addresses: vec![Addr::new(Some("John"), "john@example.com")],
+                    },
+                ]),
+            ),
+            (
+                "X-Ids",
+                HeaderForm::MessageIds,
+                HeaderValue::TextList(vec!["a@example.com".into(), "b@example.com".into()]),
+            ),
+            (
+                "List-Post",
+                HeaderForm::URLs,
+                HeaderValue::Text("mailto:list@example.com".into()),
+            ),
+            ("X-Tenant", HeaderForm::Text, HeaderValue::Text("2".into())),
+        ] {
+            assert_eq!(
+                message.get_header_as(name, form),
+                Some(expected),
+                "{} {:?}",
+                name,
+                form
+            );
+        }
+        assert_eq!(
+            message
+                .get_header_as("X-Date", HeaderForm::Date)
+                .unwrap()
+                .as_datetime_ref()
+                .unwrap()
+                .to_rfc3339(),
+            "2018-01-15T10:00:00Z"
+        );
+        assert_eq!(
+            message.get_all_headers_as("x-tenant", HeaderForm::Raw),
+            vec![
+                HeaderValue::Text(" 1".into()),
+                HeaderValue::Text(" 2".into())
+            ]
+        );
+        assert_eq!(message.get_header_as("X-Missing", HeaderForm::Text), None);
+
+        // Offsets of nested messages refer to the top-level message
+        let nested = message.get_attachment(0).unwrap().get_message().unwrap();
+        assert_eq!(
+            nested.get_header_as("X-Url", HeaderForm::URLs),
+            Some(HeaderValue::TextList(vec![
+                "https://example.com".into(),
+                "mailto:a@example.com".into()
+            ]))
+        );
+        assert_eq!(
+            nested.parts[0].headers[0].parse_as(&message.raw_message, HeaderForm::Text),
+            HeaderValue::Text("nested".into())
+        );
+        assert_eq!(
+            HeaderForm::parse("asgroupedaddresses"),
+            Some(HeaderForm::GroupedAddresses)
+        );
+        assert_eq!(HeaderForm::parse("asFoo"), None);
+    }
+
+    #[test]
+    fn header_custom_parsers() {
+        fn parse_score(value: &[u8]) -> HeaderValue<'_> {
+            std::str::from_utf8(value)
+                .ok()
+                .and_then(|value| value.trim().parse::<f64>().ok())
+                .map_or(HeaderValue::Empty, |score| {
+                    HeaderValue::Text(format!("{:.1}", score).into())
+                })
+        }
+
+        let parser = MessageParser::new()
+            .with_header_parser("x-tenant-id", HeaderParser::Text)
+            .with_header_parser("X-Spam-Score", HeaderParser::Custom(parse_score))
+            .with_header_parser("X-Vendor-List", HeaderParser::Address)
+            .with_header_parser("X-Sent", HeaderParser::Date);
+        let raw_message = concat!(
+            "X-Tenant-ID: =?utf-8?q?T=C3=BCbingen?=\r\n",
+            "X-Spam-Score:  3.50\r\n",
+            "X-Vendor-List: <https://example.com/list>,\r\n <mailto:list@example.com>\r\n",
+            "X-Sent: Mon, 15 Jan 2018 10:00:00 +0000\r\n",
+            "X-Priority: 1 (Highest)\r\n",
+            "Subject: =?utf-8?q?Gr=C3=BC=C3=9Fe?=\r\n",
+            "Content-Type: message/rfc822\r\n\r\n",
+            "X-Tenant-Id: nested\r\n\r\n",
+            "Hi!\r\n"
+        );
+        let message = parser.parse(raw_message.as_bytes()).unwrap();
+
+        for (name, expected) in [
+            ("X-Tenant-Id", HeaderValue::Text("Tübingen".into())),
+            ("X-Spam-Score", HeaderValue::Text("3.5".into())),
+            (
+                "X-Vendor-List",
+                HeaderValue::AddressList(vec![
+                    Addr::new(None, "https://example.com/list"),
+                    Addr::new(None, "mailto:list@example.com"),
+                ]),
+            ),
+            (
+                "X-Sent",
+                HeaderValue::DateTime(DateTime {
+                    year: 2018,
+                    month: 1,
+                    day: 15,
+                    hour: 10,
+                    minute: 0,
+                    second: 0,
+                    tz_before_gmt: false,
+                    tz_hour: 0,
+                    tz_minute: 0,
+                }),
+            ),
+            ("X-Priority", HeaderValue::Text(" 1 (Highest)\r\n".into())),
+        ] {
+            assert_eq!(message.get_header(name), Some(&expected), "{}", name);
+        }
+        let nested = message
+            .get_attachment(0)
+            .and_then(|part| part.get_message())
+            .unwrap();
+        assert_eq!(
+            nested.get_header("X-Tenant-Id"),
+            Some(&HeaderValue::Text("nested".into()))
+        );
+
+        // Registrations for RFC headers are ignored
+        assert_eq!(
+            MessageParser::new()
+                .with_header_parser("subject", HeaderParser::Raw)
+                .parse(raw_message.as_bytes())
+                .unwrap()
+                .get_subject(),
+            Some("Grüße")
+        );
+
+        // The default configuration returns custom headers as raw text
+        assert_eq!(
+            Message::parse(raw_message.as_bytes())
+                .unwrap()
+                .get_header("X-Spam-Score"),
+            Some(&HeaderValue::Text("  3.50\r\n".into()))
+        );
+    }
 }
 
 #[allow(clippy::type_complexity)]
@@ -0,0 +1,1023 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use crate::{DateTime, Message, MimeHeaders, PartType};
+
+#[cfg(feature = "serde_support")]
+use serde::{Deserialize, Serialize};
+
+/// An RFC5545 content line, such as `DTSTART;TZID=Europe/Paris:20220101T100000`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct ContentLine {
+    /// Uppercase property name
+    pub name: String,
+    /// Parameters with uppercase names and their values
+    pub params: Vec<(String, Vec<String>)>,
+    /// Raw property value
+    pub value: String,
+}
+
+/// An RFC5545 component, such as `VCALENDAR`, `VEVENT` or `VTODO`.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct Component {
+    /// Uppercase component name
+    pub name: String,
+    pub properties: Vec<ContentLine>,
+    pub components: Vec<Component>,
+}
+
+/// An iCalendar DATE or DATE-TIME value.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct CalendarDateTime {
+    /// Date and local time, including the UTC offset when known
+    pub date_time: DateTime,
+    /// `true` for DATE values (all-day events)
+    pub is_date: bool,
+    /// `true` when the UTC offset could not be determined
+    pub is_floating: bool,
+    /// Time zone identifier, if any
+    pub tz_id: Option<String>,
+}
+
+/// The participation status of a calendar user.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub enum ParticipationStatus {
+    NeedsAction,
+    Accepted,
+    Declined,
+    Tentative,
+    Delegated,
+    Completed,
+    InProcess,
+    Other(String),
+}
+
+/// An organizer or attendee of a scheduled event.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct Attendee {
+    /// Calendar user address, without the `mailto:` prefix
+    pub address: String,
+    /// Common name
+    pub name: Option<String>,
+    pub status: ParticipationStatus,
+    pub role: Option<String>,
+    /// `true` if a reply is expected
+    pub rsvp: bool,
+}
+
+/// An RFC6047 iMIP scheduling message.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct CalendarInvite {
+    /// iTIP method, such as `REQUEST`, `REPLY` or `CANCEL`
+    pub method: Option<String>,
+    pub uid: Option<String>,
+    pub sequence: Option<u32>,
+    pub summary: Option<String>,
+    pub location: Option<String>,
+    pub organizer: Option<Attendee>,
+    pub attendees: Vec<Attendee>,
+    pub start: Option<CalendarDateTime>,
+    pub end: Option<CalendarDateTime>,
+    /// Recurrence rule (`RRULE` value)
+    pub recurrence: Option<String>,
+    /// The `VEVENT` or `VTODO` component of the invite
+    pub component: Component,
+}
+
+impl<'x> Message<'x> {
+    /// Returns the first iMIP (`text/calendar`) invitation found in the message.
+    pub fn get_calendar_invite(&self) -> Option<CalendarInvite> {
+        self.parts.iter().find_map(|part| {
+            let content_type = part.get_content_type()?;
+            let is_calendar = match (content_type.get_type(), content_type.get_subtype()) {
+                ("text", Some(subtype)) => subtype.eq_ignore_ascii_case("calendar"),
+                ("application", Some(subtype)) => subtype.eq_ignore_ascii_case("ics"),
+                _ => false,
+            };
+            if !is_calendar {
+                return None;
+            }
+
+            let contents = match &part.body {
+                PartType::Text(text) => text.as_ref().into(),
+                PartType::Binary(bin) | PartType::InlineBinary(bin) => {
+                    String::from_utf8_lossy(bin.as_ref())
+                }
+                _ => return None,
+            };
+
+            let calendar = parse_components(&contents)
+                .into_iter()
+                .find(|component| component.name == "VCALENDAR")?;
+            let mut invite = calendar.get_invite()?;
+            if invite.method.is_none() {
+                invite.method = content_type
+                    .get_attribute("method")
+                    .map(|method| method.to_ascii_uppercase());
+            }
+            invite.into()
+        })
+    }
+}
+
+impl Component {
+    /// Parses an iCalendar object, returning its first `VCALENDAR` component.
+    pub fn parse(text: &str) -> Option<Component> {
+        parse_components(text)
+            .into_iter()
+            .find(|component| component.name == "VCALENDAR")
+    }
+
+    /// Returns the first property with the specified name.
+    pub fn get_property(&self, name: &str) -> Option<&ContentLine> {
+        self.properties
+            .iter()
+            .find(|property| property.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns all properties with the specified name.
+    pub fn get_properties<'y>(&'y self, name: &'y str) -> impl Iterator<Item = &'y ContentLine> {
+        self.properties
+            .iter()
+            .filter(move |property| property.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns all sub-components with the specified name.
+    pub fn get_components<'y>(&'y self, name: &'y str) -> impl Iterator<Item = &'y Component> {
+        self.components
+            .iter()
+            .filter(move |component| component.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the unescaped text value of the first property with the specified name.
+    pub fn get_text(&self, name: &str) -> Option<String> {
+        self.get_property(name).map(|property| property.get_text())
+    }
+
+    /// Parses a DATE or DATE-TIME property, resolving its TZID parameter using
+    /// the `VTIMEZONE` definitions of this `VCALENDAR` component.
+    pub fn get_date_time(&self, property: &ContentLine) -> Option<CalendarDateTime> {
+        let (mut date_time, is_date, is_utc) = parse_date_time(&property.value)?;
+        let tz_id = property.get_param("TZID").map(|tz_id| tz_id.to_string());
+        let mut is_floating = !is_utc;
+
+        if !is_utc && !is_date {
+            if let Some(offset) = tz_id
+                .as_deref()
+                .and_then(|tz_id| self.get_utc_offset(tz_id, &date_time))
+            {
+                set_utc_offset(&mut date_time, offset);
+                is_floating = false;
+            }
+        }
+
+        Some(CalendarDateTime {
+            date_time,
+            is_date,
+            is_floating: is_floating && !is_date,
+            tz_id,
+        })
+    }
+
+    /// Returns the iMIP scheduling information of this `VCALENDAR` component.
+    pub fn get_invite(&self) -> Option<CalendarInvite> {
+        let component = self
+            .get_components("VEVENT")
+            .next()
+            .or_else(|| self.get_components("VTODO").next())?;
+        let is_event = component.name == "VEVENT";
+
+        let start = component
+            .get_property("DTSTART")
+            .and_then(|property| self.get_date_time(property));
+        let end = if let Some(end) = component
+            .get_property(if is_event { "DTEND" } else { "DUE" })
+            .and_then(|property| self.get_date_time(property))
+        {
+            Some(end)
+        } else if let Some(start) = &start {
+            // Calculate the end using the duration, all-day events last one day by default
+            component
+                .get_property("DURATION")
+                .and_then(|property| parse_duration(&property.value))
+                .or(if start.is_date && is_event {
+                    Some(86400)
+                } else {
+                    None
+                })
+                .map(|duration| {
+                    let mut end = start.clone();
+                    add_seconds(&mut end.date_time, duration);
+                    end
+                })
+        } else {
+            None
+        };
+
+        CalendarInvite {
+            method: self
+                .get_property("METHOD")
+                .map(|method| method.value.trim().to_ascii_uppercase()),
+            uid: component.get_text("UID"),
+            sequence: component
+                .get_property("SEQUENCE")
+                .and_then(|sequence| sequence.value.trim().parse().ok()),
+            summary: component.get_text("SUMMARY"),
+            location: component.get_text("LOCATION"),
+            organizer: component.get_property("ORGANIZER").map(Attendee::from),
+            attendees: component
+                .get_properties("ATTENDEE")
+                .map(Attendee::from)
+                .collect(),
+            start,
+            end,
+            recurrence: component
+                .get_property("RRULE")
+                .map(|rrule| rrule.value.clone()),
+            component: component.clone(),
+        }
+        .into()
+    }
+
+    /// Returns the UTC offset in seconds of a local time in the specified time zone.
+    fn get_utc_offset(&self, tz_id: &str, local_time: &DateTime) -> Option<i64> {
+        let time_zone = self.get_components("VTIMEZONE").find(|tz| {
+            tz.get_property("TZID")
+                .is_some_and(|id| id.value.trim() == tz_id)
+        })?;
+        let local_time = local_time.to_timestamp();
+        let mut current: Option<(i64, i64)> = None;
+        let mut first: Option<(i64, i64)> = None;
+
+        for observance in time_zone
+            .components
+            .iter()
+            .filter(|observance| observance.name == "STANDARD" || observance.name == "DAYLIGHT")
+        {
+            let (offset_from, offset_to, dt_start) = match (
+                observance
+                    .get_property("TZOFFSETFROM")
+                    .and_then(|offset| parse_utc_offset(&offset.value)),
+                observance
+                    .get_property("TZOFFSETTO")
+                    .and_then(|offset| parse_utc_offset(&offset.value)),
+                observance
+                    .get_property("DTSTART")
+                    .and_then(|dt_start| parse_date_time(&dt_start.value)),
+            ) {
+                (Some(offset_from), Some(offset_to), Some((dt_start, _, _))) => {
+                    (offset_from, offset_to, dt_start)
+                }
+                _ => continue,
+            };
+
+            let start = dt_start.to_timestamp();
+            if first.is_none_or(|(first_start, _)| start < first_start) {
+                first = Some((start, offset_from));
+            }
+
+            // Find the most recent onset of this observance
+            let mut onset = if start <= local_time {
+                Some(start)
+            } else {
+                None
+            };
+            for rdate in observance.get_properties("RDATE") {
+                for value in rdate.value.split(',') {
+                    if let Some((rdate, _, _)) = parse_date_time(value) {
+                        let rdate = rdate.to_timestamp();
+                        if rdate <= local_time && onset.is_none_or(|onset| rdate > onset) {
+                            onset = Some(rdate);
+                        }
+                    }
+                }
+            }
+            if let Some(rrule) = observance.get_property("RRULE") {
+                if let Some(rdate) = yearly_onset(&rrule.value, &dt_start, local_time) {
+                    if onset.is_none_or(|onset| rdate > onset) {
+                        onset = Some(rdate);
+                    }
+                }
+            }
+
+            if let Some(onset) = onset {
+                if current.is_none_or(|(current_onset, _)| onset > current_onset) {
+                    current = Some((onset, offset_to));
+                }
+            }
+        }
+
+        current.or(first).map(|(_, offset)| offset)
+    }
+}
+
+impl ContentLine {
+    /// Returns the first value of a parameter.
+    pub fn get_param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .and_then(|(_, values)| values.first())
+            .map(|value| value.as_str())
+    }
+
+    /// Returns all the values of a parameter.
+    pub fn get_param_values<'y>(&'y self, name: &'y str) -> impl Iterator<Item = &'y str> {
+        self.params
+            .iter()
+            .filter(move |(param, _)| param.eq_ignore_ascii_case(name))
+            .flat_map(|(_, values)| values.iter().map(|value| value.as_str()))
+    }
+
+    /// Returns the value with the TEXT escape sequences removed.
+    pub fn get_text(&self) -> String {
+        let mut text = String::with_capacity(self.value.len());
+        let mut is_escaped = false;
+
+        for ch in self.value.chars() {
+            if is_escaped {
+                text.push(match ch {
+                    'n' | 'N' => '\n',
+                    _ => ch,
+                });
+                is_escaped = false;
+            } else if ch == '\\' {
+                is_escaped = true;
+            } else {
+                text.push(ch);
+            }
+        }
+
+        text
+    }
+}
+
+impl From<&ContentLine> for Attendee {
+    fn from(property: &ContentLine) -> Self {
+        let address = property.value.trim();
+        Attendee {
+            address: if address
+                .get(..7)
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case("mailto:"))
+            {
+                address[7..].to_string()
+            } else {
+                address.to_string()
+            },
+            name: property.get_param("CN").map(|name| name.to_string()),
+            status: property
+                .get_param("PARTSTAT")
+                .map(ParticipationStatus::from)
+                .unwrap_or(ParticipationStatus::NeedsAction),
+            role: property
+                .get_param("ROLE")
+                .map(|role| role.to_ascii_uppercase()),
+            rsvp: property
+                .get_param("RSVP")
+                .is_some_and(|rsvp| rsvp.eq_ignore_ascii_case("TRUE")),
+        }
+    }
+}
+
+impl From<&str> for ParticipationStatus {
+    fn from(value: &str) -> Self {
+        match value.to_ascii_uppercase().as_str() {
+            "NEEDS-ACTION" => ParticipationStatus::NeedsAction,
+            "ACCEPTED" => ParticipationStatus::Accepted,
+            "DECLINED" => ParticipationStatus::Declined,
+            "TENTATIVE" => ParticipationStatus::Tentative,
+            "DELEGATED" => ParticipationStatus::Delegated,
+            "COMPLETED" => ParticipationStatus::Completed,
+            "IN-PROCESS" => ParticipationStatus::InProcess,
+            other => ParticipationStatus::Other(other.to_string()),
+        }
+    }
+}
+
+/// Parses all the top-level `BEGIN`/`END` components of an iCalendar or vCard object.
+pub fn parse_components(text: &str) -> Vec<Component> {
+    let mut components = Vec::new();
+    let mut stack: Vec<Component> = Vec::new();
+
+    for line in unfold_lines(text) {
+        let line = match parse_content_line(&line) {
+            Some(line) => line,
+            None => continue,
+        };
+
+        if line.name == "BEGIN" {
+            stack.push(Component {
+                name: line.value.trim().to_ascii_uppercase(),
+                ..Default::default()
+            });
+        } else if line.name == "END" {
+            let name = line.value.trim().to_ascii_uppercase();
+
+            // Close any components left open
+            if stack.iter().any(|component| component.name == name) {
+                while let Some(component) = stack.pop() {
+                    let is_done = component.name == name;
+                    if let Some(parent) = stack.last_mut() {
+                        parent.components.push(component);
+                    } else {
+                        components.push(component);
+                    }
+                    if is_done {
+                        break;
+                    }
+                }
+            }
+        } else if let Some(component) = stack.last_mut() {
+            component.properties.push(line);
+        }
+    }
+
+    // Add unterminated components
+    while let Some(component) = stack.pop() {
+        if let Some(parent) = stack.last_mut() {
+            parent.components.push(component);
+        } else {
+            components.push(component);
+        }
+    }
+
+    components
+}
+
+/// Unfolds the content lines of an iCalendar or vCard object.
+pub(crate) fn unfold_lines(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut is_soft_break = false;
+
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if is_soft_break {
+            // Quoted-printable soft line break, used by vCard 2.1 and vCalendar 1.0
+            let last = lines.last_mut().unwrap();
+            last.pop();
+            last.push_str(line);
+        } else {
+            match line.chars().next() {
+                Some(' ' | '\t') if !lines.is_empty() => {
+                    lines.last_mut().unwrap().push_str(&line[1..]);
+                }
+                Some(_) => {
+                    lines.push(line.to_string());
+                }
+                None => continue,
+            }
+        }
+
+        is_soft_break = lines.last().is_some_and(|line| {
+            line.ends_with('=')
+                && line.split_once(':').is_some_and(|(params, _)| {
+                    params.to_ascii_uppercase().contains("QUOTED-PRINTABLE")
+                })
+        });
+    }
+
+    lines
+}
+
+/// Parses an unfolded content line.
+pub(crate) fn parse_content_line(line: &str) -> Option<ContentLine> {
+    let name_end = line.find([';', ':'])?;
+    let name = line[..name_end].trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut params: Vec<(String, Vec<String>)> = Vec::new();
+    let mut chars = line[name_end..]
+        .char_indices()
+        .map(|(pos, ch)| (pos + name_end, ch));
+    let mut last_ch = line[name_end..].chars().next()?;
+    chars.next();
+
+    while last_ch == ';' {
+        // Parameter name
+        let mut param_name = String::new();
+        let mut values = Vec::new();
+        last_ch = '\0';
+        for (_, ch) in chars.by_ref() {
+            match ch {
+                '=' | ';' | ':' => {
+                    last_ch = ch;
+                    break;
+                }
+                _ => param_name.push(ch),
+            }
+        }
+
+        // Parameter values
+        if last_ch == '=' {
+            let mut value = String::new();
+            let mut in_quotes = false;
+            last_ch = '\0';
+
+            for (_, ch) in chars.by_ref() {
+                match ch {
+                    '"' => in_quotes = !in_quotes,
+                    ',' | ';' | ':' if !in_quotes => {
+                        values.push(unescape_param(&value));
+                        value.clear();
+                        if ch != ',' {
+                            last_ch = ch;
+                            break;
+                        }
+                    }
+                    _ => value.push(ch),
+                }
+            }
+            if last_ch == '\0' {
+                return None;
+            }
+        }
+
+        // vCard 2.1 parameters might not have a value, such as 'TEL;WORK:'
+        let param_name = param_name.trim().to_ascii_uppercase();
+        if !param_name.is_empty() {
+            params.push((param_name, values));
+        }
+    }
+
+    if last_ch == ':' {
+        Some(ContentLine {
+            name: name.to_ascii_uppercase(),
+            params,
+            value: chars
+                .next()
+                .map(|(pos, _)| line[pos..].to_string())
+                .unwrap_or_default(),
+        })
+    } else {
+        None
+    }
+}
+
+/// Decodes RFC6868 parameter value encoding.
+fn unescape_param(value: &str) -> String {
+    if !value.contains('^') {
+        return value.to_string();
+    }
+
+    let mut result = String::with_capacity(value.len());
+    let mut is_escaped = false;
+    for ch in value.chars() {
+        if is_escaped {
+            match ch {
+                'n' => result.push('\n'),
+                '\'' => result.push('"'),
+                '^' => result.push('^'),
+                _ => {
+                    result.push('^');
+                    result.push(ch);
+                }
+            }
+            is_escaped = false;
+        } else if ch == '^' {
+            is_escaped = true;
+        } else {
+            result.push(ch);
+        }
+    }
+    if is_escaped {
+        result.push('^');
+    }
+
+    result
+}
+
+/// Parses a `YYYYMMDD` or `YYYYMMDDTHHMMSS[Z]` value, returning the local date,
+/// whether it is a DATE value and whether it is in UTC.
+fn parse_date_time(value: &str) -> Option<(DateTime, bool, bool)> {
+    let value = value.trim().as_bytes();
+    let number = |range: std::ops::Range<usize>| -> Option<u32> {
+        let digits = value.get(range)?;
+        if digits.iter().all(|ch| ch.is_ascii_digit()) {
+            std::str::from_utf8(digits).ok()?.parse().ok()
+        } else {
+            None
+        }
+    };
+
+    let mut date_time = DateTime {
+        year: number(0..4)? as u16,
+        month: number(4..6)? as u8,
+        day: number(6..8)? as u8,
+        hour: 0,
+        minute: 0,
+        second: 0,
+        tz_before_gmt: false,
+        tz_hour: 0,
+        tz_minute: 0,
+    };
+
+    let (is_date, is_utc) = match value.get(8) {
+        None => (true, false),
+        Some(b'T' | b't') => {
+            date_time.hour = number(9..11)? as u8;
+            date_time.minute = number(11..13)? as u8;
+            date_time.second = number(13..15)? as u8;
+            (false, matches!(value.get(15), Some(b'Z' | b'z')))
+        }
+        _ => return None,
+    };
+
+    if (1..=12).contains(&date_time.month)
+        && (1..=31).contains(&date_time.day)
+        && date_time.hour <= 23
+        && date_time.minute <= 59
+        && date_time.second <= 60
+    {
+        Some((date_time, is_date, is_utc))
+    } else {
+        None
+    }
+}
+
+/// Parses a `+HHMM[SS]` or `-HHMM[SS]` UTC offset, returning the offset in seconds.
+fn parse_utc_offset(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (sign, value) = match value.as_bytes().first()? {
+        b'+' => (1, &value[1..]),
+        b'-' => (-1, &value[1..]),
+        _ => return None,
+    };
+    if !(value.len() == 4 || value.len() == 6) || !value.bytes().all(|ch| ch.is_ascii_digit()) {
+        return None;
+    }
+
+    let hour: i64 = value[0..2].parse().ok()?;
+    let minute: i64 = value[2..4].parse().ok()?;
+    let second: i64 = value.get(4..6).map_or(Some(0), |s| s.parse().ok())?;
+    Some(sign * (hour * 3600 + minute * 60 + second))
+}
+
+/// Parses an RFC5545 DURATION value such as `P1W`, `PT1H30M` or `-P1D`, returning
+/// the number of seconds.
+fn parse_duration(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (sign, value) = match value.as_bytes().first()? {
+        b'-' => (-1, &value[1..]),
+        b'+' => (1, &value[1..]),
+        _ => (1, value),
+    };
+    let value = value.strip_prefix(['P', 'p'])?;
+
+    let mut seconds = 0;
+    let mut number: Option<i64> = None;
+    for ch in value.chars() {
+        match ch.to_ascii_uppercase() {
+            '0'..='9' => {
+                number = Some(number.unwrap_or(0) * 10 + ch.to_digit(10)? as i64);
+            }
+            'T' => (),
+            unit => {
+                seconds += number.take()?
+                    * match unit {
+                        'W' => 7 * 86400,
+                        'D' => 86400,
+                        'H' => 3600,
+                        'M' => 60,
+                        'S' => 1,
+                        _ => return None,
+                    };
+            }
+        }
+    }
+
+    if number.is_none() {
+        Some(sign * seconds)
+    } else {
+        None
+    }
+}
+
+/// Returns the most recent onset before `local_time` of a yearly time zone
+/// transition rule, such as `FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU`.
+fn yearly_onset(rrule: &str, dt_start: &DateTime, local_time: i64) -> Option<i64> {
+    let mut month = dt_start.month;
+    let mut by_day = None;
+    let mut by_month_day = Vec::new();
+    let mut until = None;
+
+    for (name, value) in rrule
+        .split(';')
+        .filter_map(|part| part.split_once('='))
+        .map(|(name, value)| (name.trim().to_ascii_uppercase(), value.trim()))
+    {
+        match name.as_str() {
+            "FREQ" if !value.eq_ignore_ascii_case("YEARLY") => return None,
+            "BYMONTH" => month = value.parse().ok()?,
+            "BYDAY" => {
+                let value = value.to_ascii_uppercase();
+                let (ordinal, weekday) = value.split_at(value.len().checked_sub(2)?);
+                by_day = Some((
+                    if !ordinal.is_empty() {
+                        Some(ordinal.trim_start_matches('+').parse::<i32>().ok()?)
+                    } else {
+                        None
+                    },
+                    ["SU", "MO", "TU", "WE", "TH", "FR", "SA"]
+                        .iter()
+                        .position(|day| *day == weekday)? as i64,
+                ));
+            }
+            "BYMONTHDAY" => {
+                by_month_day = value
+                    .split(',')
+                    .filter_map(|day| day.trim().parse::<u8>().ok())
+                    .collect();
+            }
+            "UNTIL" => {
+                until = parse_date_time(value).map(|(until, _, _)| until.to_timestamp());
+            }
+            _ => (),
+        }
+    }
+
+    let start = dt_start.to_timestamp();
+    let year = DateTime::from_timestamp(local_time).year;
+
+    for year in [year, year.saturating_sub(1)] {
+        let day = match (by_day, by_month_day.is_empty()) {
+            (Some((Some(ordinal), weekday)), _) => nth_weekday(year, month, ordinal, weekday)?,
+            (Some((None, weekday)), false) => *by_month_day
+                .iter()
+                .find(|&&day| weekday_of(year, month, day) == weekday)?,
+            (None, false) => by_month_day[0],
+            _ => dt_start.day,
+        };
+        let onset = DateTime {
+            year,
+            month,
+            day,
+            ..dt_start.clone()
+        }
+        .to_timestamp();
+
+        if onset <= local_time && onset >= start && until.is_none_or(|until| onset <= until) {
+            return Some(onset);
+        }
+    }
+
+    None
+}
+
+/// Returns the day of the month of the nth (or nth last, if negative) weekday.
+fn nth_weekday(year: u16, month: u8, ordinal: i32, weekday: i64) -> Option<u8> {
+    let days_in_month = (28..=31)
+        .rev()
+        .find(|&day| {
+            DateTime::from_timestamp(
+                DateTime {
+                    year,
+                    month,
+                    day,
+                    hour: 0,
+                    minute: 0,
+                    second: 0,
+                    tz_before_gmt: false,
+                    tz_hour: 0,
+                    tz_minute: 0,
+                }
+                .to_timestamp(),
+            )
+            .month
+                == month
+        })
+        .unwrap_or(28);
+
+    let day = if ordinal > 0 {
+        let first = weekday_of(year, month, 1);
+        1 + (weekday - first).rem_euclid(7) as i32 + (ordinal - 1) * 7
+    } else if ordinal < 0 {
+        let last = weekday_of(year, month, days_in_month);
+        days_in_month as i32 - (last - weekday).rem_euclid(7) as i32 + (ordinal + 1) * 7
+    } else {
+        return None;
+    };
+
+    if (1..=days_in_month as i32).contains(&day) {
+        Some(day as u8)
+    } else {
+        None
+    }
+}
+
+/// Returns the day of the week, where 0 is Sunday.
+fn weekday_of(year: u16, month: u8, day: u8) -> i64 {
+    let days = DateTime {
+        year,
+        month,
+        day,
+        hour: 0,
+        minute: 0,
+        second: 0,
+        tz_before_gmt: false,
+        tz_hour: 0,
+        tz_minute: 0,
+    }
+    .to_timestamp()
+    .div_euclid(86400);
+
+    // 1970-01-01 was a Thursday
+    (days + 4).rem_euclid(7)
+}
+
+fn set_utc_offset(date_time: &mut DateTime, offset: i64) {
+    date_time.tz_before_gmt = offset < 0;
+    date_time.tz_hour = (offset.abs() / 3600) as u8;
+    date_time.tz_minute = (offset.abs() % 3600 / 60) as u8;
+}
+
+fn add_seconds(date_time: &mut DateTime, seconds: i64) {
+    let mut result = DateTime::from_timestamp(
+        DateTime {
+            tz_before_gmt: false,
+            tz_hour: 0,
+            tz_minute: 0,
+            ..date_time.clone()
+        }
+        .to_timestamp()
+            + seconds,
+    );
+    result.tz_before_gmt = date_time.tz_before_gmt;
+    result.tz_hour = date_time.tz_hour;
+    result.tz_minute = date_time.tz_minute;
+    *date_time = result;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parsers::icalendar::{Component, ParticipationStatus},
+        Message,
+    };
+
+    #[test]
+    fn parse_calendar_invite() {
+        let message = Message::parse(
+            concat!(
+                "From: organizer@example.com\r\n",
+                "To: jane@example.com\r\n",
+                "Subject: Invitation: Project review\r\n",
+                "Content-Type: multipart/alternative; boundary=\"boundary\"\r\n",
+                "\r\n",
+                "--boundary\r\n",
+                "Content-Type: text/plain\r\n",
+                "\r\n",
+                "You have been invited.\r\n",
+                "--boundary\r\n",
+                "Content-Type: text/calendar; charset=utf-8; method=REQUEST\r\n",
+                "\r\n",
+                "BEGIN:VCALENDAR\r\n",
+                "PRODID:-//Example Corp.//CalDAV Client//EN\r\n",
+                "VERSION:2.0\r\n",
+                "BEGIN:VTIMEZONE\r\n",
+                "TZID:Europe/Berlin\r\n",
+                "BEGIN:DAYLIGHT\r\n",
+                "TZOFFSETFROM:+0100\r\n",
+                "TZOFFSETTO:+0200\r\n",
+                "TZNAME:CEST\r\n",
+                "DTSTART:19700329T020000\r\n",
+                "RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\r\n",
+                "END:DAYLIGHT\r\n",
+                "BEGIN:STANDARD\r\n",
+                "TZOFFSETFROM:+0200\r\n",
+                "TZOFFSETTO:+0100\r\n",
+                "TZNAME:CET\r\n",
+                "DTSTART:19701025T030000\r\n",
+                "RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\r\n",
+                "END:STANDARD\r\n",
+                "END:VTIMEZONE\r\n",
+                "BEGIN:VEVENT\r\n",
+                "UID:20220315T101500-1234@example.com\r\n",
+                "SEQUENCE:2\r\n",
+                "DTSTAMP:20220301T120000Z\r\n",
+                "DTSTART;TZID=Europe/Berlin:20220329T100000\r\n",
+                "DURATION:PT1H30M\r\n",
+                "RRULE:FREQ=WEEKLY;COUNT=4\r\n",
+                "SUMMARY:Project review\\, Q1\r\n",
+                "LOCATION:Room 1\\; Building A\r\n",
+                "ORGANIZER;CN=\"Doe, John\":mailto:organizer@example.com\r\n",
+                "ATTENDEE;CN=Jane;ROLE=REQ-PARTICIPANT;PARTSTAT=NEEDS-ACTION;\r\n",
+                " RSVP=TRUE:mailto:jane@example.com\r\n",
+                "ATTENDEE;PARTSTAT=ACCEPTED;DELEGATED-FROM=\"mailto:a@example.com\",\r\n",
+                " \"mailto:b@example.com\":MAILTO:bob@exam\r\n",
+                "\tple.com\r\n",
+                "END:VEVENT\r\n",
+                "END:VCALENDAR\r\n",
+                "--boundary--\r\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let invite = message.get_calendar_invite().unwrap();
+        assert_eq!(invite.method.as_deref(), Some("REQUEST"));
+        assert_eq!(
+            invite.uid.as_deref(),
+            Some("20220315T101500-1234@example.com")
+        );
+        assert_eq!(invite.sequence, Some(2));
+        assert_eq!(invite.summary.as_deref(), Some("Project review, Q1"));
+        assert_eq!(invite.location.as_deref(), Some("Room 1; Building A"));
+        assert_eq!(invite.recurrence.as_deref(), Some("FREQ=WEEKLY;COUNT=4"));
+
+        let organizer = invite.organizer.unwrap();
+        assert_eq!(organizer.address, "organizer@example.com");
+        assert_eq!(organizer.name.as_deref(), Some("Doe, John"));
+
+        assert_eq!(invite.attendees.len(), 2);
+        assert_eq!(invite.attendees[0].address, "jane@example.com");
+        assert_eq!(invite.attendees[0].name.as_deref(), Some("Jane"));
+        assert_eq!(invite.attendees[0].status, ParticipationStatus::NeedsAction);
+        assert_eq!(invite.attendees[0].role.as_deref(), Some("REQ-PARTICIPANT"));
+        assert!(invite.attendees[0].rsvp);
+        assert_eq!(invite.attendees[1].address, "bob@example.com");
+        assert_eq!(invite.attendees[1].status, ParticipationStatus::Accepted);
+        assert!(!invite.attendees[1].rsvp);
+        assert_eq!(
+            invite
+                .component
+                .get_properties("ATTENDEE")
+                .nth(1)
+                .unwrap()
+                .get_param_values("DELEGATED-FROM")
+                .collect::<Vec<_>>(),
+            vec!["mailto:a@example.com", "mailto:b@example.com"]
+        );
+
+        // Daylight saving time starts on 2022-03-27
+        let start = invite.start.unwrap();
+        assert_eq!(start.date_time.to_rfc3339(), "2022-03-29T10:00:00+02:00");
+        assert_eq!(start.tz_id.as_deref(), Some("Europe/Berlin"));
+        assert!(!start.is_date && !start.is_floating);
+        assert_eq!(
+            invite.end.unwrap().date_time.to_rfc3339(),
+            "2022-03-29T11:30:00+02:00"
+        );
+
+        // Standard time, all-day and floating events
+        let calendar = Component::parse(concat!(
+            "BEGIN:VCALENDAR\n",
+            "METHOD:CANCEL\n",
+            "BEGIN:VTIMEZONE\n",
+            "TZID:Europe/Berlin\n",
+            "BEGIN:DAYLIGHT\n",
+            "TZOFFSETFROM:+0100\n",
+            "TZOFFSETTO:+0200\n",
+            "DTSTART:19700329T020000\n",
+            "RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\n",
+            "END:DAYLIGHT\n",
+            "BEGIN:STANDARD\n",
+            "TZOFFSETFROM:+0200\n",
+            "TZOFFSETTO:+0100\n",
+            "DTSTART:19701025T030000\n",
+            "RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\n",
+            "END:STANDARD\n",
+            "END:VTIMEZONE\n",
+            "BEGIN:VEVENT\n",
+            "DTSTART;TZID=Europe/Berlin:20221030T100000\n",
+            "DTEND;TZID=America/New_York:20221030T120000\n",
+            "END:VEVENT\n",
+            "BEGIN:VEVENT\n",
+            "DTSTART;VALUE=DATE:20221224\n",
+            "END:VEVENT\n",
+            "END:VCALENDAR\n",
+        ))
+        .unwrap();
+        let invite = calendar.get_invite().unwrap();
+        assert_eq!(invite.method.as_deref(), Some("CANCEL"));
+        assert_eq!(
+            invite.start.unwrap().date_time.to_rfc3339(),
+            "2022-10-30T10:00:00+01:00"
+        );
+        let end = invite.end.unwrap();
+        assert!(end.is_floating);
+        assert_eq!(end.tz_id.as_deref(), Some("America/New_York"));
+
+        let event = calendar.get_components("VEVENT").nth(1).unwrap();
+        let start = calendar
+            .get_date_time(event.get_property("DTSTART").unwrap())
+            .unwrap();
+        assert!(start.is_date && !start.is_floating);
+        assert_eq!(start.date_time.to_rfc3339(), "2022-12-24T00:00:00Z");
+    }
+}
@@ -14,14 +14,15 @@ use std::borrow::Cow;
 use crate::{
     decoders::{
         base64::decode_base64, charsets::map::get_charset_decoder,
-        quoted_printable::decode_quoted_printable, DecodeFnc, DecodeResult,
+        quoted_printable::decode_quoted_printable, uuencode::decode_uuencode, DecodeFnc,
+        DecodeResult,
     },
-    ContentType, Encoding, GetHeader, HeaderValue, Message, MessageAttachment, MessagePart,
-    MessagePartId, PartType, RfcHeader,
+    ContentType, Encoding, GetHeader, HeaderValue, Message, MessageAttachment, MessageParser,
+    MessagePart, MessagePartId, PartType, RfcHeader,
 };
 
 use super::{
-    header::parse_headers,
+    header::parse_headers_with,
     mime::{get_bytes_to_boundary, seek_crlf, seek_next_part, skip_crlf, skip_multipart_end},
 };
 
@@ -170,6 +171,14 @@ pub struct MessageStream<'x> {
     pub pos: usize,
 }
 
+impl MessageParser {
+    /// Parses a byte slice containing the RFC5322 raw message using this
+    /// configuration, see [`Message::parse`].
+    pub fn parse<'x>(&self, raw_message: &'x [u8]) -> Option<Message<'x>> {
+        Message::parse_with(raw_message, self)
+    }
+}
+
 impl<'x> MessageStream<'x> {
     pub fn new(data: &'x [u8]) -> MessageStream<'x> {
         MessageStream { data, pos: 0 }
@@ -195,6 +204,10 @@ impl<'x> Message<'x> {
     /// if no headers are found None is returned.
     ///
     pub fn parse(raw_message: &'x [u8]) -> Option<Message<'x>> {
+        Message::parse_with(raw_message, &MessageParser::default())
+    }
+
+    fn parse_with(raw_message: &'x [u8], parser: &MessageParser) -> Option<Message<'x>> {
         let mut stream = MessageStream::new(raw_message);
 
         let mut message = Message::new();
@@ -207,7 +220,7 @@ impl<'x> Message<'x> {
         'outer: loop {
             // Parse headers
             state.offset_header = stream.pos;
-            if !parse_headers(&mut part_headers, &mut stream) {
+            if !parse_headers_with(&mut part_headers, &mut stream, parser) {
                 break;
             }
 
@@ -279,6 +292,13 @@ impl<'x> Message<'x> {
                     {
                         (false, Encoding::QuotedPrintable, decode_quoted_printable)
                     }
+                    Some(HeaderValue::Text(encoding))
+                        if encoding.eq_ignore_ascii_case("x-uuencode")
+                            || encoding.eq_ignore_ascii_case("x-uue")
+                            || encoding.eq_ignore_ascii_case("uuencode") =>
+                    {
+                        (false, Encoding::UuEncode, decode_uuencode)
+                    }
                     _ => (true, Encoding::None, get_bytes_to_boundary),
                 };
 
@@ -9,8 +9,13 @@
  * except according to those terms.
  */
 
+pub mod embedded;
+pub mod external;
 pub mod fields;
 pub mod header;
+pub mod icalendar;
 pub mod message;
 pub mod mime;
+pub mod partial;
 pub mod preview;
+pub mod vcard;
@@ -0,0 +1,352 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{Header, HeaderName, Message, MimeHeaders, RfcHeader};
+
+use super::{header::parse_headers, message::MessageStream};
+
+/// Reassembles messages split into `message/partial` fragments as defined in
+/// [RFC 2046 - Section 5.2.2](https://datatracker.ietf.org/doc/html/rfc2046#section-5.2.2).
+///
+/// Fragments are collected by their `id` parameter and, once all of them have been
+/// received, the raw reconstructed message is returned so it can be parsed
+/// with `Message::parse`.
+#[derive(Debug, Default)]
+pub struct PartialMessageAssembler {
+    messages: HashMap<String, PartialMessage>,
+}
+
+#[derive(Debug, Default)]
+struct PartialMessage {
+    total: Option<usize>,
+    fragments: BTreeMap<usize, Vec<u8>>,
+}
+
+/// Iterator adapter that reassembles the `message/partial` fragments found in a
+/// sequence of raw messages, such as the contents of an mbox or Maildir.
+///
+/// Messages that are not fragments are returned unchanged, fragments are
+/// held until the message they belong to is complete. Fragments of incomplete
+/// messages are returned unchanged once the underlying iterator is exhausted.
+pub struct PartialMessageIterator<T: Iterator<Item = Vec<u8>>> {
+    it: T,
+    assembler: PartialMessageAssembler,
+    incomplete: Option<std::vec::IntoIter<Vec<u8>>>,
+}
+
+impl PartialMessageAssembler {
+    /// Creates a new `message/partial` reassembler.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a message to the reassembler. Returns `Err` with the message if it
+    /// is not a `message/partial` fragment, `Ok(None)` if more fragments are
+    /// needed and `Ok(Some(raw_message))` once the original message has been
+    /// reconstructed.
+    pub fn add<'x>(&mut self, message: Message<'x>) -> Result<Option<Vec<u8>>, Message<'x>> {
+        let (id, number, total) = match message.get_content_type() {
+            Some(ct) if ct.get_type() == "message" && ct.get_subtype() == Some("partial") => {
+                match (
+                    ct.get_attribute("id"),
+                    ct.get_attribute("number").and_then(|n| n.parse().ok()),
+                ) {
+                    (Some(id), Some(number)) if number > 0 => (
+                        id.to_string(),
+                        number,
+                        ct.get_attribute("total").and_then(|n| n.parse().ok()),
+                    ),
+                    _ => return Err(message),
+                }
+            }
+            _ => return Err(message),
+        };
+
+        let partial = self.messages.entry(id).or_default();
+        if total.is_some() {
+            partial.total = total;
+        }
+        partial
+            .fragments
+            .insert(number, message.raw_message.into_owned());
+
+        Ok(self.try_assemble())
+    }
+
+    /// Parses a raw message and adds it to the reassembler, see `add`.
+    pub fn add_raw(&mut self, raw_message: Vec<u8>) -> Result<Option<Vec<u8>>, Vec<u8>> {
+        let result = match Message::parse(&raw_message) {
+            Some(message) => self.add(message).map_err(|_| ()),
+            None => Err(()),
+        };
+        result.map_err(|_| raw_message)
+    }
+
+    /// Returns the number of messages pending reassembly.
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Returns `true` if there are no messages pending reassembly.
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Returns the raw fragments of all incomplete messages.
+    pub fn into_fragments(self) -> Vec<Vec<u8>> {
+        let mut messages = self.messages.into_iter().collect::<Vec<_>>();
+        messages.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        messages
+            .into_iter()
+            .flat_map(|(_, partial)| partial.fragments.into_values())
+            .collect()
+    }
+
+    fn try_assemble(&mut self) -> Option<Vec<u8>> {
+        let id = self
+            .messages
+            .iter()
+            .find(|(_, partial)| partial.is_complete())
+            .map(|(id, _)| id.clone())?;
+        self.messages.remove(&id)?.assemble()
+    }
+}
+
+impl PartialMessage {
+    fn is_complete(&self) -> bool {
+        self.total.is_some_and(|total| {
+            self.fragments.len() == total && self.fragments.keys().copied().eq(1..=total)
+        })
+    }
+
+    fn assemble(self) -> Option<Vec<u8>> {
+        let mut fragments = self.fragments.into_values();
+        let first = fragments.next()?;
+        let first = Message::parse(&first)?;
+        let root = first.get_root_part();
+        let raw = first.raw_message.as_ref();
+        let body = raw
+            .get(root.offset_body..root.offset_end)
+            .unwrap_or_default();
+
+        // Parse the headers of the enclosed message
+        let mut stream = MessageStream::new(body);
+        let mut enclosed_headers = Vec::new();
+        let has_body = parse_headers(&mut enclosed_headers, &mut stream);
+
+        let mut result = Vec::with_capacity(raw.len() * 2);
+
+        // Copy the enclosing headers, except Content-*, Subject, Message-ID,
+        // Encrypted and MIME-Version.
+        for header in &root.headers {
+            if !is_enclosed_header(&header.name) {
+                add_header(&mut result, header, raw);
+            }
+        }
+
+        // Append the Content-*, Subject, Message-ID, Encrypted and MIME-Version
+        // headers of the enclosed message, ignore any other headers.
+        for header in &enclosed_headers {
+            if is_enclosed_header(&header.name) {
+                add_header(&mut result, header, body);
+            }
+        }
+
+        if has_body {
+            result.extend_from_slice(&body[stream.pos..]);
+        } else {
+            result.extend_from_slice(b"\r\n");
+        }
+
+        // Append the contents of the remaining fragments
+        for fragment in fragments {
+            if let Some(message) = Message::parse(&fragment) {
+                let root = message.get_root_part();
+                if let Some(body) = message.raw_message.get(root.offset_body..root.offset_end) {
+                    result.extend_from_slice(body);
+                }
+            }
+        }
+
+        result.into()
+    }
+}
+
+impl<T: Iterator<Item = Vec<u8>>> PartialMessageIterator<T> {
+    /// Creates a new iterator that reassembles `message/partial` fragments.
+    pub fn new(it: T) -> Self {
+        PartialMessageIterator {
+            it,
+            assembler: PartialMessageAssembler::new(),
+            incomplete: None,
+        }
+    }
+}
+
+impl<T: Iterator<Item = Vec<u8>>> Iterator for PartialMessageIterator<T> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(incomplete) = &mut self.incomplete {
+            return incomplete.next();
+        }
+
+        for raw_message in self.it.by_ref() {
+            match self.assembler.add_raw(raw_message) {
+                Ok(Some(raw_message)) | Err(raw_message) => return Some(raw_message),
+                Ok(None) => (),
+            }
+        }
+
+        let mut incomplete = std::mem::take(&mut self.assembler)
+            .into_fragments()
+            .into_iter();
+        let next = incomplete.next();
+        self.incomplete = incomplete.into();
+        next
+    }
+}
+
+fn is_enclosed_header(name: &HeaderName) -> bool {
+    match name {
+        HeaderName::Rfc(header) => {
+            header.is_mime_header()
+                || matches!(
+                    header,
+                    RfcHeader::Subject | RfcHeader::MessageId | RfcHeader::MimeVersion
+                )
+        }
+        HeaderName::Other(name) => {
+            name.eq_ignore_ascii_case("Encrypted")
+                || name
+                    .get(..8)
+                    .is_some_and(|prefix| prefix.eq_ignore_ascii_case("Content-"))
+        }
+    }
+}
+
+fn add_header(result: &mut Vec<u8>, header: &Header, raw: &[u8]) {
+    if let Some(value) = raw.get(header.offset_start..header.offset_end) {
+        result.extend_from_slice(header.name.as_str().as_bytes());
+        result.push(b':');
+        result.extend_from_slice(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parsers::partial::PartialMessageIterator, Message, MimeHeaders};
+
+    use super::PartialMessageAssembler;
+
+    #[test]
+    fn reassemble_partial() {
+        let fragments = [
+            concat!(
+                "X-Weird-Header-1: Foo\n",
+                "From: Bill@host.com\n",
+                "To: joe@otherhost.com\n",
+                "Date: Fri, 26 Mar 1993 12:59:38 -0500 (EST)\n",
+                "Subject: Audio mail (part 1 of 2)\n",
+                "Message-ID: <id1@host.com>\n",
+                "MIME-Version: 1.0\n",
+                "Content-type: message/partial; id=\"ABC@host.com\";\n",
+                "              number=1; total=2\n",
+                "\n",
+                "X-Weird-Header-1: Bar\n",
+                "X-Weird-Header-2: Hello\n",
+                "Message-ID: <anotherid@foo.com>\n",
+                "Subject: Audio mail\n",
+                "MIME-Version: 1.0\n",
+                "Content-type: audio/basic\n",
+                "Content-transfer-encoding: base64\n",
+                "\n",
+                "SGVsbG8g\n",
+            ),
+            concat!(
+                "From: Bill@host.com\n",
+                "To: joe@otherhost.com\n",
+                "Date: Fri, 26 Mar 1993 12:59:38 -0500 (EST)\n",
+                "Subject: Audio mail (part 2 of 2)\n",
+                "MIME-Version: 1.0\n",
+                "Message-ID: <id2@host.com>\n",
+                "Content-type: message/partial;\n",
+                "              id=\"ABC@host.com\"; number=2\n",
+                "\n",
+                "V29ybGQh\n",
+            ),
+        ];
+
+        let expected_result = concat!(
+            "X-Weird-Header-1: Foo\n",
+            "From: Bill@host.com\n",
+            "To: joe@otherhost.com\n",
+            "Date: Fri, 26 Mar 1993 12:59:38 -0500 (EST)\n",
+            "Message-ID: <anotherid@foo.com>\n",
+            "Subject: Audio mail\n",
+            "MIME-Version: 1.0\n",
+            "Content-Type: audio/basic\n",
+            "Content-Transfer-Encoding: base64\n",
+            "\n",
+            "SGVsbG8g\n",
+            "V29ybGQh\n",
+        );
+
+        // Fragments can arrive in any order, the total is sent in the first one
+        let mut assembler = PartialMessageAssembler::new();
+        assert_eq!(
+            assembler.add(Message::parse(fragments[1].as_bytes()).unwrap()),
+            Ok(None)
+        );
+        assert_eq!(assembler.len(), 1);
+        let result = assembler
+            .add(Message::parse(fragments[0].as_bytes()).unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(assembler.is_empty());
+        assert_eq!(std::str::from_utf8(&result).unwrap(), expected_result);
+
+        let message = Message::parse(&result).unwrap();
+        assert_eq!(message.get_subject(), Some("Audio mail"));
+        assert_eq!(message.get_content_type().unwrap().get_type(), "audio");
+        assert_eq!(message.get_part(0).unwrap().get_contents(), b"Hello World!");
+
+        // Non-partial messages are rejected
+        assert!(assembler
+            .add(Message::parse(b"Subject: test\n\nbody\n").unwrap())
+            .is_err());
+
+        // Iterate over a list of messages
+        let messages = vec![
+            b"Subject: message 1\n\nbody\n".to_vec(),
+            fragments[0].as_bytes().to_vec(),
+            b"Subject: message 2\n\nbody\n".to_vec(),
+            fragments[1].as_bytes().to_vec(),
+            fragments[1]
+                .replace("ABC@host.com", "DEF@host.com")
+                .into_bytes(),
+        ];
+        let result = PartialMessageIterator::new(messages.into_iter()).collect::<Vec<_>>();
+        assert_eq!(
+            result,
+            vec![
+                b"Subject: message 1\n\nbody\n".to_vec(),
+                b"Subject: message 2\n\nbody\n".to_vec(),
+                expected_result.as_bytes().to_vec(),
+                fragments[1]
+                    .replace("ABC@host.com", "DEF@host.com")
+                    .into_bytes(),
+            ]
+        );
+    }
+}
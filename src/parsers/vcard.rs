@@ -0,0 +1,535 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use crate::{
+    decoders::{
+        base64::decode_base64, charsets::map::get_charset_decoder,
+        quoted_printable::decode_quoted_printable, DecodeResult,
+    },
+    Message, MessagePart, MimeHeaders, PartType,
+};
+
+use super::{
+    icalendar::{parse_components, Component, ContentLine},
+    message::MessageStream,
+};
+
+#[cfg(feature = "serde_support")]
+use serde::{Deserialize, Serialize};
+
+/// A vCard 2.1 (versit), 3.0 (RFC2426) or 4.0 (RFC6350) contact.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct VCard {
+    pub version: Option<String>,
+    /// Formatted name (`FN`)
+    pub full_name: Option<String>,
+    /// Structured name (`N`)
+    pub name: Option<ContactName>,
+    pub emails: Vec<ContactValue>,
+    pub phones: Vec<ContactValue>,
+    /// Organization name followed by its units (`ORG`)
+    pub organization: Vec<String>,
+    pub photo: Option<ContactPhoto>,
+    /// All properties, with quoted-printable and charset encoded values decoded
+    pub properties: Vec<ContentLine>,
+}
+
+/// The components of a vCard `N` property.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct ContactName {
+    pub family: String,
+    pub given: String,
+    pub additional: String,
+    pub prefixes: String,
+    pub suffixes: String,
+}
+
+/// An email address or telephone number with its types.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct ContactValue {
+    pub value: String,
+    /// Lowercase types, such as `work`, `home` or `cell`
+    pub types: Vec<String>,
+    pub is_preferred: bool,
+}
+
+/// A contact photo, either embedded or referenced by URI.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub enum ContactPhoto {
+    Uri(String),
+    Data {
+        content_type: Option<String>,
+        contents: Vec<u8>,
+    },
+}
+
+impl<'x> Message<'x> {
+    /// Returns all the contacts found in the vCard parts of the message.
+    pub fn get_contacts(&self) -> Vec<VCard> {
+        self.parts
+            .iter()
+            .filter(|part| part.is_vcard())
+            .flat_map(|part| match &part.body {
+                PartType::Text(text) => VCard::parse(text),
+                PartType::Binary(bin) | PartType::InlineBinary(bin) => VCard::parse_bytes(bin),
+                _ => Vec::new(),
+            })
+            .collect()
+    }
+}
+
+impl<'x> MessagePart<'x> {
+    fn is_vcard(&self) -> bool {
+        if let Some(content_type) = self.get_content_type() {
+            match (content_type.get_type(), content_type.get_subtype()) {
+                ("text", Some("vcard" | "x-vcard" | "directory")) => return true,
+                ("text" | "application", Some("plain" | "octet-stream")) | ("text", None) => (),
+                _ => return false,
+            }
+        }
+
+        self.get_attachment_name().is_some_and(|name| {
+            name.rsplit_once('.')
+                .is_some_and(|(_, ext)| ext.eq_ignore_ascii_case("vcf"))
+        })
+    }
+}
+
+impl VCard {
+    /// Parses all the vCards contained in a text.
+    pub fn parse(text: &str) -> Vec<VCard> {
+        parse_components(text)
+            .into_iter()
+            .filter(|component| component.name == "VCARD")
+            .map(VCard::from)
+            .collect()
+    }
+
+    // Values are kept as raw bytes, one char per byte, until the CHARSET
+    // parameter of each property has been applied.
+    fn parse_bytes(bytes: &[u8]) -> Vec<VCard> {
+        parse_components(&bytes.iter().map(|&ch| char::from(ch)).collect::<String>())
+            .into_iter()
+            .filter(|component| component.name == "VCARD")
+            .map(|component| VCard::from_component(component, true))
+            .collect()
+    }
+
+    /// Returns the first property with the specified name.
+    pub fn get_property(&self, name: &str) -> Option<&ContentLine> {
+        self.properties
+            .iter()
+            .find(|property| property.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl From<Component> for VCard {
+    fn from(component: Component) -> Self {
+        VCard::from_component(component, false)
+    }
+}
+
+impl VCard {
+    fn from_component(component: Component, is_raw: bool) -> Self {
+        let mut vcard = VCard::default();
+
+        for mut property in component.properties {
+            // Remove group names, such as 'item1.EMAIL'
+            if let Some((_, name)) = property.name.rsplit_once('.') {
+                property.name = name.to_string();
+            }
+
+            let is_base64 = property.get_param("ENCODING").is_some_and(|encoding| {
+                encoding.eq_ignore_ascii_case("BASE64") || encoding.eq_ignore_ascii_case("B")
+            }) || property.get_param("BASE64").is_some();
+            if is_raw {
+                for value in property.params.iter_mut().flat_map(|(_, values)| values) {
+                    *value = String::from_utf8_lossy(&raw_bytes(value)).into_owned();
+                }
+            }
+            if !is_base64 {
+                decode_value(&mut property, is_raw);
+            }
+
+            match property.name.as_str() {
+                "VERSION" => vcard.version = property.value.trim().to_string().into(),
+                "FN" => vcard.full_name = property.get_text().into(),
+                "N" => {
+                    let mut name = split_structured(&property.value).into_iter();
+                    vcard.name = ContactName {
+                        family: name.next().unwrap_or_default(),
+                        given: name.next().unwrap_or_default(),
+                        additional: name.next().unwrap_or_default(),
+                        prefixes: name.next().unwrap_or_default(),
+                        suffixes: name.next().unwrap_or_default(),
+                    }
+                    .into();
+                }
+                "EMAIL" => vcard.emails.push(ContactValue::from(&property)),
+                "TEL" => vcard.phones.push(ContactValue::from(&property)),
+                "ORG" => {
+                    vcard.organization = split_structured(&property.value)
+                        .into_iter()
+                        .filter(|unit| !unit.is_empty())
+                        .collect();
+                }
+                "PHOTO" if vcard.photo.is_none() => {
+                    vcard.photo = get_photo(&property, is_base64);
+                }
+                _ => (),
+            }
+
+            vcard.properties.push(property);
+        }
+
+        vcard
+    }
+}
+
+impl From<&ContentLine> for ContactValue {
+    fn from(property: &ContentLine) -> Self {
+        let mut types = Vec::new();
+        let mut is_preferred = false;
+
+        for (name, values) in &property.params {
+            let values = match name.as_str() {
+                "TYPE" => values
+                    .iter()
+                    .map(|value| value.as_str())
+                    .collect::<Vec<_>>(),
+                "PREF" => {
+                    is_preferred = true;
+                    continue;
+                }
+                // vCard 2.1 types are parameters without values, such as 'TEL;WORK;VOICE:'
+                _ if values.is_empty() => vec![name.as_str()],
+                _ => continue,
+            };
+
+            for value in values.iter().flat_map(|value| value.split(',')) {
+                let value = value.trim().to_ascii_lowercase();
+                if value == "pref" {
+                    is_preferred = true;
+                } else if !value.is_empty() && !types.contains(&value) {
+                    types.push(value);
+                }
+            }
+        }
+
+        let mut value = property.get_text();
+        if value
+            .get(..4)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("tel:"))
+        {
+            value = value[4..].to_string();
+        }
+
+        ContactValue {
+            value: value.trim().to_string(),
+            types,
+            is_preferred,
+        }
+    }
+}
+
+/// Decodes quoted-printable values and converts raw values from the charset
+/// specified in the CHARSET parameter. Values of text parts have already been
+/// decoded using the charset of the part, so CHARSET only applies to the
+/// quoted-printable octets.
+fn decode_value(property: &mut ContentLine, is_raw: bool) {
+    let is_quoted_printable = property.get_param("ENCODING").map_or_else(
+        || property.get_param("QUOTED-PRINTABLE").is_some(),
+        |encoding| encoding.eq_ignore_ascii_case("QUOTED-PRINTABLE"),
+    );
+
+    let bytes = if is_quoted_printable {
+        let value = if is_raw {
+            raw_bytes(&property.value)
+        } else {
+            property.value.as_bytes().to_vec()
+        };
+        match decode_quoted_printable(&MessageStream::new(&value), 0, &[], false) {
+            (_, DecodeResult::Owned(bytes)) => bytes,
+            _ => return,
+        }
+    } else if is_raw {
+        raw_bytes(&property.value)
+    } else {
+        return;
+    };
+
+    property.value = match property
+        .get_param("CHARSET")
+        .and_then(|charset| get_charset_decoder(charset.as_bytes()))
+    {
+        Some(decoder) => decoder(&bytes),
+        None => String::from_utf8(bytes)
+            .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned()),
+    };
+}
+
+// Reverses the one char per byte mapping of raw values
+fn raw_bytes(value: &str) -> Vec<u8> {
+    value.chars().map(|ch| ch as u8).collect()
+}
+
+fn get_photo(property: &ContentLine, is_base64: bool) -> Option<ContactPhoto> {
+    let value = property.value.trim();
+
+    if is_base64 {
+        Some(ContactPhoto::Data {
+            content_type: property.get_param("TYPE").map(|image_type| {
+                if image_type.contains('/') {
+                    image_type.to_ascii_lowercase()
+                } else {
+                    format!("image/{}", image_type.to_ascii_lowercase())
+                }
+            }),
+            contents: decode_base64_value(value)?,
+        })
+    } else if value
+        .get(..5)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("data:"))
+    {
+        // vCard 4.0 data URIs, such as 'data:image/jpeg;base64,...'
+        let (media_type, data) = value[5..].split_once(',')?;
+        let (media_type, is_base64) = match media_type.strip_suffix(";base64") {
+            Some(media_type) => (media_type, true),
+            None => (media_type, false),
+        };
+
+        Some(ContactPhoto::Data {
+            content_type: if !media_type.is_empty() {
+                media_type.to_ascii_lowercase().into()
+            } else {
+                None
+            },
+            contents: if is_base64 {
+                decode_base64_value(data)?
+            } else {
+                data.as_bytes().to_vec()
+            },
+        })
+    } else if !value.is_empty() {
+        Some(ContactPhoto::Uri(value.to_string()))
+    } else {
+        None
+    }
+}
+
+fn decode_base64_value(value: &str) -> Option<Vec<u8>> {
+    match decode_base64(&MessageStream::new(value.as_bytes()), 0, &[], false) {
+        (_, DecodeResult::Owned(bytes)) => Some(bytes),
+        _ => None,
+    }
+}
+
+/// Splits a structured value, such as `N` or `ORG`, into its unescaped components.
+fn split_structured(value: &str) -> Vec<String> {
+    let mut components = Vec::new();
+    let mut component = String::new();
+    let mut is_escaped = false;
+
+    for ch in value.chars() {
+        if is_escaped {
+            component.push(match ch {
+                'n' | 'N' => '\n',
+                _ => ch,
+            });
+            is_escaped = false;
+        } else {
+            match ch {
+                '\\' => is_escaped = true,
+                ';' => components.push(std::mem::take(&mut component)),
+                _ => component.push(ch),
+            }
+        }
+    }
+    components.push(component);
+
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parsers::vcard::{ContactName, ContactPhoto, ContactValue, VCard},
+        Message,
+    };
+
+    #[test]
+    fn parse_vcards() {
+        let message = Message::parse(
+            concat!(
+                "From: john@example.com\n",
+                "Subject: Contacts\n",
+                "Content-Type: multipart/mixed; boundary=\"boundary\"\n",
+                "\n",
+                "--boundary\n",
+                "Content-Type: text/plain\n",
+                "\n",
+                "Here are the contacts.\n",
+                "--boundary\n",
+                "Content-Type: text/x-vcard; charset=utf-8\n",
+                "\n",
+                "BEGIN:VCARD\n",
+                "VERSION:2.1\n",
+                "N;CHARSET=ISO-8859-1;ENCODING=QUOTED-PRINTABLE:M=FCller;J=FCrgen\n",
+                "FN;ENCODING=QUOTED-PRINTABLE;CHARSET=UTF-8:J=C3=BCrgen M=C3=BCller, =\n",
+                "Gesch=C3=A4ftsf=C3=BChrer\n",
+                "ORG:Example GmbH;Sales\n",
+                "TEL;WORK;VOICE;PREF:+49 30 1234567\n",
+                "TEL;CELL:+49 170 1234567\n",
+                "EMAIL;INTERNET:juergen@example.de\n",
+                "PHOTO;ENCODING=BASE64;TYPE=GIF:\n",
+                "    R0lGODlhAQABAIAAAP///wAAACH5BAEAAAAALAAAAAABAAEAAAICRAEAOw==\n",
+                "\n",
+                "END:VCARD\n",
+                "--boundary\n",
+                "Content-Type: application/octet-stream\n",
+                "Content-Disposition: attachment; filename=\"jane.vcf\"\n",
+                "Content-Transfer-Encoding: base64\n",
+                "\n",
+                "QkVHSU46VkNBUkQNClZFUlNJT046NC4wDQpGTjpKYW5lIERvZVwsIFBoRA0KTjpEb2U7SmFu\n",
+                "ZTs7RHIuO1BoRA0KRU1BSUw7VFlQRT13b3JrO1BSRUY9MTpqYW5lQGV4YW1wbGUuY29tDQpp\n",
+                "dGVtMS5FTUFJTDtUWVBFPSJob21lLGludGVybmV0IjpqYW5lQGhvbWUuZXhhbXBsZQ0KVEVM\n",
+                "O1ZBTFVFPXVyaTtUWVBFPWNlbGw6dGVsOisxLTU1NS0wMTAwDQpQSE9UTzpodHRwOi8vZXhh\n",
+                "bXBsZS5jb20vamFuZS5qcGcNCkVORDpWQ0FSRA0K\n",
+                "--boundary--\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let contacts = message.get_contacts();
+        assert_eq!(contacts.len(), 2);
+
+        let contact = &contacts[0];
+        assert_eq!(contact.version.as_deref(), Some("2.1"));
+        assert_eq!(
+            contact.full_name.as_deref(),
+            Some("Jürgen Müller, Geschäftsführer")
+        );
+        assert_eq!(
+            contact.name,
+            Some(ContactName {
+                family: "Müller".into(),
+                given: "Jürgen".into(),
+                ..Default::default()
+            })
+        );
+        assert_eq!(contact.organization, vec!["Example GmbH", "Sales"]);
+        assert_eq!(
+            contact.phones,
+            vec![
+                ContactValue {
+                    value: "+49 30 1234567".into(),
+                    types: vec!["work".into(), "voice".into()],
+                    is_preferred: true,
+                },
+                ContactValue {
+                    value: "+49 170 1234567".into(),
+                    types: vec!["cell".into()],
+                    is_preferred: false,
+                }
+            ]
+        );
+        assert_eq!(contact.emails[0].value, "juergen@example.de");
+        assert_eq!(contact.emails[0].types, vec!["internet"]);
+        match contact.photo.as_ref().unwrap() {
+            ContactPhoto::Data {
+                content_type,
+                contents,
+            } => {
+                assert_eq!(content_type.as_deref(), Some("image/gif"));
+                assert!(contents.starts_with(b"GIF89a"));
+            }
+            photo => panic!("Unexpected photo {:?}", photo),
+        }
+
+        let contact = &contacts[1];
+        assert_eq!(contact.version.as_deref(), Some("4.0"));
+        assert_eq!(contact.full_name.as_deref(), Some("Jane Doe, PhD"));
+        assert_eq!(contact.name.as_ref().unwrap().prefixes, "Dr.");
+        assert_eq!(contact.name.as_ref().unwrap().suffixes, "PhD");
+        assert_eq!(
+            contact.emails,
+            vec![
+                ContactValue {
+                    value: "jane@example.com".into(),
+                    types: vec!["work".into()],
+                    is_preferred: true,
+                },
+                ContactValue {
+                    value: "jane@home.example".into(),
+                    types: vec!["home".into(), "internet".into()],
+                    is_preferred: false,
+                }
+            ]
+        );
+        assert_eq!(contact.phones[0].value, "+1-555-0100");
+        assert_eq!(
+            contact.photo,
+            Some(ContactPhoto::Uri("http://example.com/jane.jpg".into()))
+        );
+
+        // vCard 3.0 with an inline photo
+        let contacts = VCard::parse(concat!(
+            "BEGIN:VCARD\r\n",
+            "VERSION:3.0\r\n",
+            "FN:Bob\r\n",
+            "EMAIL;TYPE=INTERNET;TYPE=PREF:bob@example.com\r\n",
+            "PHOTO;ENCODING=b;TYPE=image/png:iVBORw0K\r\n",
+            "END:VCARD\r\n",
+            "BEGIN:VCARD\r\n",
+            "VERSION:4.0\r\n",
+            "FN:Alice\r\n",
+            "PHOTO:data:image/png;base64,iVBORw0K\r\n",
+            "END:VCARD\r\n",
+        ));
+        assert_eq!(contacts.len(), 2);
+        assert!(contacts[0].emails[0].is_preferred);
+        assert_eq!(contacts[0].emails[0].types, vec!["internet"]);
+        for contact in contacts {
+            assert_eq!(
+                contact.photo,
+                Some(ContactPhoto::Data {
+                    content_type: Some("image/png".into()),
+                    contents: b"\x89PNG\r\n".to_vec()
+                })
+            );
+        }
+
+        // CHARSET without quoted-printable only applies to undecoded bytes
+        let raw_message = [
+            &b"Content-Type: multipart/mixed; boundary=\"boundary\"\n\n--boundary\n"[..],
+            b"Content-Type: text/x-vcard; charset=iso-8859-1\n\n",
+            b"BEGIN:VCARD\nVERSION:2.1\nFN;CHARSET=ISO-8859-1:M\xfcller\nEND:VCARD\n",
+            b"--boundary\n",
+            b"Content-Type: application/octet-stream\n",
+            b"Content-Disposition: attachment; filename=\"anna.vcf\"\n",
+            b"Content-Transfer-Encoding: base64\n\n",
+            b"QkVHSU46VkNBUkQNClZFUlNJT046Mi4xDQpGTjtDSEFSU0VUPUlTTy04ODU5LTE6TfxsbGVyDQpO\n",
+            b"Ok3DvGxsZXI7QW5uYQ0KRU5EOlZDQVJEDQo=\n",
+            b"--boundary--\n",
+        ]
+        .concat();
+        let contacts = Message::parse(&raw_message).unwrap().get_contacts();
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(contacts[0].full_name.as_deref(), Some("Müller"));
+        assert_eq!(contacts[1].full_name.as_deref(), Some("Müller"));
+        assert_eq!(contacts[1].name.as_ref().unwrap().family, "Müller");
+    }
+}
@@ -0,0 +1,502 @@
+/*
+ * Copyright Stalwart Labs Ltd. See the COPYING
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Conversation threading using Jamie Zawinski's algorithm
+//! ([JWZ](https://www.jwz.org/doc/threading.html)).
+
+use std::collections::{HashMap, HashSet};
+
+#[cfg(feature = "serde_support")]
+use serde::{Deserialize, Serialize};
+
+use crate::{HeaderValue, Message};
+
+/// A node of a conversation thread
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct ThreadNode {
+    /// Position of the message in the threaded slice, `None` for dummy
+    /// nodes standing in for messages that were referenced but not found.
+    pub message: Option<usize>,
+    /// Replies to this message, ordered by date
+    pub children: Vec<ThreadNode>,
+}
+
+#[derive(Default)]
+struct Container {
+    message: Option<usize>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+// Threading input extracted from a message
+pub(crate) struct ThreadMessage<'x> {
+    message_id: Option<&'x str>,
+    references: Vec<&'x str>,
+    // Normalized subject and whether the message is a reply or forward
+    subject: Option<(String, bool)>,
+    date: Option<i64>,
+}
+
+// Only the first and the most recent references of a message are linked,
+// which bounds the work a single message can cause.
+const MAX_REFERENCES: usize = 100;
+
+struct Threader<'x> {
+    messages: Vec<ThreadMessage<'x>>,
+    containers: Vec<Container>,
+}
+
+/// Groups messages into threads using their Message-ID, In-Reply-To and
+/// References headers, messages with matching thread names are grouped
+/// as well. Threads and replies are ordered by date, messages with the same
+/// date keep their original order.
+pub fn thread_messages(messages: &[Message]) -> Vec<ThreadNode> {
+    thread_references(
+        messages
+            .iter()
+            .map(|message| {
+                let subject = message.get_subject().and_then(|subject| {
+                    let thread_name = message.get_thread_name()?;
+                    Some((
+                        thread_name.to_string(),
+                        thread_name.len() != subject.trim().len(),
+                    ))
+                });
+                ThreadMessage::new(
+                    message,
+                    subject,
+                    message.get_date().map(|date| date.to_timestamp()),
+                )
+            })
+            .collect(),
+    )
+}
+
+pub(crate) fn thread_references(messages: Vec<ThreadMessage>) -> Vec<ThreadNode> {
+    let mut threader = Threader {
+        containers: Vec::with_capacity(messages.len()),
+        messages,
+    };
+    let roots = threader.link_messages();
+    let roots = threader.prune(roots);
+    let roots = threader.group_by_subject(roots);
+
+    let mut threads = threader.build_nodes(&roots);
+    threads.sort_by_key(|(key, _)| *key);
+    threads.into_iter().map(|(_, node)| node).collect()
+}
+
+impl<'x> ThreadMessage<'x> {
+    // Messages without a References header use their first In-Reply-To id,
+    // long References headers keep their first and last ids.
+    pub(crate) fn new(
+        message: &'x Message,
+        subject: Option<(String, bool)>,
+        date: Option<i64>,
+    ) -> Self {
+        let mut references = header_ids(message.get_references());
+        if references.is_empty() {
+            references = header_ids(message.get_in_reply_to());
+            references.truncate(1);
+        } else if references.len() > MAX_REFERENCES {
+            references.drain(1..references.len() - MAX_REFERENCES + 1);
+        }
+        ThreadMessage {
+            message_id: message.get_message_id().filter(|id| !id.is_empty()),
+            references,
+            subject: subject.filter(|(subject, _)| !subject.is_empty()),
+            date,
+        }
+    }
+}
+
+impl<'x> Threader<'x> {
+    // Builds the containers from the Message-ID and References headers,
+    // returns the root set.
+    fn link_messages(&mut self) -> Vec<usize> {
+        let mut ids: HashMap<&'x str, usize> = HashMap::with_capacity(self.messages.len());
+
+        for pos in 0..self.messages.len() {
+            // Messages with a duplicate or missing Message-ID are threaded
+            // on their own.
+            let container = match self.messages[pos].message_id {
+                Some(id) => match ids.get(id) {
+                    Some(&container) if self.containers[container].message.is_none() => container,
+                    Some(_) => self.new_container(),
+                    None => {
+                        let container = self.new_container();
+                        ids.insert(id, container);
+                        container
+                    }
+                },
+                None => self.new_container(),
+            };
+            self.containers[container].message = Some(pos);
+
+            // Link the references without breaking existing links
+            let mut parent = None;
+            for id in std::mem::take(&mut self.messages[pos].references) {
+                let reference = *ids.entry(id).or_insert_with(|| {
+                    self.containers.push(Container::default());
+                    self.containers.len() - 1
+                });
+                if let Some(parent) = parent {
+                    if self.containers[reference].parent.is_none()
+                        && !self.is_ancestor(reference, parent)
+                    {
+                        self.link(parent, reference);
+                    }
+                }
+                parent = Some(reference);
+            }
+
+            // The message's own references replace any guessed parent
+            self.unlink(container);
+            if let Some(parent) = parent.filter(|&parent| !self.is_ancestor(container, parent)) {
+                self.link(parent, container);
+            }
+        }
+
+        (0..self.containers.len())
+            .filter(|&container| self.containers[container].parent.is_none())
+            .collect()
+    }
+
+    // Removes empty containers, promoting their children. Children are
+    // pruned before their parents so that promoted containers are final.
+    fn prune(&mut self, roots: Vec<usize>) -> Vec<usize> {
+        for container in self.post_order(&roots) {
+            let children = std::mem::take(&mut self.containers[container].children);
+            let mut pruned = Vec::with_capacity(children.len());
+            for child in children {
+                if self.containers[child].message.is_some() {
+                    pruned.push(child);
+                } else {
+                    pruned.append(&mut self.containers[child].children);
+                }
+            }
+            self.containers[container].children = pruned;
+        }
+
+        // Dummy roots are only kept when they group several messages
+        let mut result = Vec::with_capacity(roots.len());
+        for root in roots {
+            let container = &mut self.containers[root];
+            if container.message.is_some() || container.children.len() > 1 {
+                result.push(root);
+            } else {
+                result.append(&mut container.children);
+            }
+        }
+        result
+    }
+
+    // Merges root containers sharing the same thread name, the root set is
+    // visited in date order.
+    fn group_by_subject(&mut self, mut roots: Vec<usize>) -> Vec<usize> {
+        let sort_keys = self.sort_keys(&roots);
+        roots.sort_by_key(|&root| sort_keys[root]);
+
+        let mut subjects: HashMap<String, usize> = HashMap::new();
+        for &root in &roots {
+            if let Some((thread_name, is_reply)) = self.subject(root) {
+                subjects
+                    .entry(thread_name.to_string())
+                    .and_modify(|current| {
+                        let is_dummy = self.containers[root].message.is_none();
+                        let current_is_dummy = self.containers[*current].message.is_none();
+                        if (is_dummy && !current_is_dummy)
+                            || (!current_is_dummy
+                                && !is_reply
+                                && self.subject(*current).is_some_and(|(_, is_reply)| is_reply))
+                        {
+                            *current = root;
+                        }
+                    })
+                    .or_insert(root);
+            }
+        }
+
+        let mut merged = HashSet::new();
+        for pos in 0..roots.len() {
+            let root = roots[pos];
+            let (thread_name, is_reply) = match self.subject(root) {
+                Some((thread_name, is_reply)) => (thread_name.to_string(), is_reply),
+                None => continue,
+            };
+            let other = subjects[&thread_name];
+            if other == root {
+                continue;
+            }
+
+            let is_dummy = self.containers[root].message.is_none();
+            let other_is_dummy = self.containers[other].message.is_none();
+            if is_dummy && other_is_dummy {
+                let children = std::mem::take(&mut self.containers[root].children);
+                for child in children {
+                    self.containers[child].parent = None;
+                    self.link(other, child);
+                }
+            } else if other_is_dummy
+                || (is_reply && self.subject(other).is_some_and(|(_, is_reply)| !is_reply))
+            {
+                self.link(other, root);
+            } else {
+                // Neither message is a reply to the other
+                let dummy = self.new_container();
+                self.link(dummy, other);
+                self.link(dummy, root);
+                if let Some(other_pos) = roots.iter().position(|&root| root == other) {
+                    roots[other_pos] = dummy;
+                }
+                subjects.insert(thread_name, dummy);
+            }
+            merged.insert(root);
+        }
+
+        roots.retain(|root| !merged.contains(root));
+        roots
+    }
+
+    // Builds the thread trees of the root set along with their sort keys
+    fn build_nodes(&self, roots: &[usize]) -> Vec<((Option<i64>, usize), ThreadNode)> {
+        let sort_keys = self.sort_keys(roots);
+        let mut nodes: Vec<Option<ThreadNode>> = Vec::new();
+        nodes.resize_with(self.containers.len(), Default::default);
+
+        for container in self.post_order(roots) {
+            let mut children = self.containers[container]
+                .children
+                .iter()
+                .filter_map(|&child| Some((sort_keys[child], nodes[child].take()?)))
+                .collect::<Vec<_>>();
+            children.sort_by_key(|(key, _)| *key);
+            nodes[container] = Some(ThreadNode {
+                message: self.containers[container].message,
+                children: children.into_iter().map(|(_, node)| node).collect(),
+            });
+        }
+
+        roots
+            .iter()
+            .filter_map(|&root| Some((sort_keys[root], nodes[root].take()?)))
+            .collect()
+    }
+
+    // Messages are sorted by date and then by position, dummy containers
+    // sort as their earliest descendant.
+    fn sort_keys(&self, roots: &[usize]) -> Vec<(Option<i64>, usize)> {
+        let mut sort_keys = vec![(None, usize::MAX); self.containers.len()];
+        for container in self.post_order(roots) {
+            sort_keys[container] = match self.containers[container].message {
+                Some(pos) => (self.messages[pos].date, pos),
+                None => self.containers[container]
+                    .children
+                    .iter()
+                    .map(|&child| sort_keys[child])
+                    .min()
+                    .unwrap_or((None, usize::MAX)),
+            };
+        }
+        sort_keys
+    }
+
+    // Returns the containers below the roots, children before their parents
+    fn post_order(&self, roots: &[usize]) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.containers.len());
+        let mut pending = roots.to_vec();
+        while let Some(container) = pending.pop() {
+            order.push(container);
+            pending.extend(&self.containers[container].children);
+        }
+        order.reverse();
+        order
+    }
+
+    // Returns the subject of a container and whether it is a reply, dummy
+    // containers use their earliest child.
+    fn subject(&self, container: usize) -> Option<(&str, bool)> {
+        let container = &self.containers[container];
+        let pos = match container.message {
+            Some(pos) => pos,
+            None => {
+                container
+                    .children
+                    .iter()
+                    .filter_map(|&child| {
+                        let pos = self.containers[child].message?;
+                        Some((self.messages[pos].date, pos))
+                    })
+                    .min()?
+                    .1
+            }
+        };
+        self.messages[pos]
+            .subject
+            .as_ref()
+            .map(|(subject, is_reply)| (subject.as_str(), *is_reply))
+    }
+
+    // Returns true if `ancestor` is `container` or one of its parents
+    fn is_ancestor(&self, ancestor: usize, mut container: usize) -> bool {
+        loop {
+            if container == ancestor {
+                return true;
+            }
+            match self.containers[container].parent {
+                Some(parent) => container = parent,
+                None => return false,
+            }
+        }
+    }
+
+    fn link(&mut self, parent: usize, child: usize) {
+        self.containers[child].parent = Some(parent);
+        self.containers[parent].children.push(child);
+    }
+
+    fn unlink(&mut self, child: usize) {
+        if let Some(parent) = self.containers[child].parent.take() {
+            self.containers[parent]
+                .children
+                .retain(|&container| container != child);
+        }
+    }
+
+    fn new_container(&mut self) -> usize {
+        self.containers.push(Container::default());
+        self.containers.len() - 1
+    }
+}
+
+// Deep threads are dropped iteratively to avoid overflowing the stack
+impl Drop for ThreadNode {
+    fn drop(&mut self) {
+        let mut pending = std::mem::take(&mut self.children);
+        while let Some(mut node) = pending.pop() {
+            pending.append(&mut node.children);
+        }
+    }
+}
+
+impl ThreadNode {
+    /// Returns the positions of all messages in this thread, depth-first
+    pub fn messages(&self) -> Vec<usize> {
+        let mut messages = Vec::new();
+        let mut pending = vec![self];
+        while let Some(node) = pending.pop() {
+            messages.extend(node.message);
+            pending.extend(node.children.iter().rev());
+        }
+        messages
+    }
+}
+
+fn header_ids<'x>(value: &'x HeaderValue) -> Vec<&'x str> {
+    match value {
+        HeaderValue::Text(id) => vec![id.as_ref()],
+        HeaderValue::TextList(ids) => ids.iter().map(|id| id.as_ref()).collect(),
+        _ => Vec::new(),
+    }
+    .into_iter()
+    .filter(|id| !id.is_empty())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Message;
+
+    use super::{thread_messages, ThreadNode};
+
+    #[test]
+    fn thread_jwz() {
+        let raw_messages = [
+            ("a", "", "Hello", 10),
+            ("b", "<a>", "Re: Hello", 11),
+            ("c", "<a> <m>", "Re: Hello", 12),
+            ("d", "<z>", "Re: Lunch?", 9),
+            ("e", "<z>", "Re: Lunch?", 8),
+            ("f", "<g>", "Loop", 14),
+            ("g", "<f>", "Loop", 13),
+            ("h", "", "Meeting", 15),
+            ("i", "", "Re: Meeting", 16),
+        ]
+        .iter()
+        .map(|(id, references, subject, hour)| {
+            let mut message = format!(
+                "Message-ID: <{}>\nSubject: {}\nDate: Mon, 15 Jan 2018 {:02}:00:00 +0000\n",
+                id, subject, hour
+            );
+            if !references.is_empty() {
+                message.push_str(&format!("References: {}\n", references));
+            }
+            message.push_str("\nHi.\n");
+            message
+        })
+        .collect::<Vec<_>>();
+        let messages = raw_messages
+            .iter()
+            .map(|message| Message::parse(message.as_bytes()).unwrap())
+            .collect::<Vec<_>>();
+
+        let node =
+            |message: Option<usize>, children: Vec<ThreadNode>| ThreadNode { message, children };
+        let threads = thread_messages(&messages);
+        assert_eq!(
+            threads,
+            vec![
+                node(None, vec![node(Some(4), vec![]), node(Some(3), vec![])]),
+                node(Some(0), vec![node(Some(1), vec![]), node(Some(2), vec![])]),
+                node(Some(6), vec![node(Some(5), vec![])]),
+                node(Some(7), vec![node(Some(8), vec![])]),
+            ]
+        );
+        assert_eq!(threads[1].messages(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn thread_long_references() {
+        // A single message referencing a very long chain of missing ids
+        let references = (0..20000)
+            .map(|id| format!("<{}>", id))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let raw_message = format!("Message-ID: <a>\nReferences: {}\n\nHi.\n", references);
+        let messages = vec![Message::parse(raw_message.as_bytes()).unwrap()];
+        assert_eq!(
+            thread_messages(&messages),
+            vec![ThreadNode {
+                message: Some(0),
+                children: vec![]
+            }]
+        );
+
+        // A long conversation where each message replies to the previous one
+        let raw_messages = (0..20000)
+            .map(|id| {
+                format!(
+                    "Message-ID: <{}>\nIn-Reply-To: <{}>\n\nHi.\n",
+                    id,
+                    id.max(1) - 1
+                )
+            })
+            .collect::<Vec<_>>();
+        let messages = raw_messages
+            .iter()
+            .map(|message| Message::parse(message.as_bytes()).unwrap())
+            .collect::<Vec<_>>();
+        let threads = thread_messages(&messages);
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].messages(), (0..20000).collect::<Vec<_>>());
+    }
+}